@@ -6,6 +6,12 @@ pub type Result<T> = std::result::Result<T, Error>;
 pub enum Error {
     #[error(transparent)]
     Blob(#[from] BlobError),
+    #[error(transparent)]
+    Bucket(#[from] BucketError),
+    #[error("store is read-only")]
+    ReadOnly,
+    #[error("store is locked by another handle")]
+    Locked,
     #[error("OS I/O error: {0}")]
     Io(#[from] std::io::Error),
     #[cfg(feature = "sqlite")]
@@ -29,4 +35,26 @@ pub enum BlobError {
     AlreadyExists,
     #[error("blob range out of bounds")]
     RangeError,
+    #[error("blob authentication failed")]
+    AuthenticationFailed,
+    #[error("blob corrupted: checksum mismatch")]
+    Corrupted,
+    #[error("invalid blob key")]
+    InvalidKey,
+    #[error("blob key collision: the key belongs to another name")]
+    KeyCollision,
+    #[error("blob precondition failed")]
+    PreconditionFailed,
+    #[error("store quota exceeded")]
+    QuotaExceeded,
+}
+
+#[derive(Debug, Error)]
+pub enum BucketError {
+    #[error("bucket not found")]
+    NotFound,
+    #[error("bucket already exists")]
+    AlreadyExists,
+    #[error("invalid bucket name")]
+    InvalidName,
 }
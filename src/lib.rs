@@ -1,6 +1,9 @@
 pub mod error;
 mod ffi;
 mod store_impl;
+mod uri;
+
+pub use uri::open;
 
 pub mod prelude {
     pub use super::error::Error as BlobStoreError;
@@ -9,9 +12,53 @@ pub mod prelude {
     pub use super::*;
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Default)]
 pub struct BlobMeta {
     pub size: usize,
+    /// Checksum of the blob, if the store maintains one.
+    pub checksum: Option<Checksum>,
+    /// Version of the blob, if the store maintains one. It increases with every write.
+    pub version: Option<u64>,
+    /// Deadline of the blob, if it expires.
+    pub expires_at: Option<std::time::SystemTime>,
+    /// Space the blob takes on the device, if the store knows it. It is
+    /// smaller than `size` for sparse blobs.
+    pub allocated: Option<usize>,
+}
+
+/// Space used by the blobs of a store, see `BlobStore::stats`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub struct StoreStats {
+    pub blobs: u64,
+    /// Sum of the sizes of the blobs.
+    pub logical_bytes: u64,
+    /// Space the blobs take on the device, or their size for the stores that
+    /// don't know it.
+    pub physical_bytes: u64,
+}
+
+impl StoreStats {
+    pub(crate) fn add(&mut self, other: &Self) {
+        self.blobs += other.blobs;
+        self.logical_bytes += other.logical_bytes;
+        self.physical_bytes += other.physical_bytes;
+    }
+
+    pub(crate) fn sub(&mut self, other: &Self) {
+        self.blobs = self.blobs.saturating_sub(other.blobs);
+        self.logical_bytes = self.logical_bytes.saturating_sub(other.logical_bytes);
+        self.physical_bytes = self.physical_bytes.saturating_sub(other.physical_bytes);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Checksum {
+    /// CRC32C of the whole content.
+    Crc32c(u32),
+    /// Digest of the per-chunk checksums of a `ChecksumStore`, not of the
+    /// content: it only compares with the digests of stores with the same
+    /// algorithm and chunk size.
+    ChunkTable(u64),
 }
 
 pub type BlobRange = std::ops::Range<usize>;
@@ -19,6 +66,9 @@ pub type Offset = usize;
 
 pub type Key = [u8; 8];
 
+/// Default maximum length of a variable-length key.
+pub const DEFAULT_MAX_KEY_LEN: usize = 1024;
+
 pub trait KeyLike {
     fn as_key(&self) -> Key;
 }
@@ -29,6 +79,73 @@ impl KeyLike for u64 {
     }
 }
 
+impl KeyLike for u32 {
+    fn as_key(&self) -> Key {
+        u64::from(*self).as_key()
+    }
+}
+
+/// Truncated to its low 64 bits: values differing only in their high 64 bits
+/// map to the same key. Hash the value as a 16-byte id to spread all its bits.
+impl KeyLike for u128 {
+    fn as_key(&self) -> Key {
+        (*self as u64).as_key()
+    }
+}
+
+impl KeyLike for Key {
+    fn as_key(&self) -> Key {
+        *self
+    }
+}
+
+/// 16-byte ids such as UUIDs, hashed since their bits are not all random.
+impl KeyLike for [u8; 16] {
+    fn as_key(&self) -> Key {
+        hash_key(self)
+    }
+}
+
+/// Names are hashed, so distinct names may collide. See `NamedStore` to
+/// detect collisions.
+impl KeyLike for str {
+    fn as_key(&self) -> Key {
+        hash_key(self.as_bytes())
+    }
+}
+
+impl KeyLike for String {
+    fn as_key(&self) -> Key {
+        self.as_str().as_key()
+    }
+}
+
+impl<T: KeyLike + ?Sized> KeyLike for &T {
+    fn as_key(&self) -> Key {
+        (**self).as_key()
+    }
+}
+
+/// stable hash of a byte string to a key, XXH3 64 bits
+pub(crate) fn hash_key(bytes: &[u8]) -> Key {
+    xxhash_rust::xxh3::xxh3_64(bytes).to_le_bytes()
+}
+
+/// check that every range of a vectored access lies in a blob of `size` bytes
+/// and has the length of its buffer
+pub(crate) fn check_ranges<'r>(
+    size: usize,
+    ranges: impl IntoIterator<Item = (&'r BlobRange, usize)>,
+) -> error::Result<()> {
+    ranges.into_iter().try_for_each(|(range, len)| {
+        if range.start <= range.end && range.end <= size && range.len() == len {
+            Ok(())
+        } else {
+            Err(error::BlobError::RangeError.into())
+        }
+    })
+}
+
 #[derive(Debug, Clone)]
 pub enum PutOpt {
     /// Create the blob if it doesn't exist, fail if it does.
@@ -55,6 +172,23 @@ pub enum DeleteOpt {
     Discard,
 }
 
+#[derive(Debug, Clone)]
+pub enum CopyOpt {
+    /// Fail if the destination blob exists.
+    Create,
+    /// Replace the destination blob if it exists.
+    ReplaceOrCreate,
+}
+
+impl From<CopyOpt> for PutOpt {
+    fn from(opt: CopyOpt) -> Self {
+        match opt {
+            CopyOpt::Create => PutOpt::Create,
+            CopyOpt::ReplaceOrCreate => PutOpt::ReplaceOrCreate,
+        }
+    }
+}
+
 pub trait BlobStore {
     fn contains(&self, key: Key) -> error::Result<bool>;
     /// # Error
@@ -79,7 +213,257 @@ pub trait BlobStore {
         let mut buf = vec![0_u8; len];
         self.get(key, &mut buf, opt).map(|_| buf)
     }
+    /// Call `f` with the content of a blob. Stores keeping the blob in memory
+    /// lend it without a copy, the others read it with `get_owned` first.
+    /// `f` must not call back into the store.
+    /// # Error
+    /// See `get`.
+    fn with_blob(&self, key: Key, opt: GetOpt, f: &mut dyn FnMut(&[u8])) -> error::Result<()> {
+        f(&self.get_owned(key, opt)?);
+        Ok(())
+    }
+    /// Read several ranges of a blob, each into its own buffer. All the ranges
+    /// are checked before any data is read.
+    /// # Error
+    /// - Blob(BlobError::NotFound): the blob doesn't exist.
+    /// - Blob(BlobError::RangeError): a range is out of bounds or doesn't match the length of its buffer.
+    fn get_vectored(&self, key: Key, bufs: &mut [(BlobRange, &mut [u8])]) -> error::Result<()> {
+        check_ranges(
+            self.meta(key)?.size,
+            bufs.iter().map(|(range, buf)| (range, buf.len())),
+        )?;
+        bufs.iter_mut()
+            .try_for_each(|(range, buf)| self.get(key, buf, GetOpt::Range(range.clone())))
+    }
+    /// Write several ranges of a blob, as `PutOpt::Replace` does for one. All
+    /// the ranges are checked before any data is written.
+    /// # Error
+    /// See `get_vectored`.
+    fn put_vectored(&self, key: Key, bufs: &[(BlobRange, &[u8])]) -> error::Result<()> {
+        check_ranges(
+            self.meta(key)?.size,
+            bufs.iter().map(|(range, buf)| (range, buf.len())),
+        )?;
+        bufs.iter()
+            .try_for_each(|(range, buf)| self.put(key, buf, PutOpt::Replace(range.clone())))
+    }
+    /// Create a blob of `len` zero bytes, reserving its space where the store
+    /// supports it.
+    /// # Error
+    /// - Blob(BlobError::AlreadyExists): the blob already exists.
+    fn create_sized(&self, key: Key, len: usize) -> error::Result<()> {
+        self.put(key, &vec![0; len], PutOpt::Create)
+    }
+    /// Zero a range of a blob, deallocating its space where the store
+    /// supports it. The size of the blob doesn't change.
+    /// # Error
+    /// - Blob(BlobError::NotFound): the blob doesn't exist.
+    /// - Blob(BlobError::RangeError): the range is out of bounds.
+    fn punch_hole(&self, key: Key, range: BlobRange) -> error::Result<()> {
+        let zeros = vec![0; range.len()];
+        self.put(key, &zeros, PutOpt::Replace(range))
+    }
     /// # Error
     /// - Blob(BlobError::NotFound): the blob doesn't exist.
     fn delete(&self, key: Key, opt: DeleteOpt) -> error::Result<Option<Vec<u8>>>;
+    /// List the keys of all the blobs in the store, in no particular order.
+    /// # Error
+    /// - Other: the store can't list its keys, the default for the stores
+    ///   that don't implement it.
+    fn keys(&self) -> error::Result<Vec<Key>> {
+        Err(error::Error::other(anyhow::anyhow!(
+            "the store can't list its keys"
+        )))
+    }
+    /// Usage of the whole store: the blobs with fixed and variable-length
+    /// keys, and the blobs of its buckets. The store keeps it up to date with
+    /// its writes rather than counting the blobs. The stores that wrap
+    /// another one report the usage of the wrapped store.
+    /// # Error
+    /// - Other: the store doesn't keep its usage, the default for the stores
+    ///   that don't implement it.
+    fn stats(&self) -> error::Result<StoreStats> {
+        Err(error::Error::other(anyhow::anyhow!(
+            "the store doesn't keep its usage"
+        )))
+    }
+    /// The blobs may be written through other handles meanwhile, e.g. by a
+    /// `LocalFileSystemBlobStore` opened with `OpenMode::Shared`.
+    fn is_shared(&self) -> bool {
+        false
+    }
+    /// Copy the content of a blob to another key.
+    /// # Error
+    /// - Blob(BlobError::NotFound): the source blob doesn't exist.
+    /// - Blob(BlobError::AlreadyExists): the destination blob exists and CopyOpt::Create is used.
+    fn copy(&self, src: Key, dst: Key, opt: CopyOpt) -> error::Result<()> {
+        let data = self.get_owned(src, GetOpt::All)?;
+        self.put(dst, &data, opt.into())
+    }
+    /// Move a blob to another key.
+    /// # Error
+    /// See `copy`.
+    fn rename(&self, src: Key, dst: Key, opt: CopyOpt) -> error::Result<()> {
+        self.copy(src, dst, opt)?;
+        if src != dst {
+            self.delete(src, DeleteOpt::Discard)?;
+        }
+        Ok(())
+    }
+}
+
+/// A store also addressed by byte-string keys of up to `max_key_len` bytes.
+///
+/// A key of exactly `size_of::<Key>()` bytes is the fixed key with these bytes:
+/// it takes the fast path of the store and names the same blob as the
+/// `BlobStore` methods do. `BlobStore::keys` only lists fixed keys.
+pub trait VarKeyBlobStore: BlobStore {
+    fn max_key_len(&self) -> usize;
+    /// # Error
+    /// - Blob(BlobError::InvalidKey): the key is empty or longer than `max_key_len`.
+    fn contains_var(&self, key: &[u8]) -> error::Result<bool>;
+    /// See `BlobStore::meta`.
+    fn meta_var(&self, key: &[u8]) -> error::Result<BlobMeta>;
+    /// See `BlobStore::put`.
+    fn put_var(&self, key: &[u8], value: &[u8], opt: PutOpt) -> error::Result<()>;
+    /// See `BlobStore::get`.
+    fn get_var(&self, key: &[u8], buf: &mut [u8], opt: GetOpt) -> error::Result<()>;
+    fn get_owned_var(&self, key: &[u8], opt: GetOpt) -> error::Result<Vec<u8>> {
+        let len = match &opt {
+            GetOpt::All => self.meta_var(key)?.size,
+            GetOpt::Range(range) => range.end - range.start,
+        };
+        let mut buf = vec![0_u8; len];
+        self.get_var(key, &mut buf, opt).map(|_| buf)
+    }
+    /// See `BlobStore::delete`.
+    fn delete_var(&self, key: &[u8], opt: DeleteOpt) -> error::Result<Option<Vec<u8>>>;
+    /// See `BlobStore::copy`.
+    fn copy_var(&self, src: &[u8], dst: &[u8], opt: CopyOpt) -> error::Result<()> {
+        let data = self.get_owned_var(src, GetOpt::All)?;
+        self.put_var(dst, &data, opt.into())
+    }
+    /// See `BlobStore::rename`.
+    fn rename_var(&self, src: &[u8], dst: &[u8], opt: CopyOpt) -> error::Result<()> {
+        self.copy_var(src, dst, opt)?;
+        if src != dst {
+            self.delete_var(src, DeleteOpt::Discard)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precondition {
+    /// The blob exists and has this version.
+    IfMatch(u64),
+    /// The blob doesn't exist.
+    IfNoneMatch,
+}
+
+/// A store whose writes can be conditioned on the version of the blob, for
+/// optimistic concurrency.
+pub trait ConditionalBlobStore: BlobStore {
+    /// Put if the precondition holds, return the new version of the blob.
+    /// # Error
+    /// - Blob(BlobError::PreconditionFailed): the precondition doesn't hold.
+    /// - the errors of `BlobStore::put`.
+    fn put_if(
+        &self,
+        key: Key,
+        value: &[u8],
+        opt: PutOpt,
+        precondition: Precondition,
+    ) -> error::Result<u64>;
+    /// Delete if the precondition holds.
+    /// # Error
+    /// - Blob(BlobError::PreconditionFailed): the precondition doesn't hold.
+    /// - the errors of `BlobStore::delete`.
+    fn delete_if(
+        &self,
+        key: Key,
+        opt: DeleteOpt,
+        precondition: Precondition,
+    ) -> error::Result<Option<Vec<u8>>>;
+}
+
+/// A store able to apply writes to several blobs atomically.
+pub trait TransactionalBlobStore: BlobStore {
+    type Transaction<'a>: Transaction
+    where
+        Self: 'a;
+
+    fn begin(&self) -> error::Result<Self::Transaction<'_>>;
+}
+
+/// Writes staged by a transaction, applied all together or not at all by
+/// `commit`. Dropping a transaction without committing it rolls it back.
+pub trait Transaction {
+    /// # Error
+    /// See `BlobStore::put`, against the state of the store updated with the
+    /// writes staged so far.
+    fn put(&mut self, key: Key, value: &[u8], opt: PutOpt) -> error::Result<()>;
+    /// # Error
+    /// - Blob(BlobError::NotFound): the blob doesn't exist.
+    fn delete(&mut self, key: Key) -> error::Result<()>;
+    fn commit(self) -> error::Result<()>;
+    fn rollback(self) -> error::Result<()>;
+}
+
+/// A store serving several blobs in one call, e.g. with their I/O submitted
+/// together. The default methods go through the blobs one at a time.
+pub trait BatchBlobStore: BlobStore {
+    /// Read whole blobs, one result per key, in the order of `keys`.
+    /// # Error
+    /// See `BlobStore::get`, for each blob.
+    fn get_many(&self, keys: &[Key]) -> Vec<error::Result<Vec<u8>>> {
+        keys.iter()
+            .map(|key| self.get_owned(*key, GetOpt::All))
+            .collect()
+    }
+    /// Write whole blobs with `opt`, one result per blob, in the order of
+    /// `blobs`. A failed write doesn't stop the others.
+    /// # Error
+    /// See `BlobStore::put`, for each blob.
+    fn put_many(&self, blobs: &[(Key, &[u8])], opt: PutOpt) -> Vec<error::Result<()>> {
+        blobs
+            .iter()
+            .map(|(key, value)| self.put(*key, value, opt.clone()))
+            .collect()
+    }
+}
+
+/// A store able to take point-in-time snapshots of itself.
+pub trait SnapshotBlobStore: BlobStore {
+    /// Read-only view of the store, writes fail with `Error::ReadOnly`.
+    type Snapshot<'a>: BlobStore
+    where
+        Self: 'a;
+
+    /// Take a snapshot of the blobs of the store. The snapshot is not
+    /// affected by the later writes to the store.
+    fn snapshot(&self) -> error::Result<Self::Snapshot<'_>>;
+}
+
+/// A store partitioned into named buckets, each an independent key space.
+///
+/// Bucket names are 1 to 63 characters of `[a-z0-9._-]` and don't start with a dot.
+pub trait BucketStore {
+    type Bucket<'a>: BlobStore
+    where
+        Self: 'a;
+
+    /// # Error
+    /// - Bucket(BucketError::AlreadyExists): the bucket already exists.
+    /// - Bucket(BucketError::InvalidName): the name is not a valid bucket name.
+    fn create_bucket(&self, name: &str) -> error::Result<Self::Bucket<'_>>;
+    /// # Error
+    /// - Bucket(BucketError::NotFound): the bucket doesn't exist.
+    fn bucket(&self, name: &str) -> error::Result<Self::Bucket<'_>>;
+    /// List the names of all the buckets, in no particular order.
+    fn list_buckets(&self) -> error::Result<Vec<String>>;
+    /// Drop a bucket together with all of its blobs.
+    /// # Error
+    /// - Bucket(BucketError::NotFound): the bucket doesn't exist.
+    fn drop_bucket(&self, name: &str) -> error::Result<()>;
 }
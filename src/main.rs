@@ -1,12 +1,25 @@
-use blob_store::Key;
+use blob_store::{BlobStore, Key, KeyLike, VarKeyBlobStore};
 use rand::Rng;
 
-type LoadRecord = (blob_store::prelude::Key, BlobOps);
+/// the fixed key of a blob, the hash of its name, then the name itself
+type LoadRecord = (Key, Vec<u8>, BlobOps);
 
 fn main() {
-    const HELP_MSG: &str = "Usage: blobstore <device path> <test load file>";
+    const HELP_MSG: &str =
+        "Usage: blobstore [--direct] [--var-keys] <device path> <test load file>";
     const MAX_LOAD: usize = 1024 * 1024;
-    let args = std::env::args().collect::<Vec<String>>();
+    let mut args = std::env::args().collect::<Vec<String>>();
+    let mut flag = |name: &str| match args.iter().position(|arg| arg == name) {
+        Some(i) => {
+            args.remove(i);
+            true
+        }
+        None => false,
+    };
+    // bypass the page cache of the device where the backend supports it
+    let direct_io = flag("--direct");
+    // also run the load by the names of the blobs, as variable-length keys
+    let var_keys = flag("--var-keys");
     if args.len() != 3 {
         panic!("{}", HELP_MSG);
     }
@@ -22,66 +35,83 @@ fn main() {
         .filter_map(Result::ok)
         .map(parse_record)
         .map(|Record { blob, read, .. }| {
-            let blob_id = blob.blob_name.as_bytes().chunks(Key::default().len()).fold(
-                Key::default(),
-                |mut acc, x| {
-                    acc.iter_mut().zip(x).for_each(|(acc, x)| *acc ^= x);
-                    acc
-                },
-            );
+            let blob_id = blob.blob_name.as_key();
             let ops = if read {
                 BlobOps::Read
             } else {
                 BlobOps::Write((0..blob.blob_bytes).map(|_| rng.gen::<u8>()).collect())
             };
-            (blob_id, ops)
+            (blob_id, blob.blob_name.into_bytes(), ops)
         })
         .collect::<Vec<_>>();
     #[cfg(feature = "local_fs")]
     {
-        let path = {
-            let mut p = device_path.clone();
-            p.push("local_fs");
-            if p.exists() {
-                std::fs::remove_dir_all(p.as_path()).unwrap();
-            }
-            p
+        let connect = |name| {
+            blob_store::prelude::LocalFileSystemBlobStore::connect(fresh_dir(&device_path, name))
+                .unwrap()
+                .with_direct_io(direct_io)
         };
-        std::fs::create_dir_all(path.as_path()).unwrap();
-        let blob_store = blob_store::prelude::LocalFileSystemBlobStore::connect(path).unwrap();
-        let result = bench_backend(&blob_store, &load);
+        let result = bench_backend(&connect("local_fs"), &load);
         println!("local fs benchmark:\n{result}");
+        if var_keys {
+            let result = bench_backend_var(&connect("local_fs_var"), &load);
+            println!("local fs benchmark, variable-length keys:\n{result}");
+        }
     }
     #[cfg(feature = "memmap")]
     {
-        let path = {
-            let mut p = device_path.clone();
-            p.push("memmap");
-            if p.exists() {
-                std::fs::remove_dir_all(p.as_path()).unwrap();
-            }
-            p
+        let connect = |name| {
+            blob_store::prelude::MemMapStore::connect(fresh_dir(&device_path, name)).unwrap()
         };
-        std::fs::create_dir_all(path.as_path()).unwrap();
-        let blob_store = blob_store::prelude::MemMapStore::connect(path).unwrap();
-        let result = bench_backend(&blob_store, &load);
+        let result = bench_backend(&connect("memmap"), &load);
         println!("memmap benchmark:\n{result}");
+        if var_keys {
+            let result = bench_backend_var(&connect("memmap_var"), &load);
+            println!("memmap benchmark, variable-length keys:\n{result}");
+        }
     }
     #[cfg(feature = "sqlite")]
     {
-        let path = {
-            let mut p = device_path.clone();
-            p.push("sqlite");
-            if p.exists() {
-                std::fs::remove_dir_all(p.as_path()).unwrap();
+        use blob_store::prelude::{JournalMode, SqliteConfig, Synchronous};
+        // SQLite's own defaults, then tuned settings, to compare their effect
+        let configs = [
+            (
+                "sqlite",
+                SqliteConfig::new().with_journal_mode(JournalMode::Delete),
+            ),
+            (
+                "sqlite_tuned",
+                SqliteConfig::new()
+                    .with_journal_mode(JournalMode::Wal)
+                    .with_synchronous(Synchronous::Normal)
+                    .with_cache_size(-64 * 1024)
+                    .with_mmap_size(256 * 1024 * 1024)
+                    .with_checkpoint_interval(1024),
+            ),
+        ];
+        for (name, config) in configs {
+            let blob_store = config.open(fresh_dir(&device_path, name)).unwrap();
+            let result = bench_backend(&blob_store, &load);
+            println!("{name} benchmark:\n{config:?}\n{result}");
+            if var_keys {
+                let var_name = format!("{name}_var");
+                let blob_store = config.open(fresh_dir(&device_path, &var_name)).unwrap();
+                let result = bench_backend_var(&blob_store, &load);
+                println!("{name} benchmark, variable-length keys:\n{result}");
             }
-            p
-        };
-        std::fs::create_dir_all(path.as_path()).unwrap();
-        let blob_store = blob_store::prelude::SqliteBlobStore::connect(path).unwrap();
-        let result = bench_backend(&blob_store, &load);
-        println!("sqlite benchmark:\n{result}");
+        }
+    }
+}
+
+/// an empty directory `name` on the device
+#[allow(dead_code)]
+fn fresh_dir(device_path: &std::path::Path, name: &str) -> std::path::PathBuf {
+    let path = device_path.join(name);
+    if path.exists() {
+        std::fs::remove_dir_all(path.as_path()).unwrap();
     }
+    std::fs::create_dir_all(path.as_path()).unwrap();
+    path
 }
 
 fn parse_record(record: csv::StringRecord) -> Record {
@@ -128,9 +158,10 @@ fn parse_record(record: csv::StringRecord) -> Record {
     }
 }
 
-fn bench_backend(blob_store: &dyn blob_store::BlobStore, load: &[LoadRecord]) -> BenchResult {
+#[allow(dead_code)]
+fn bench_backend(blob_store: &dyn BlobStore, load: &[LoadRecord]) -> BenchResult {
     let mut result = BenchResult::default();
-    for (blob_id, ops) in load {
+    for (blob_id, _, ops) in load {
         match ops {
             BlobOps::Read => {
                 let start = std::time::Instant::now();
@@ -160,7 +191,50 @@ fn bench_backend(blob_store: &dyn blob_store::BlobStore, load: &[LoadRecord]) ->
                     result.write_size += size;
                 } else {
                     blob_store
-                        .put(*blob_id, &data, blob_store::PutOpt::Create)
+                        .put(*blob_id, data, blob_store::PutOpt::Create)
+                        .unwrap();
+                    result.create += 1;
+                    result.write_size += data.len();
+                }
+                result.write_time += start.elapsed();
+            }
+        }
+    }
+    result
+}
+
+/// `bench_backend` by the names of the blobs, which skip the fast path of
+/// fixed keys in the stores that have one
+#[allow(dead_code)]
+fn bench_backend_var(blob_store: &dyn VarKeyBlobStore, load: &[LoadRecord]) -> BenchResult {
+    let mut result = BenchResult::default();
+    for (_, name, ops) in load {
+        match ops {
+            BlobOps::Read => {
+                let start = std::time::Instant::now();
+                if blob_store.contains_var(name).unwrap() {
+                    let val = blob_store
+                        .get_owned_var(name, blob_store::GetOpt::All)
+                        .unwrap();
+                    result.read += 1;
+                    result.read_size += val.len();
+                } else {
+                    result.read_non_exist += 1;
+                }
+                result.read_time += start.elapsed();
+            }
+            BlobOps::Write(data) => {
+                let start = std::time::Instant::now();
+                if blob_store.contains_var(name).unwrap() {
+                    let size = std::cmp::min(blob_store.meta_var(name).unwrap().size, data.len());
+                    blob_store
+                        .put_var(name, &data[0..size], blob_store::PutOpt::Replace(0..size))
+                        .unwrap();
+                    result.replace += 1;
+                    result.write_size += size;
+                } else {
+                    blob_store
+                        .put_var(name, data, blob_store::PutOpt::Create)
                         .unwrap();
                     result.create += 1;
                     result.write_size += data.len();
@@ -217,7 +291,7 @@ impl std::fmt::Display for BenchResult {
         let str = format!(
             "[LOAD] read: {}; read non-exist: {}; create: {}, replace: {}\n[SIZE] read: {:.2}MB; write: {:.2}MB\n[TIME] read: {:.3} s; write: {:.3} s\n[THRPUT] read: {:.3} MB/s; write: {:.3} MB/s",
             self.read, self.read_non_exist, self.create, self.replace
-            , f64::try_from(self.read_size as u32).unwrap() , f64::try_from(self.write_size as u32).unwrap(), self.read_time.as_secs_f64(), self.write_time.as_secs_f64()
+            , f64::from(self.read_size as u32) , f64::from(self.write_size as u32), self.read_time.as_secs_f64(), self.write_time.as_secs_f64()
             ,thrput.0, thrput.1
         );
         f.write_str(&str)
@@ -227,11 +301,11 @@ impl std::fmt::Display for BenchResult {
 /// calculate benchmark (read, write) throughput (in MB/s)
 fn bench_throughput(bench_result: &BenchResult) -> (f64, f64) {
     (
-        f64::try_from(bench_result.read_size as u32).unwrap()
+        f64::from(bench_result.read_size as u32)
             / 1024_f64
             / 1024_f64
             / bench_result.read_time.as_secs_f64(),
-        f64::try_from(bench_result.write_size as u32).unwrap()
+        f64::from(bench_result.write_size as u32)
             / 1024_f64
             / 1024_f64
             / bench_result.write_time.as_secs_f64(),
@@ -0,0 +1,327 @@
+use anyhow::anyhow;
+
+use crate::{
+    error::{BlobError, Error, Result},
+    BlobMeta, BlobRange, BlobStore, Checksum, DeleteOpt, GetOpt, Key, PutOpt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChecksumAlgorithm {
+    Crc32c,
+    XxHash64,
+}
+
+impl ChecksumAlgorithm {
+    fn id(&self) -> u8 {
+        match self {
+            ChecksumAlgorithm::Crc32c => 1,
+            ChecksumAlgorithm::XxHash64 => 2,
+        }
+    }
+
+    fn from_id(id: u8) -> Result<Self> {
+        match id {
+            1 => Ok(ChecksumAlgorithm::Crc32c),
+            2 => Ok(ChecksumAlgorithm::XxHash64),
+            _ => Err(BlobError::Corrupted.into()),
+        }
+    }
+
+    fn digest(&self, data: &[u8]) -> u64 {
+        match self {
+            ChecksumAlgorithm::Crc32c => crc32c::crc32c(data).into(),
+            ChecksumAlgorithm::XxHash64 => xxhash_rust::xxh3::xxh3_64(data),
+        }
+    }
+}
+
+/// Checksum table of a blob.
+///
+/// Layout of a blob in the inner store:
+/// `magic | algorithm | chunk size | size | crc32c of the previous fields | chunk checksums | data`.
+/// The data starts right after the table, whose length only depends on the size.
+struct Header {
+    algorithm: ChecksumAlgorithm,
+    chunk_size: usize,
+    size: usize,
+}
+
+impl Header {
+    const MAGIC: [u8; 4] = *b"BSC1";
+    const PREFIX_LEN: usize = 24;
+    const ENTRY_LEN: usize = std::mem::size_of::<u64>();
+
+    fn decode(prefix: &[u8; Self::PREFIX_LEN]) -> Result<Self> {
+        if prefix[0..4] != Self::MAGIC {
+            return Err(Error::other(anyhow!("not a checksummed blob")));
+        }
+        let crc = u32::from_le_bytes(prefix[20..24].try_into().unwrap());
+        if crc32c::crc32c(&prefix[0..20]) != crc {
+            return Err(BlobError::Corrupted.into());
+        }
+        Ok(Self {
+            algorithm: ChecksumAlgorithm::from_id(prefix[4])?,
+            chunk_size: u32::from_le_bytes(prefix[8..12].try_into().unwrap()) as usize,
+            size: u64::from_le_bytes(prefix[12..20].try_into().unwrap()) as usize,
+        })
+    }
+
+    fn encode(&self) -> [u8; Self::PREFIX_LEN] {
+        let mut prefix = [0_u8; Self::PREFIX_LEN];
+        prefix[0..4].copy_from_slice(&Self::MAGIC);
+        prefix[4] = self.algorithm.id();
+        prefix[8..12].copy_from_slice(&u32::try_from(self.chunk_size).unwrap().to_le_bytes());
+        prefix[12..20].copy_from_slice(&u64::try_from(self.size).unwrap().to_le_bytes());
+        let crc = crc32c::crc32c(&prefix[0..20]);
+        prefix[20..24].copy_from_slice(&crc.to_le_bytes());
+        prefix
+    }
+
+    fn chunk_count(&self) -> usize {
+        self.size.div_ceil(self.chunk_size)
+    }
+
+    /// offset of the data in the inner blob
+    fn data_offset(&self) -> usize {
+        Self::PREFIX_LEN + self.chunk_count() * Self::ENTRY_LEN
+    }
+
+    /// range of the checksums of the chunks `chunks` in the inner blob
+    fn entries_range(&self, chunks: std::ops::Range<usize>) -> BlobRange {
+        Self::PREFIX_LEN + chunks.start * Self::ENTRY_LEN
+            ..Self::PREFIX_LEN + chunks.end * Self::ENTRY_LEN
+    }
+
+    /// chunks overlapping a non-empty logical range
+    fn chunks_of(&self, range: &BlobRange) -> std::ops::Range<usize> {
+        range.start / self.chunk_size..(range.end - 1) / self.chunk_size + 1
+    }
+
+    /// logical range covered by the chunks `chunks`
+    fn logical_range(&self, chunks: std::ops::Range<usize>) -> BlobRange {
+        chunks.start * self.chunk_size..std::cmp::min(chunks.end * self.chunk_size, self.size)
+    }
+}
+
+/// Maintain per-chunk checksums of the blobs of the inner store.
+///
+/// Checksums are computed on `put` and verified on `get`, a range read only
+/// verifies the chunks it overlaps. A mismatch is reported as
+/// `BlobError::Corrupted`. The checksum reported by `meta` is derived from
+/// the chunk checksums, so that it is maintained without reading the whole
+/// blob on `PutOpt::Replace`: with CRC32C the chunk checksums combine into a
+/// `Checksum::Crc32c` of the whole content, with xxHash it is a
+/// `Checksum::ChunkTable`, the digest of the chunk checksums.
+pub struct ChecksumStore<S>
+where
+    S: BlobStore,
+{
+    store: S,
+    algorithm: ChecksumAlgorithm,
+    chunk_size: usize,
+}
+
+impl<S> ChecksumStore<S>
+where
+    S: BlobStore,
+{
+    pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+    pub fn new(store: S, algorithm: ChecksumAlgorithm) -> Self {
+        Self::with_chunk_size(store, algorithm, Self::DEFAULT_CHUNK_SIZE)
+    }
+
+    /// # Panic
+    /// - `chunk_size` is zero or doesn't fit in 32 bits.
+    pub fn with_chunk_size(store: S, algorithm: ChecksumAlgorithm, chunk_size: usize) -> Self {
+        assert!(chunk_size > 0 && u32::try_from(chunk_size).is_ok());
+        Self {
+            store,
+            algorithm,
+            chunk_size,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn read_header(&self, key: Key) -> Result<Header> {
+        let mut prefix = [0_u8; Header::PREFIX_LEN];
+        self.store
+            .get(key, &mut prefix, GetOpt::Range(0..Header::PREFIX_LEN))?;
+        Header::decode(&prefix)
+    }
+
+    fn read_entries(
+        &self,
+        key: Key,
+        header: &Header,
+        chunks: std::ops::Range<usize>,
+    ) -> Result<Vec<u64>> {
+        Ok(self
+            .store
+            .get_owned(key, GetOpt::Range(header.entries_range(chunks)))?
+            .chunks_exact(Header::ENTRY_LEN)
+            .map(|entry| u64::from_le_bytes(entry.try_into().unwrap()))
+            .collect())
+    }
+
+    /// read and verify the chunks `chunks`, return their content
+    fn read_chunks(
+        &self,
+        key: Key,
+        header: &Header,
+        chunks: std::ops::Range<usize>,
+    ) -> Result<Vec<u8>> {
+        let entries = self.read_entries(key, header, chunks.clone())?;
+        let logical_range = header.logical_range(chunks);
+        let offset = header.data_offset();
+        let data = self.store.get_owned(
+            key,
+            GetOpt::Range(offset + logical_range.start..offset + logical_range.end),
+        )?;
+        let intact = data
+            .chunks(header.chunk_size)
+            .zip(entries)
+            .all(|(chunk, entry)| header.algorithm.digest(chunk) == entry);
+        if !intact {
+            return Err(BlobError::Corrupted.into());
+        }
+        Ok(data)
+    }
+
+    fn entries_of(algorithm: ChecksumAlgorithm, chunk_size: usize, data: &[u8]) -> Vec<u8> {
+        data.chunks(chunk_size)
+            .flat_map(|chunk| algorithm.digest(chunk).to_le_bytes())
+            .collect()
+    }
+
+    fn replace(&self, key: Key, value: &[u8], range: BlobRange) -> Result<()> {
+        let header = self.read_header(key)?;
+        if !crate::store_impl::helpers::range_contains(&(0..header.size), &range) {
+            return Err(BlobError::RangeError.into());
+        }
+        if value.len() != range.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+        let chunks = header.chunks_of(&range);
+        let logical_range = header.logical_range(chunks.clone());
+        let mut data = self.read_chunks(key, &header, chunks.clone())?;
+        data[range.start - logical_range.start..range.end - logical_range.start]
+            .copy_from_slice(value);
+        let offset = header.data_offset();
+        self.store.put(
+            key,
+            &data,
+            PutOpt::Replace(offset + logical_range.start..offset + logical_range.end),
+        )?;
+        let entries = Self::entries_of(header.algorithm, header.chunk_size, &data);
+        self.store
+            .put(key, &entries, PutOpt::Replace(header.entries_range(chunks)))
+    }
+}
+
+impl<S> BlobStore for ChecksumStore<S>
+where
+    S: BlobStore,
+{
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.store.contains(key)
+    }
+
+    fn meta(&self, key: Key) -> Result<BlobMeta> {
+        let header = self.read_header(key)?;
+        let entries = self.store.get_owned(
+            key,
+            GetOpt::Range(header.entries_range(0..header.chunk_count())),
+        )?;
+        let checksum = match header.algorithm {
+            ChecksumAlgorithm::Crc32c => Checksum::Crc32c(
+                entries
+                    .chunks_exact(Header::ENTRY_LEN)
+                    .zip(
+                        (0..header.chunk_count())
+                            .map(|chunk| header.logical_range(chunk..chunk + 1)),
+                    )
+                    .fold(0, |crc, (entry, range)| {
+                        let entry = u64::from_le_bytes(entry.try_into().unwrap()) as u32;
+                        crc32c::crc32c_combine(crc, entry, range.len())
+                    }),
+            ),
+            ChecksumAlgorithm::XxHash64 => Checksum::ChunkTable(header.algorithm.digest(&entries)),
+        };
+        Ok(BlobMeta {
+            size: header.size,
+            checksum: Some(checksum),
+            ..Default::default()
+        })
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        match opt {
+            PutOpt::Create | PutOpt::ReplaceOrCreate => {
+                let header = Header {
+                    algorithm: self.algorithm,
+                    chunk_size: self.chunk_size,
+                    size: value.len(),
+                };
+                let mut blob = Vec::with_capacity(header.data_offset() + value.len());
+                blob.extend_from_slice(&header.encode());
+                blob.extend_from_slice(&Self::entries_of(self.algorithm, self.chunk_size, value));
+                blob.extend_from_slice(value);
+                self.store.put(key, &blob, opt)
+            }
+            PutOpt::Replace(range) => self.replace(key, value, range),
+        }
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        let header = self.read_header(key)?;
+        let range = match opt {
+            GetOpt::All => 0..header.size,
+            GetOpt::Range(range) => range,
+        };
+        if !crate::store_impl::helpers::range_contains(&(0..header.size), &range) {
+            return Err(BlobError::RangeError.into());
+        }
+        if buf.len() != range.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+        let chunks = header.chunks_of(&range);
+        let logical_range = header.logical_range(chunks.clone());
+        let data = self.read_chunks(key, &header, chunks)?;
+        buf.copy_from_slice(
+            &data[range.start - logical_range.start..range.end - logical_range.start],
+        );
+        Ok(())
+    }
+
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        let content = crate::store_impl::helpers::interest(self, key, &opt)?;
+        self.store.delete(key, DeleteOpt::Discard)?;
+        Ok(content)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.store.keys()
+    }
+
+    fn stats(&self) -> Result<crate::StoreStats> {
+        self.store.stats()
+    }
+
+    fn is_shared(&self) -> bool {
+        self.store.is_shared()
+    }
+}
@@ -0,0 +1,389 @@
+use anyhow::anyhow;
+
+use crate::{
+    error::{BlobError, Error, Result},
+    BlobMeta, BlobRange, BlobStore, DeleteOpt, GetOpt, Key, PutOpt,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Codec {
+    Zstd { level: i32 },
+    Lz4,
+}
+
+impl Codec {
+    fn id(&self) -> u8 {
+        match self {
+            Codec::Zstd { .. } => 1,
+            Codec::Lz4 => 2,
+        }
+    }
+}
+
+/// Frame index and sizes of a compressed blob.
+///
+/// Layout of a blob in the inner store:
+/// `magic | codec | frame size | logical size | frame index | frames...`,
+/// where each index entry is the stored length of a frame, with the highest bit
+/// set if the frame is stored raw.
+struct Header {
+    codec: u8,
+    frame_size: usize,
+    size: usize,
+    frames: Vec<u32>,
+}
+
+impl Header {
+    const MAGIC: [u8; 4] = *b"BSZ1";
+    const PREFIX_LEN: usize = 20;
+    const ENTRY_LEN: usize = std::mem::size_of::<u32>();
+    const RAW_FRAME: u32 = 1 << 31;
+
+    fn decode_prefix(prefix: &[u8; Self::PREFIX_LEN]) -> Result<(u8, usize, usize)> {
+        if prefix[0..4] != Self::MAGIC {
+            return Err(Error::other(anyhow!("not a compressed blob")));
+        }
+        let frame_size = u32::from_le_bytes(prefix[8..12].try_into().unwrap()) as usize;
+        let size = u64::from_le_bytes(prefix[12..20].try_into().unwrap()) as usize;
+        if frame_size == 0 {
+            return Err(Error::other(anyhow!("invalid frame size")));
+        }
+        Ok((prefix[4], frame_size, size))
+    }
+
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&Self::MAGIC);
+        out.extend_from_slice(&[self.codec, 0, 0, 0]);
+        out.extend_from_slice(&u32::try_from(self.frame_size).unwrap().to_le_bytes());
+        out.extend_from_slice(&u64::try_from(self.size).unwrap().to_le_bytes());
+        self.frames
+            .iter()
+            .for_each(|entry| out.extend_from_slice(&entry.to_le_bytes()));
+    }
+
+    fn len(&self) -> usize {
+        Self::PREFIX_LEN + self.frames.len() * Self::ENTRY_LEN
+    }
+
+    #[inline]
+    fn stored_len(entry: u32) -> usize {
+        (entry & !Self::RAW_FRAME) as usize
+    }
+
+    /// physical range of the frames `frames` in the inner blob
+    fn stored_range(&self, frames: std::ops::Range<usize>) -> BlobRange {
+        let start = self.len()
+            + self.frames[..frames.start]
+                .iter()
+                .copied()
+                .map(Self::stored_len)
+                .sum::<usize>();
+        let len = self.frames[frames]
+            .iter()
+            .copied()
+            .map(Self::stored_len)
+            .sum::<usize>();
+        start..start + len
+    }
+
+    /// logical range covered by the frames `frames`
+    fn logical_range(&self, frames: std::ops::Range<usize>) -> BlobRange {
+        frames.start * self.frame_size..std::cmp::min(frames.end * self.frame_size, self.size)
+    }
+
+    /// frames overlapping a non-empty logical range
+    fn frames_of(&self, range: &BlobRange) -> std::ops::Range<usize> {
+        range.start / self.frame_size..(range.end - 1) / self.frame_size + 1
+    }
+}
+
+/// Compress blobs transparently before handing them to the inner store.
+///
+/// Blobs are split into fixed-size frames which are compressed independently,
+/// so that a range read only decompresses the frames it overlaps. Frames that
+/// do not shrink are stored raw. `meta` reports the logical (uncompressed) size.
+pub struct CompressedStore<S>
+where
+    S: BlobStore,
+{
+    store: S,
+    codec: Codec,
+    frame_size: usize,
+}
+
+impl<S> CompressedStore<S>
+where
+    S: BlobStore,
+{
+    pub const DEFAULT_FRAME_SIZE: usize = 64 * 1024;
+
+    pub fn new(store: S, codec: Codec) -> Self {
+        Self::with_frame_size(store, codec, Self::DEFAULT_FRAME_SIZE)
+    }
+
+    /// # Panic
+    /// - `frame_size` is zero or doesn't fit in 31 bits.
+    pub fn with_frame_size(store: S, codec: Codec, frame_size: usize) -> Self {
+        assert!(frame_size > 0 && frame_size < Header::RAW_FRAME as usize);
+        Self {
+            store,
+            codec,
+            frame_size,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn read_header(&self, key: Key) -> Result<Header> {
+        let mut prefix = [0_u8; Header::PREFIX_LEN];
+        self.store
+            .get(key, &mut prefix, GetOpt::Range(0..Header::PREFIX_LEN))?;
+        let (codec, frame_size, size) = Header::decode_prefix(&prefix)?;
+        let frame_count = size.div_ceil(frame_size);
+        let index_range = Header::PREFIX_LEN..Header::PREFIX_LEN + frame_count * Header::ENTRY_LEN;
+        let frames = self
+            .store
+            .get_owned(key, GetOpt::Range(index_range))?
+            .chunks_exact(Header::ENTRY_LEN)
+            .map(|entry| u32::from_le_bytes(entry.try_into().unwrap()))
+            .collect();
+        Ok(Header {
+            codec,
+            frame_size,
+            size,
+            frames,
+        })
+    }
+
+    /// codec to recompress the frames of a blob written with codec `id`
+    fn codec_of(&self, id: u8) -> Result<Codec> {
+        match id {
+            id if id == self.codec.id() => Ok(self.codec),
+            1 => Ok(Codec::Zstd {
+                level: zstd::DEFAULT_COMPRESSION_LEVEL,
+            }),
+            2 => Ok(Codec::Lz4),
+            id => Err(Error::other(anyhow!("unknown codec {id}"))),
+        }
+    }
+
+    /// compress a frame, return its index entry and stored bytes
+    fn compress_frame(codec: Codec, frame: &[u8]) -> Result<(u32, Vec<u8>)> {
+        let compressed = match codec {
+            Codec::Zstd { level } => zstd::bulk::compress(frame, level)?,
+            Codec::Lz4 => lz4_flex::block::compress(frame),
+        };
+        if compressed.len() < frame.len() {
+            Ok((u32::try_from(compressed.len()).unwrap(), compressed))
+        } else {
+            let entry = u32::try_from(frame.len()).unwrap() | Header::RAW_FRAME;
+            Ok((entry, frame.to_vec()))
+        }
+    }
+
+    /// decompress the stored bytes of a frame into `buf`, whose length is the logical frame length
+    fn decompress_frame(codec: u8, entry: u32, stored: &[u8], buf: &mut [u8]) -> Result<()> {
+        if entry & Header::RAW_FRAME != 0 {
+            buf.copy_from_slice(stored);
+            return Ok(());
+        }
+        let len = match codec {
+            1 => zstd::bulk::decompress_to_buffer(stored, buf)?,
+            2 => lz4_flex::block::decompress_into(stored, buf).map_err(Error::other)?,
+            codec => return Err(Error::other(anyhow!("unknown codec {codec}"))),
+        };
+        if len != buf.len() {
+            return Err(Error::other(anyhow!("truncated compressed frame")));
+        }
+        Ok(())
+    }
+
+    /// decompress consecutive frames `frames` from their stored bytes into `buf`
+    fn decompress_frames(
+        header: &Header,
+        frames: std::ops::Range<usize>,
+        stored: &[u8],
+        buf: &mut [u8],
+    ) -> Result<()> {
+        let mut stored_offset = 0;
+        let first = frames.start;
+        frames.into_iter().try_for_each(|idx| {
+            let entry = header.frames[idx];
+            let stored_len = Header::stored_len(entry);
+            let logical = header.logical_range(idx..idx + 1);
+            let base = first * header.frame_size;
+            Self::decompress_frame(
+                header.codec,
+                entry,
+                &stored[stored_offset..stored_offset + stored_len],
+                &mut buf[logical.start - base..logical.end - base],
+            )?;
+            stored_offset += stored_len;
+            Ok(())
+        })
+    }
+
+    fn encode_blob(&self, value: &[u8]) -> Result<Vec<u8>> {
+        let (frames, data): (Vec<_>, Vec<_>) = value
+            .chunks(self.frame_size)
+            .map(|frame| Self::compress_frame(self.codec, frame))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let header = Header {
+            codec: self.codec.id(),
+            frame_size: self.frame_size,
+            size: value.len(),
+            frames,
+        };
+        let mut blob = Vec::with_capacity(header.len() + data.iter().map(Vec::len).sum::<usize>());
+        header.encode(&mut blob);
+        data.iter().for_each(|frame| blob.extend_from_slice(frame));
+        Ok(blob)
+    }
+
+    fn replace(&self, key: Key, value: &[u8], range: BlobRange) -> Result<()> {
+        let mut header = self.read_header(key)?;
+        if !crate::store_impl::helpers::range_contains(&(0..header.size), &range) {
+            return Err(BlobError::RangeError.into());
+        }
+        if value.len() != range.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+        // decompress the affected frames and patch them
+        let frames = header.frames_of(&range);
+        let stored_range = header.stored_range(frames.clone());
+        let logical_range = header.logical_range(frames.clone());
+        let stored = self
+            .store
+            .get_owned(key, GetOpt::Range(stored_range.clone()))?;
+        let mut logical = vec![0_u8; logical_range.len()];
+        Self::decompress_frames(&header, frames.clone(), &stored, &mut logical)?;
+        logical[range.start - logical_range.start..range.end - logical_range.start]
+            .copy_from_slice(value);
+        // recompress them with the codec of the blob
+        let codec = self.codec_of(header.codec)?;
+        let (entries, data): (Vec<_>, Vec<_>) = logical
+            .chunks(header.frame_size)
+            .map(|frame| Self::compress_frame(codec, frame))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter()
+            .unzip();
+        let data = data.concat();
+        let same_layout = entries
+            .iter()
+            .zip(&header.frames[frames.clone()])
+            .all(|(new, old)| Header::stored_len(*new) == Header::stored_len(*old));
+        if same_layout {
+            // frames fit in place, only rewrite the frames and their index entries
+            let index_start = Header::PREFIX_LEN + frames.start * Header::ENTRY_LEN;
+            let index = entries
+                .iter()
+                .flat_map(|entry| entry.to_le_bytes())
+                .collect::<Vec<_>>();
+            self.store.put(
+                key,
+                &index,
+                PutOpt::Replace(index_start..index_start + index.len()),
+            )?;
+            return self.store.put(key, &data, PutOpt::Replace(stored_range));
+        }
+        // splice the new frames between the untouched ones, the header length doesn't change
+        let old = self.store.get_owned(key, GetOpt::All)?;
+        header.frames.splice(frames, entries);
+        let mut blob = Vec::with_capacity(old.len() + data.len());
+        header.encode(&mut blob);
+        blob.extend_from_slice(&old[header.len()..stored_range.start]);
+        blob.extend_from_slice(&data);
+        blob.extend_from_slice(&old[stored_range.end..]);
+        self.store.put(key, &blob, PutOpt::ReplaceOrCreate)
+    }
+}
+
+impl<S> BlobStore for CompressedStore<S>
+where
+    S: BlobStore,
+{
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.store.contains(key)
+    }
+
+    fn meta(&self, key: Key) -> Result<BlobMeta> {
+        let mut prefix = [0_u8; Header::PREFIX_LEN];
+        self.store
+            .get(key, &mut prefix, GetOpt::Range(0..Header::PREFIX_LEN))?;
+        let (_, _, size) = Header::decode_prefix(&prefix)?;
+        Ok(BlobMeta {
+            size,
+            ..Default::default()
+        })
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        match opt {
+            PutOpt::Create | PutOpt::ReplaceOrCreate => {
+                let blob = self.encode_blob(value)?;
+                self.store.put(key, &blob, opt)
+            }
+            PutOpt::Replace(range) => self.replace(key, value, range),
+        }
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        let header = self.read_header(key)?;
+        let range = match opt {
+            GetOpt::All => 0..header.size,
+            GetOpt::Range(range) => range,
+        };
+        if !crate::store_impl::helpers::range_contains(&(0..header.size), &range) {
+            return Err(BlobError::RangeError.into());
+        }
+        if buf.len() != range.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+        let frames = header.frames_of(&range);
+        let stored = self
+            .store
+            .get_owned(key, GetOpt::Range(header.stored_range(frames.clone())))?;
+        let logical_range = header.logical_range(frames.clone());
+        if logical_range == range {
+            return Self::decompress_frames(&header, frames, &stored, buf);
+        }
+        let mut logical = vec![0_u8; logical_range.len()];
+        Self::decompress_frames(&header, frames, &stored, &mut logical)?;
+        buf.copy_from_slice(
+            &logical[range.start - logical_range.start..range.end - logical_range.start],
+        );
+        Ok(())
+    }
+
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        let content = crate::store_impl::helpers::interest(self, key, &opt)?;
+        self.store.delete(key, DeleteOpt::Discard)?;
+        Ok(content)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.store.keys()
+    }
+
+    fn stats(&self) -> Result<crate::StoreStats> {
+        self.store.stats()
+    }
+
+    fn is_shared(&self) -> bool {
+        self.store.is_shared()
+    }
+}
@@ -0,0 +1,43 @@
+use serde::{Deserialize, Serialize};
+
+/// How a handle shares its store with the other handles.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OpenMode {
+    /// The only handle of the store, reading and writing.
+    #[default]
+    Exclusive,
+    /// Reading and writing along with other shared and read-only handles, for
+    /// the stores that support it.
+    Shared,
+    /// Reading only, along with other read-only handles.
+    ReadOnly,
+}
+
+/// When the writes to a store reach the device.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Durability {
+    /// The OS writes the blobs back when it sees fit.
+    #[default]
+    None,
+    /// The content of a blob is flushed before a write to it returns.
+    Data,
+    /// The directories are flushed too, so that the blobs created, renamed or
+    /// removed by a write survive a crash.
+    Full,
+}
+
+/// How the files of the blobs are laid out under the root of a store.
+///
+/// A store must be opened with the layout it was created with, the blobs of
+/// another layout are not found.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Layout {
+    /// `root/<first half of the hex key>/<second half>`
+    #[default]
+    Nested,
+    /// `root/<hex key>`
+    Flat,
+}
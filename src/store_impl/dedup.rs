@@ -0,0 +1,326 @@
+use anyhow::anyhow;
+use parking_lot::Mutex;
+
+use crate::{
+    error::{BlobError, Error, Result},
+    BlobMeta, BlobRange, BlobStore, DeleteOpt, GetOpt, Key, PutOpt,
+};
+
+/// How blobs are split before deduplication.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chunking {
+    /// Deduplicate whole blobs only.
+    Whole,
+    /// Content-defined chunking with FastCDC, so that blobs sharing regions
+    /// share the chunks of these regions. Sizes are in bytes, see
+    /// `fastcdc::v2020` for their valid ranges.
+    FastCdc { min: u32, avg: u32, max: u32 },
+}
+
+impl Chunking {
+    /// FastCDC with 16 KiB chunks on average
+    pub const DEFAULT_FASTCDC: Chunking = Chunking::FastCdc {
+        min: 4 * 1024,
+        avg: 16 * 1024,
+        max: 64 * 1024,
+    };
+
+    fn split<'a>(&self, data: &'a [u8]) -> Vec<&'a [u8]> {
+        match *self {
+            Chunking::Whole if data.is_empty() => vec![],
+            Chunking::Whole => vec![data],
+            Chunking::FastCdc { min, avg, max } => {
+                fastcdc::v2020::FastCDC::new(data, min, avg, max)
+                    .map(|chunk| &data[chunk.offset..chunk.offset + chunk.length])
+                    .collect()
+            }
+        }
+    }
+}
+
+/// Chunks of a blob, stored in the index store under the key of the blob:
+/// `magic | size | (chunk key, chunk length)...`
+struct Manifest {
+    size: usize,
+    chunks: Vec<(Key, usize)>,
+}
+
+impl Manifest {
+    const MAGIC: [u8; 4] = *b"BSD1";
+    const PREFIX_LEN: usize = 12;
+    const ENTRY_LEN: usize = 16;
+
+    fn decode(buf: &[u8]) -> Result<Self> {
+        if buf.len() < Self::PREFIX_LEN
+            || buf[0..4] != Self::MAGIC
+            || !(buf.len() - Self::PREFIX_LEN).is_multiple_of(Self::ENTRY_LEN)
+        {
+            return Err(Error::other(anyhow!("invalid dedup manifest")));
+        }
+        let size = u64::from_le_bytes(buf[4..12].try_into().unwrap()) as usize;
+        let chunks = buf[Self::PREFIX_LEN..]
+            .chunks_exact(Self::ENTRY_LEN)
+            .map(|entry| {
+                let key = entry[0..8].try_into().unwrap();
+                let len = u64::from_le_bytes(entry[8..16].try_into().unwrap()) as usize;
+                (key, len)
+            })
+            .collect();
+        Ok(Self { size, chunks })
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::with_capacity(Self::PREFIX_LEN + self.chunks.len() * Self::ENTRY_LEN);
+        buf.extend_from_slice(&Self::MAGIC);
+        buf.extend_from_slice(&u64::try_from(self.size).unwrap().to_le_bytes());
+        self.chunks.iter().for_each(|(key, len)| {
+            buf.extend_from_slice(key);
+            buf.extend_from_slice(&u64::try_from(*len).unwrap().to_le_bytes());
+        });
+        buf
+    }
+}
+
+/// Header of a chunk in the chunk store: `content hash | reference count`.
+const HASH_LEN: usize = blake3::OUT_LEN;
+const CHUNK_HEADER_LEN: usize = HASH_LEN + 8;
+const REFCOUNT_RANGE: BlobRange = HASH_LEN..CHUNK_HEADER_LEN;
+
+/// Store identical content only once.
+///
+/// The index store maps every key to the list of its chunks, and the chunk
+/// store keeps each distinct chunk once, under a key derived from its content
+/// hash, together with the number of references to it. Deleting a blob
+/// releases its chunks, and a chunk is deleted with its last reference.
+pub struct DedupStore<S>
+where
+    S: BlobStore,
+{
+    index: S,
+    chunks: S,
+    chunking: Chunking,
+    /// serialize the updates of the reference counts
+    lock: Mutex<()>,
+}
+
+impl<S> DedupStore<S>
+where
+    S: BlobStore,
+{
+    pub fn new(index: S, chunks: S, chunking: Chunking) -> Self {
+        Self {
+            index,
+            chunks,
+            chunking,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn index(&self) -> &S {
+        &self.index
+    }
+
+    pub fn chunks(&self) -> &S {
+        &self.chunks
+    }
+
+    pub fn into_inner(self) -> (S, S) {
+        (self.index, self.chunks)
+    }
+
+    fn read_manifest(&self, key: Key) -> Result<Manifest> {
+        Manifest::decode(&self.index.get_owned(key, GetOpt::All)?)
+    }
+
+    fn read_refcount(&self, chunk_key: Key) -> Result<u64> {
+        let mut buf = [0_u8; 8];
+        self.chunks
+            .get(chunk_key, &mut buf, GetOpt::Range(REFCOUNT_RANGE))?;
+        Ok(u64::from_le_bytes(buf))
+    }
+
+    /// add a reference to the chunk with this content, storing it if it is new
+    fn acquire_chunk(&self, data: &[u8]) -> Result<Key> {
+        let hash = blake3::hash(data);
+        let mut chunk_key: Key = hash.as_bytes()[0..8].try_into().unwrap();
+        loop {
+            let mut stored_hash = [0_u8; HASH_LEN];
+            match self
+                .chunks
+                .get(chunk_key, &mut stored_hash, GetOpt::Range(0..HASH_LEN))
+            {
+                Ok(()) if stored_hash == *hash.as_bytes() => {
+                    let refcount = self.read_refcount(chunk_key)? + 1;
+                    self.chunks.put(
+                        chunk_key,
+                        &refcount.to_le_bytes(),
+                        PutOpt::Replace(REFCOUNT_RANGE),
+                    )?;
+                    return Ok(chunk_key);
+                }
+                // another content with the same key prefix, probe the next key
+                Ok(()) => {
+                    chunk_key = u64::from_le_bytes(chunk_key).wrapping_add(1).to_le_bytes();
+                }
+                Err(Error::Blob(BlobError::NotFound)) => {
+                    let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + data.len());
+                    chunk.extend_from_slice(hash.as_bytes());
+                    chunk.extend_from_slice(&1_u64.to_le_bytes());
+                    chunk.extend_from_slice(data);
+                    self.chunks.put(chunk_key, &chunk, PutOpt::Create)?;
+                    return Ok(chunk_key);
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// drop a reference to a chunk, deleting it with its last reference
+    fn release_chunk(&self, chunk_key: Key) -> Result<()> {
+        let refcount = self.read_refcount(chunk_key)?;
+        if refcount <= 1 {
+            self.chunks.delete(chunk_key, DeleteOpt::Discard)?;
+        } else {
+            self.chunks.put(
+                chunk_key,
+                &(refcount - 1).to_le_bytes(),
+                PutOpt::Replace(REFCOUNT_RANGE),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// store the chunks of a blob and write its manifest, then release the
+    /// chunks of the manifest it replaces
+    fn write_blob(&self, key: Key, value: &[u8], opt: PutOpt, old: Option<Manifest>) -> Result<()> {
+        let chunks = self
+            .chunking
+            .split(value)
+            .into_iter()
+            .map(|chunk| self.acquire_chunk(chunk).map(|key| (key, chunk.len())))
+            .collect::<Result<Vec<_>>>()?;
+        let manifest = Manifest {
+            size: value.len(),
+            chunks,
+        };
+        if let Err(e) = self.index.put(key, &manifest.encode(), opt) {
+            manifest
+                .chunks
+                .iter()
+                .try_for_each(|(chunk_key, _)| self.release_chunk(*chunk_key))?;
+            return Err(e);
+        }
+        old.into_iter()
+            .flat_map(|old| old.chunks)
+            .try_for_each(|(chunk_key, _)| self.release_chunk(chunk_key))
+    }
+
+    /// read the logical range `range` of a blob into `buf`
+    fn read_range(&self, manifest: &Manifest, range: BlobRange, buf: &mut [u8]) -> Result<()> {
+        let mut chunk_start = 0;
+        for (chunk_key, len) in manifest.chunks.iter() {
+            let chunk_range = chunk_start..chunk_start + len;
+            chunk_start += len;
+            let start = std::cmp::max(range.start, chunk_range.start);
+            let end = std::cmp::min(range.end, chunk_range.end);
+            if start >= end {
+                continue;
+            }
+            self.chunks.get(
+                *chunk_key,
+                &mut buf[start - range.start..end - range.start],
+                GetOpt::Range(
+                    CHUNK_HEADER_LEN + start - chunk_range.start
+                        ..CHUNK_HEADER_LEN + end - chunk_range.start,
+                ),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl<S> BlobStore for DedupStore<S>
+where
+    S: BlobStore,
+{
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.index.contains(key)
+    }
+
+    fn meta(&self, key: Key) -> Result<BlobMeta> {
+        let size = self.read_manifest(key)?.size;
+        Ok(BlobMeta {
+            size,
+            ..Default::default()
+        })
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        let _guard = self.lock.lock();
+        match opt {
+            PutOpt::Create => {
+                if self.index.contains(key)? {
+                    return Err(BlobError::AlreadyExists.into());
+                }
+                self.write_blob(key, value, PutOpt::Create, None)
+            }
+            PutOpt::ReplaceOrCreate => {
+                let old = match self.read_manifest(key) {
+                    Ok(old) => Some(old),
+                    Err(Error::Blob(BlobError::NotFound)) => None,
+                    Err(e) => return Err(e),
+                };
+                self.write_blob(key, value, PutOpt::ReplaceOrCreate, old)
+            }
+            PutOpt::Replace(range) => {
+                let old = self.read_manifest(key)?;
+                if !crate::store_impl::helpers::range_contains(&(0..old.size), &range) {
+                    return Err(BlobError::RangeError.into());
+                }
+                if value.len() != range.len() {
+                    return Err(BlobError::RangeError.into());
+                }
+                // re-chunk the patched blob, unchanged chunks are shared with the old version
+                let mut content = vec![0_u8; old.size];
+                self.read_range(&old, 0..old.size, &mut content)?;
+                content[range].copy_from_slice(value);
+                self.write_blob(key, &content, PutOpt::ReplaceOrCreate, Some(old))
+            }
+        }
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        let manifest = self.read_manifest(key)?;
+        let range = match opt {
+            GetOpt::All => 0..manifest.size,
+            GetOpt::Range(range) => range,
+        };
+        if !crate::store_impl::helpers::range_contains(&(0..manifest.size), &range) {
+            return Err(BlobError::RangeError.into());
+        }
+        if buf.len() != range.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        self.read_range(&manifest, range, buf)
+    }
+
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        let _guard = self.lock.lock();
+        let manifest = self.read_manifest(key)?;
+        let content = crate::store_impl::helpers::interest(self, key, &opt)?;
+        self.index.delete(key, DeleteOpt::Discard)?;
+        manifest
+            .chunks
+            .into_iter()
+            .try_for_each(|(chunk_key, _)| self.release_chunk(chunk_key))?;
+        Ok(content)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.index.keys()
+    }
+
+    fn is_shared(&self) -> bool {
+        self.index.is_shared() || self.chunks.is_shared()
+    }
+}
@@ -0,0 +1,358 @@
+use aes_gcm::{
+    aead::{Aead, Payload},
+    Aes256Gcm, KeyInit,
+};
+use anyhow::anyhow;
+use chacha20poly1305::ChaCha20Poly1305;
+use rand::RngCore;
+
+use crate::{
+    error::{BlobError, Error, Result},
+    BlobMeta, BlobRange, BlobStore, DeleteOpt, GetOpt, Key, PutOpt,
+};
+
+pub type EncryptionKey = [u8; 32];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Cipher {
+    Aes256Gcm,
+    ChaCha20Poly1305,
+}
+
+impl Cipher {
+    fn id(&self) -> u8 {
+        match self {
+            Cipher::Aes256Gcm => 1,
+            Cipher::ChaCha20Poly1305 => 2,
+        }
+    }
+}
+
+enum Aead256 {
+    Aes(Box<Aes256Gcm>),
+    ChaCha(Box<ChaCha20Poly1305>),
+}
+
+impl Aead256 {
+    fn encrypt(&self, nonce: &[u8], msg: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let nonce = aes_gcm::Nonce::from_slice(nonce);
+        let payload = Payload { msg, aad };
+        match self {
+            Aead256::Aes(cipher) => cipher.encrypt(nonce, payload),
+            Aead256::ChaCha(cipher) => cipher.encrypt(nonce, payload),
+        }
+        .map_err(|_| Error::Other(anyhow!("chunk too large to encrypt")))
+    }
+
+    fn decrypt(&self, nonce: &[u8], ciphertext: &[u8], aad: &[u8]) -> Result<Vec<u8>> {
+        let nonce = aes_gcm::Nonce::from_slice(nonce);
+        let payload = Payload {
+            msg: ciphertext,
+            aad,
+        };
+        match self {
+            Aead256::Aes(cipher) => cipher.decrypt(nonce, payload),
+            Aead256::ChaCha(cipher) => cipher.decrypt(nonce, payload),
+        }
+        .map_err(|_| Error::from(BlobError::AuthenticationFailed))
+    }
+}
+
+const NONCE_LEN: usize = 12;
+const TAG_LEN: usize = 16;
+
+/// Plain-text header of an encrypted blob: `magic | cipher | chunk size | logical size`.
+///
+/// The header is bound to every chunk through the associated data, so that
+/// tampering with it fails the authentication of the chunks. An empty blob
+/// has a single empty chunk for that purpose.
+#[derive(Debug, Clone, Copy)]
+struct Header {
+    cipher: u8,
+    chunk_size: usize,
+    size: usize,
+}
+
+impl Header {
+    const MAGIC: [u8; 4] = *b"BSE1";
+    const LEN: usize = 20;
+
+    fn decode(buf: &[u8; Self::LEN]) -> Result<Self> {
+        if buf[0..4] != Self::MAGIC {
+            return Err(Error::other(anyhow!("not an encrypted blob")));
+        }
+        let chunk_size = u32::from_le_bytes(buf[8..12].try_into().unwrap()) as usize;
+        if chunk_size == 0 {
+            return Err(Error::other(anyhow!("invalid chunk size")));
+        }
+        Ok(Self {
+            cipher: buf[4],
+            chunk_size,
+            size: u64::from_le_bytes(buf[12..20].try_into().unwrap()) as usize,
+        })
+    }
+
+    fn encode(&self) -> [u8; Self::LEN] {
+        let mut buf = [0_u8; Self::LEN];
+        buf[0..4].copy_from_slice(&Self::MAGIC);
+        buf[4] = self.cipher;
+        buf[8..12].copy_from_slice(&u32::try_from(self.chunk_size).unwrap().to_le_bytes());
+        buf[12..20].copy_from_slice(&u64::try_from(self.size).unwrap().to_le_bytes());
+        buf
+    }
+
+    /// stored size of a full chunk: nonce, cipher text and tag
+    fn stride(&self) -> usize {
+        NONCE_LEN + self.chunk_size + TAG_LEN
+    }
+
+    /// chunks overlapping a non-empty logical range
+    fn chunks_of(&self, range: &BlobRange) -> std::ops::Range<usize> {
+        range.start / self.chunk_size..(range.end - 1) / self.chunk_size + 1
+    }
+
+    /// logical range covered by the chunks `chunks`
+    fn logical_range(&self, chunks: std::ops::Range<usize>) -> BlobRange {
+        chunks.start * self.chunk_size..std::cmp::min(chunks.end * self.chunk_size, self.size)
+    }
+
+    /// physical range of the chunks `chunks` in the inner blob
+    fn stored_range(&self, chunks: std::ops::Range<usize>) -> BlobRange {
+        let logical = self.logical_range(chunks.clone());
+        let start = Self::LEN + chunks.start * self.stride();
+        start..start + logical.len() + chunks.len() * (NONCE_LEN + TAG_LEN)
+    }
+
+    /// associated data of a chunk, binding it to its blob, position and header
+    fn aad(&self, key: &Key, chunk: usize) -> [u8; 8 + 8 + Self::LEN] {
+        let mut aad = [0_u8; 8 + 8 + Self::LEN];
+        aad[0..8].copy_from_slice(key);
+        aad[8..16].copy_from_slice(&u64::try_from(chunk).unwrap().to_le_bytes());
+        aad[16..].copy_from_slice(&self.encode());
+        aad
+    }
+}
+
+/// Encrypt blobs at rest with an AEAD before handing them to the inner store.
+///
+/// Blobs are split into fixed-size chunks, each encrypted with its own random
+/// nonce, so that range reads and `PutOpt::Replace` only decrypt and re-encrypt
+/// the chunks they overlap. A chunk that fails authentication is reported as
+/// `BlobError::AuthenticationFailed` instead of returning its content.
+///
+/// Nonces are random, so a single key should not be used for more than 2^32
+/// chunk writes.
+pub struct EncryptedStore<S>
+where
+    S: BlobStore,
+{
+    store: S,
+    cipher: Cipher,
+    aead: Aead256,
+    chunk_size: usize,
+}
+
+impl<S> EncryptedStore<S>
+where
+    S: BlobStore,
+{
+    pub const DEFAULT_CHUNK_SIZE: usize = 64 * 1024;
+
+    pub fn new(store: S, cipher: Cipher, key: &EncryptionKey) -> Self {
+        Self::with_chunk_size(store, cipher, key, Self::DEFAULT_CHUNK_SIZE)
+    }
+
+    /// # Panic
+    /// - `chunk_size` is zero or doesn't fit in 32 bits.
+    pub fn with_chunk_size(
+        store: S,
+        cipher: Cipher,
+        key: &EncryptionKey,
+        chunk_size: usize,
+    ) -> Self {
+        assert!(chunk_size > 0 && u32::try_from(chunk_size).is_ok());
+        let aead = match cipher {
+            Cipher::Aes256Gcm => Aead256::Aes(Box::new(Aes256Gcm::new(key.into()))),
+            Cipher::ChaCha20Poly1305 => {
+                Aead256::ChaCha(Box::new(ChaCha20Poly1305::new(key.into())))
+            }
+        };
+        Self {
+            store,
+            cipher,
+            aead,
+            chunk_size,
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    fn read_header(&self, key: Key) -> Result<Header> {
+        let mut buf = [0_u8; Header::LEN];
+        self.store
+            .get(key, &mut buf, GetOpt::Range(0..Header::LEN))?;
+        let header = Header::decode(&buf)?;
+        if header.cipher != self.cipher.id() {
+            return Err(Error::other(anyhow!(
+                "blob is encrypted with another cipher"
+            )));
+        }
+        if header.size == 0 {
+            // no chunk covers the content, authenticate the header with the empty one
+            let blob = self.store.get_owned(key, GetOpt::All)?;
+            if blob.len() != Header::LEN + NONCE_LEN + TAG_LEN {
+                return Err(BlobError::AuthenticationFailed.into());
+            }
+            self.open(&key, &header, 0, &blob[Header::LEN..])?;
+        }
+        Ok(header)
+    }
+
+    /// encrypt consecutive chunks starting at chunk `first` into their stored form
+    fn seal(&self, key: &Key, header: &Header, first: usize, plain: &[u8]) -> Result<Vec<u8>> {
+        let mut rng = rand::thread_rng();
+        let mut sealed = Vec::with_capacity(
+            plain.len() + plain.len().div_ceil(header.chunk_size).max(1) * (NONCE_LEN + TAG_LEN),
+        );
+        // an empty blob still gets an empty chunk, which authenticates its header
+        plain
+            .chunks(header.chunk_size)
+            .chain(plain.is_empty().then_some(plain))
+            .enumerate()
+            .try_for_each(|(i, chunk)| -> Result<()> {
+                let mut nonce = [0_u8; NONCE_LEN];
+                rng.fill_bytes(&mut nonce);
+                let aad = header.aad(key, first + i);
+                sealed.extend_from_slice(&nonce);
+                sealed.extend_from_slice(&self.aead.encrypt(&nonce, chunk, &aad)?);
+                Ok(())
+            })?;
+        Ok(sealed)
+    }
+
+    /// decrypt consecutive chunks starting at chunk `first` from their stored form
+    fn open(&self, key: &Key, header: &Header, first: usize, sealed: &[u8]) -> Result<Vec<u8>> {
+        let mut plain = Vec::with_capacity(sealed.len());
+        sealed
+            .chunks(header.stride())
+            .enumerate()
+            .try_for_each(|(i, chunk)| {
+                if chunk.len() < NONCE_LEN + TAG_LEN {
+                    return Err(Error::from(BlobError::AuthenticationFailed));
+                }
+                let (nonce, ciphertext) = chunk.split_at(NONCE_LEN);
+                let aad = header.aad(key, first + i);
+                plain.extend_from_slice(&self.aead.decrypt(nonce, ciphertext, &aad)?);
+                Ok(())
+            })?;
+        Ok(plain)
+    }
+
+    fn replace(&self, key: Key, value: &[u8], range: BlobRange) -> Result<()> {
+        let header = self.read_header(key)?;
+        if !crate::store_impl::helpers::range_contains(&(0..header.size), &range) {
+            return Err(BlobError::RangeError.into());
+        }
+        if value.len() != range.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+        let chunks = header.chunks_of(&range);
+        let stored_range = header.stored_range(chunks.clone());
+        let logical_range = header.logical_range(chunks.clone());
+        let sealed = self
+            .store
+            .get_owned(key, GetOpt::Range(stored_range.clone()))?;
+        let mut plain = self.open(&key, &header, chunks.start, &sealed)?;
+        plain[range.start - logical_range.start..range.end - logical_range.start]
+            .copy_from_slice(value);
+        let sealed = self.seal(&key, &header, chunks.start, &plain)?;
+        self.store.put(key, &sealed, PutOpt::Replace(stored_range))
+    }
+}
+
+impl<S> BlobStore for EncryptedStore<S>
+where
+    S: BlobStore,
+{
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.store.contains(key)
+    }
+
+    fn meta(&self, key: Key) -> Result<BlobMeta> {
+        let size = self.read_header(key)?.size;
+        Ok(BlobMeta {
+            size,
+            ..Default::default()
+        })
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        match opt {
+            PutOpt::Create | PutOpt::ReplaceOrCreate => {
+                let header = Header {
+                    cipher: self.cipher.id(),
+                    chunk_size: self.chunk_size,
+                    size: value.len(),
+                };
+                let mut blob = header.encode().to_vec();
+                blob.extend_from_slice(&self.seal(&key, &header, 0, value)?);
+                self.store.put(key, &blob, opt)
+            }
+            PutOpt::Replace(range) => self.replace(key, value, range),
+        }
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        let header = self.read_header(key)?;
+        let range = match opt {
+            GetOpt::All => 0..header.size,
+            GetOpt::Range(range) => range,
+        };
+        if !crate::store_impl::helpers::range_contains(&(0..header.size), &range) {
+            return Err(BlobError::RangeError.into());
+        }
+        if buf.len() != range.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        if range.is_empty() {
+            return Ok(());
+        }
+        let chunks = header.chunks_of(&range);
+        let logical_range = header.logical_range(chunks.clone());
+        let sealed = self
+            .store
+            .get_owned(key, GetOpt::Range(header.stored_range(chunks.clone())))?;
+        let plain = self.open(&key, &header, chunks.start, &sealed)?;
+        buf.copy_from_slice(
+            &plain[range.start - logical_range.start..range.end - logical_range.start],
+        );
+        Ok(())
+    }
+
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        let content = crate::store_impl::helpers::interest(self, key, &opt)?;
+        self.store.delete(key, DeleteOpt::Discard)?;
+        Ok(content)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.store.keys()
+    }
+
+    fn stats(&self) -> Result<crate::StoreStats> {
+        self.store.stats()
+    }
+
+    fn is_shared(&self) -> bool {
+        self.store.is_shared()
+    }
+}
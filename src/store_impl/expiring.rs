@@ -0,0 +1,305 @@
+use std::{
+    sync::{mpsc, Arc, Weak},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::anyhow;
+use parking_lot::Mutex;
+
+use crate::{
+    error::{BlobError, Error, Result},
+    BlobMeta, BlobStore, DeleteOpt, GetOpt, Key, PutOpt,
+};
+
+/// Header of a blob: `magic | deadline in milliseconds since the epoch`, zero
+/// for a blob that doesn't expire.
+const MAGIC: [u8; 4] = *b"BSX1";
+const HEADER_LEN: usize = 12;
+
+/// Give blobs an optional time to live.
+///
+/// A blob past its deadline is treated as missing: reads report
+/// `BlobError::NotFound` and `PutOpt::Create` replaces it. Expired blobs still
+/// take space until they are deleted by `expire_now`, or by a sweeper started
+/// with `spawn_sweeper`. The writes and the deletes of the expired blobs are
+/// serialized, so a blob rewritten while it is swept is kept.
+pub struct ExpiringStore<S>
+where
+    S: BlobStore,
+{
+    store: S,
+    /// held by the writes, and by the sweep from reading a deadline to
+    /// deleting the blob
+    lock: Mutex<()>,
+}
+
+/// Background thread deleting the expired blobs of a store, stopped on drop.
+pub struct Sweeper {
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<std::thread::JoinHandle<()>>,
+}
+
+impl Drop for Sweeper {
+    fn drop(&mut self) {
+        // closing the channel wakes the thread up
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl<S> ExpiringStore<S>
+where
+    S: BlobStore,
+{
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            lock: Mutex::new(()),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// Put a blob that expires after `ttl`. `PutOpt::Replace` also resets the
+    /// deadline of the blob.
+    pub fn put_with_ttl(&self, key: Key, value: &[u8], opt: PutOpt, ttl: Duration) -> Result<()> {
+        let _guard = self.lock.lock();
+        self.put_until(key, value, opt, Some(SystemTime::now() + ttl))
+    }
+
+    /// Delete the expired blobs, return how many were deleted.
+    pub fn expire_now(&self) -> Result<usize> {
+        let now = SystemTime::now();
+        let mut expired = 0;
+        for key in self.store.keys()? {
+            let _guard = self.lock.lock();
+            match self.read_deadline(key) {
+                Ok(Some(deadline)) if deadline <= now => {
+                    match self.store.delete(key, DeleteOpt::Discard) {
+                        Ok(_) => expired += 1,
+                        // deleted meanwhile
+                        Err(Error::Blob(BlobError::NotFound)) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                Ok(_) | Err(Error::Blob(BlobError::NotFound)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(expired)
+    }
+
+    fn encode_deadline(deadline: Option<SystemTime>) -> [u8; HEADER_LEN] {
+        let millis = deadline.map_or(0, |deadline| {
+            let millis = deadline
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_millis() as u64);
+            // zero means no deadline
+            std::cmp::max(millis, 1)
+        });
+        let mut header = [0_u8; HEADER_LEN];
+        header[0..4].copy_from_slice(&MAGIC);
+        header[4..12].copy_from_slice(&millis.to_le_bytes());
+        header
+    }
+
+    fn content_size(&self, key: Key) -> Result<usize> {
+        self.store
+            .meta(key)?
+            .size
+            .checked_sub(HEADER_LEN)
+            .ok_or_else(|| Error::other(anyhow!("not an expiring blob")))
+    }
+
+    fn read_deadline(&self, key: Key) -> Result<Option<SystemTime>> {
+        let mut header = [0_u8; HEADER_LEN];
+        self.store
+            .get(key, &mut header, GetOpt::Range(0..HEADER_LEN))?;
+        if header[0..4] != MAGIC {
+            return Err(Error::other(anyhow!("not an expiring blob")));
+        }
+        let millis = u64::from_le_bytes(header[4..12].try_into().unwrap());
+        Ok((millis != 0).then(|| UNIX_EPOCH + Duration::from_millis(millis)))
+    }
+
+    /// deadline of a live blob
+    ///
+    /// # Error
+    /// - Blob(BlobError::NotFound): the blob doesn't exist or is expired.
+    fn live_deadline(&self, key: Key) -> Result<Option<SystemTime>> {
+        match self.read_deadline(key)? {
+            Some(deadline) if deadline <= SystemTime::now() => Err(BlobError::NotFound.into()),
+            deadline => Ok(deadline),
+        }
+    }
+
+    /// called with the lock held
+    fn put_until(
+        &self,
+        key: Key,
+        value: &[u8],
+        opt: PutOpt,
+        deadline: Option<SystemTime>,
+    ) -> Result<()> {
+        let header = Self::encode_deadline(deadline);
+        match opt {
+            PutOpt::Create | PutOpt::ReplaceOrCreate => {
+                let mut blob = Vec::with_capacity(HEADER_LEN + value.len());
+                blob.extend_from_slice(&header);
+                blob.extend_from_slice(value);
+                match self.store.put(key, &blob, opt) {
+                    // an expired blob doesn't exist anymore, replace it
+                    Err(Error::Blob(BlobError::AlreadyExists)) => match self.live_deadline(key) {
+                        Ok(_) => Err(BlobError::AlreadyExists.into()),
+                        Err(Error::Blob(BlobError::NotFound)) => {
+                            self.store.put(key, &blob, PutOpt::ReplaceOrCreate)
+                        }
+                        Err(e) => Err(e),
+                    },
+                    result => result,
+                }
+            }
+            PutOpt::Replace(range) => {
+                let current = self.live_deadline(key)?;
+                let size = self.content_size(key)?;
+                if !crate::store_impl::helpers::range_contains(&(0..size), &range) {
+                    return Err(BlobError::RangeError.into());
+                }
+                if value.len() != range.len() {
+                    return Err(BlobError::RangeError.into());
+                }
+                self.store.put(
+                    key,
+                    value,
+                    PutOpt::Replace(HEADER_LEN + range.start..HEADER_LEN + range.end),
+                )?;
+                if deadline != current {
+                    self.store
+                        .put(key, &header, PutOpt::Replace(0..HEADER_LEN))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl<S> ExpiringStore<S>
+where
+    S: BlobStore + Send + Sync + 'static,
+{
+    /// Start a thread deleting the expired blobs every `interval`. The thread
+    /// stops when the sweeper is dropped or the store is dropped.
+    pub fn spawn_sweeper(self: &Arc<Self>, interval: Duration) -> Sweeper {
+        let store: Weak<Self> = Arc::downgrade(self);
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = std::thread::spawn(move || {
+            while let Err(mpsc::RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                let Some(store) = store.upgrade() else {
+                    break;
+                };
+                // a failed sweep is retried at the next tick
+                let _ = store.expire_now();
+            }
+        });
+        Sweeper {
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+}
+
+impl<S> BlobStore for ExpiringStore<S>
+where
+    S: BlobStore,
+{
+    fn contains(&self, key: Key) -> Result<bool> {
+        match self.live_deadline(key) {
+            Ok(_) => Ok(true),
+            Err(Error::Blob(BlobError::NotFound)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn meta(&self, key: Key) -> Result<BlobMeta> {
+        let expires_at = self.live_deadline(key)?;
+        let size = self.content_size(key)?;
+        Ok(BlobMeta {
+            size,
+            expires_at,
+            ..Default::default()
+        })
+    }
+
+    /// Put a blob that doesn't expire, `PutOpt::Replace` keeps the deadline of the blob.
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        let _guard = self.lock.lock();
+        match opt {
+            PutOpt::Replace(_) => {
+                let deadline = self.live_deadline(key)?;
+                self.put_until(key, value, opt, deadline)
+            }
+            opt => self.put_until(key, value, opt, None),
+        }
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        self.live_deadline(key)?;
+        let size = self.content_size(key)?;
+        let range = match opt {
+            GetOpt::All => 0..size,
+            GetOpt::Range(range) => range,
+        };
+        if !crate::store_impl::helpers::range_contains(&(0..size), &range) {
+            return Err(BlobError::RangeError.into());
+        }
+        if buf.len() != range.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        self.store.get(
+            key,
+            buf,
+            GetOpt::Range(HEADER_LEN + range.start..HEADER_LEN + range.end),
+        )
+    }
+
+    /// # Error
+    /// - Blob(BlobError::NotFound): the blob doesn't exist, an expired blob is
+    ///   deleted but reported as not found.
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        let _guard = self.lock.lock();
+        let live = self.live_deadline(key);
+        match live {
+            Err(Error::Blob(BlobError::NotFound)) if self.store.contains(key)? => {
+                self.store.delete(key, DeleteOpt::Discard)?;
+                Err(BlobError::NotFound.into())
+            }
+            Err(e) => Err(e),
+            Ok(_) => {
+                let content = crate::store_impl::helpers::interest(self, key, &opt)?;
+                self.store.delete(key, DeleteOpt::Discard)?;
+                Ok(content)
+            }
+        }
+    }
+
+    /// Keys of the blobs, including the expired blobs not deleted yet.
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.store.keys()
+    }
+
+    fn stats(&self) -> Result<crate::StoreStats> {
+        self.store.stats()
+    }
+
+    fn is_shared(&self) -> bool {
+        self.store.is_shared()
+    }
+}
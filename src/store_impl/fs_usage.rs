@@ -0,0 +1,245 @@
+use std::{
+    collections::HashMap,
+    io::Write,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use parking_lot::Mutex;
+
+use crate::{
+    error::{Error, Result},
+    StoreStats,
+};
+
+/// Usage of a store kept as a tree of files, see `BlobStore::stats`.
+///
+/// The handle that owns the store keeps the stats of the store and of each of
+/// its buckets, and shares them with the bucket handles. Every write reads the
+/// size and the allocation of the files it touches before and after it, and
+/// adds the difference to the stats of its root and of the roots above.
+///
+/// The stats are saved to `root/usage.stats` when the last handle is dropped,
+/// and the file is removed when the store is opened again: after a crash the
+/// files are counted instead, those of the store when it is opened and those
+/// of a bucket when it is first opened. The names with a dot, the
+/// transactions and the snapshots are not blobs and are not counted.
+pub(crate) struct FsUsage {
+    shared: Arc<Shared>,
+    /// stats of the root of this handle, then of the roots it is a bucket of
+    chain: Vec<Arc<Mutex<StoreStats>>>,
+    /// root of this handle relative to the root of the store
+    rel: PathBuf,
+}
+
+struct Shared {
+    root: PathBuf,
+    /// stats of the store and of its opened buckets, by their relative root
+    stats: Mutex<HashMap<PathBuf, Arc<Mutex<StoreStats>>>>,
+    /// serialize the writes to a file, by a hash of its path
+    stripes: Vec<Mutex<()>>,
+}
+
+impl FsUsage {
+    const FILE: &'static str = "usage.stats";
+    const STRIPES: usize = 64;
+    /// directories of a root that don't hold blobs
+    const SKIPPED: [&'static str; 2] = ["txn", "snapshots"];
+
+    /// Load the stats saved by the last handle on the store rooted at `root`,
+    /// or count them.
+    pub(crate) fn open(root: &Path) -> Result<Self> {
+        let path = root.join(Self::FILE);
+        let saved: HashMap<PathBuf, StoreStats> = match std::fs::File::open(&path) {
+            Ok(file) => {
+                let saved = bincode::deserialize_from(std::io::BufReader::new(file))
+                    .map_err(anyhow::Error::new)?;
+                // the stats go stale with the first write
+                std::fs::remove_file(&path)?;
+                std::fs::File::open(root)?.sync_all()?;
+                saved
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let shared = Arc::new(Shared {
+            root: root.to_path_buf(),
+            stats: Mutex::new(
+                saved
+                    .into_iter()
+                    .map(|(rel, stats)| (rel, Arc::new(Mutex::new(stats))))
+                    .collect(),
+            ),
+            stripes: (0..Self::STRIPES).map(|_| Mutex::default()).collect(),
+        });
+        Self::attach(shared, PathBuf::new(), Vec::new())
+    }
+
+    /// the usage of the bucket rooted at `path`, under the root of this handle
+    pub(crate) fn bucket(&self, path: &Path) -> Result<Self> {
+        let rel = path
+            .strip_prefix(&self.shared.root)
+            .map_err(|e| Error::other(anyhow::Error::new(e)))?
+            .to_path_buf();
+        Self::attach(self.shared.clone(), rel, self.chain.clone())
+    }
+
+    fn attach(
+        shared: Arc<Shared>,
+        rel: PathBuf,
+        mut chain: Vec<Arc<Mutex<StoreStats>>>,
+    ) -> Result<Self> {
+        let stats = shared.get_or_count(&rel)?;
+        chain.insert(0, stats);
+        Ok(Self { shared, chain, rel })
+    }
+
+    pub(crate) fn stats(&self) -> StoreStats {
+        *self.chain[0].lock()
+    }
+
+    /// Run a write to the files at `paths` and account for the change of
+    /// their usage, whether the write succeeded or not. Without usage, only
+    /// run the write.
+    pub(crate) fn track<T>(
+        usage: Option<&Self>,
+        paths: &[&Path],
+        write: impl FnOnce() -> Result<T>,
+    ) -> Result<T> {
+        let Some(usage) = usage else {
+            return write();
+        };
+        let mut stripes = paths
+            .iter()
+            .map(|path| {
+                xxhash_rust::xxh3::xxh3_64(path.as_os_str().as_encoded_bytes()) as usize
+                    % Self::STRIPES
+            })
+            .collect::<Vec<_>>();
+        stripes.sort_unstable();
+        stripes.dedup();
+        let _guards = stripes
+            .into_iter()
+            .map(|stripe| usage.shared.stripes[stripe].lock())
+            .collect::<Vec<_>>();
+        let before = Self::usage_of(paths);
+        let result = write();
+        let after = Self::usage_of(paths);
+        for stats in &usage.chain {
+            let mut stats = stats.lock();
+            stats.sub(&before);
+            stats.add(&after);
+        }
+        result
+    }
+
+    /// Remove the bucket rooted at `path` with `drop`, and its usage.
+    pub(crate) fn drop_bucket(&self, path: &Path, drop: impl FnOnce() -> Result<()>) -> Result<()> {
+        let bucket = self.bucket(path)?;
+        let stats = bucket.stats();
+        drop()?;
+        for parent in &self.chain {
+            parent.lock().sub(&stats);
+        }
+        self.shared
+            .stats
+            .lock()
+            .retain(|rel, _| !rel.starts_with(&bucket.rel));
+        Ok(())
+    }
+
+    fn usage_of(paths: &[&Path]) -> StoreStats {
+        let mut usage = StoreStats::default();
+        for path in paths {
+            if let Ok(meta) = std::fs::symlink_metadata(path) {
+                usage.add(&Self::file_usage(&meta));
+            }
+        }
+        usage
+    }
+
+    fn file_usage(meta: &std::fs::Metadata) -> StoreStats {
+        use std::os::unix::fs::MetadataExt;
+        if !meta.is_file() {
+            return StoreStats::default();
+        }
+        StoreStats {
+            blobs: 1,
+            logical_bytes: meta.len(),
+            // st_blocks counts 512-byte units whatever the block size
+            physical_bytes: meta.blocks() * 512,
+        }
+    }
+
+    /// count the blobs of the store rooted at `root`, with those of its buckets
+    fn count(root: &Path) -> Result<StoreStats> {
+        let mut stats = StoreStats::default();
+        for entry in std::fs::read_dir(root)? {
+            let entry = entry?;
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.contains('.') || Self::SKIPPED.contains(&name.as_ref()) {
+                continue;
+            }
+            if entry.path() == crate::store_impl::helpers::bucket_root(root) {
+                for bucket in crate::store_impl::helpers::list_bucket_dirs(root)? {
+                    stats.add(&Self::count(&crate::store_impl::helpers::bucket_dir(
+                        root, &bucket,
+                    )?)?);
+                }
+            } else {
+                Self::count_dir(&entry.path(), &mut stats)?;
+            }
+        }
+        Ok(stats)
+    }
+
+    fn count_dir(path: &Path, stats: &mut StoreStats) -> Result<()> {
+        let meta = std::fs::symlink_metadata(path)?;
+        if !meta.is_dir() {
+            stats.add(&Self::file_usage(&meta));
+            return Ok(());
+        }
+        for entry in std::fs::read_dir(path)? {
+            let entry = entry?;
+            if !entry.file_name().to_string_lossy().contains('.') {
+                Self::count_dir(&entry.path(), stats)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Shared {
+    fn get_or_count(&self, rel: &Path) -> Result<Arc<Mutex<StoreStats>>> {
+        let mut stats = self.stats.lock();
+        if let Some(stats) = stats.get(rel) {
+            return Ok(stats.clone());
+        }
+        let counted = Arc::new(Mutex::new(FsUsage::count(&self.root.join(rel))?));
+        stats.insert(rel.to_path_buf(), counted.clone());
+        Ok(counted)
+    }
+
+    fn save(&self) -> Result<()> {
+        let stats = self
+            .stats
+            .lock()
+            .iter()
+            .map(|(rel, stats)| (rel.clone(), *stats.lock()))
+            .collect::<HashMap<_, _>>();
+        let path = self.root.join(FsUsage::FILE);
+        let tmp = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&bincode::serialize(&stats).map_err(anyhow::Error::new)?)?;
+        file.sync_all()?;
+        std::fs::rename(tmp, path).map_err(Error::from)
+    }
+}
+
+impl Drop for Shared {
+    /// a store without the file is counted again when it is opened
+    fn drop(&mut self) {
+        let _ = self.save();
+    }
+}
@@ -1,73 +1,463 @@
 use std::{
-    io::prelude::{Read, Seek, Write},
-    path::PathBuf,
+    collections::{BTreeMap, BTreeSet, HashMap},
+    io::prelude::Write,
+    num::NonZeroUsize,
+    os::unix::fs::FileExt,
+    path::{Path, PathBuf},
+    sync::Arc,
 };
 
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+
 use crate::{
     error::{Error, Result},
-    BlobStore, DeleteOpt, GetOpt, Key, PutOpt,
+    store_impl::{
+        config::{Durability, Layout, OpenMode},
+        fs_usage::FsUsage,
+        helpers::{StoreLock, VarKey},
+    },
+    BatchBlobStore, BlobRange, BlobStore, BucketStore, CopyOpt, DeleteOpt, GetOpt, Key, PutOpt,
+    SnapshotBlobStore, Transaction, TransactionalBlobStore, VarKeyBlobStore,
 };
 
 pub struct LocalFileSystemBlobStore {
     root: PathBuf,
+    max_key_len: usize,
+    /// serialize the commits of transactions
+    commit_lock: Arc<Mutex<()>>,
+    /// held shared by writes and exclusively while taking a snapshot,
+    /// committing a transaction or copying a blob
+    snapshot_lock: Arc<RwLock<()>>,
+    /// the locks above of the buckets under the store, by their root, so that
+    /// every handle on a bucket shares them
+    bucket_locks: Arc<Mutex<HashMap<PathBuf, RootLocks>>>,
+    /// open files of the hot blobs, see `with_fd_cache`
+    fd_cache: Option<Mutex<lru::LruCache<Key, Arc<OpenBlob>>>>,
+    /// bypass the page cache, see `with_direct_io`
+    direct_io: bool,
+    /// engine of the batches, see `with_io_uring`
+    #[cfg(feature = "io_uring")]
+    ring: Option<Mutex<crate::store_impl::uring::Ring>>,
+    mode: OpenMode,
+    layout: Layout,
+    durability: Durability,
+    /// usage of the blobs, kept by the exclusive handles and their buckets
+    usage: Option<FsUsage>,
+    /// lock on the root, `None` for the buckets and snapshots of a store
+    _lock: Option<StoreLock>,
+}
+
+/// `commit_lock` and `snapshot_lock` of a root
+type RootLocks = (Arc<Mutex<()>>, Arc<RwLock<()>>);
+
+/// Configuration of a `LocalFileSystemBlobStore`.
+///
+/// It is built in code or deserialized, e.g. from a TOML or JSON file, where
+/// the missing fields take their default. The default configuration opens the
+/// store as `LocalFileSystemBlobStore::connect` does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocalFileSystemConfig {
+    /// Create the root directory if it doesn't exist.
+    pub create_if_missing: bool,
+    /// `Shared` is `LocalFileSystemBlobStore::connect_shared`.
+    pub mode: OpenMode,
+    pub layout: Layout,
+    /// The writes through `Transaction::commit` are always flushed.
+    pub durability: Durability,
+    /// Capacity of the descriptor cache, see `LocalFileSystemBlobStore::with_fd_cache`.
+    /// It must be 0 in `Shared` mode.
+    pub fd_cache: usize,
+    /// See `LocalFileSystemBlobStore::with_direct_io`.
+    pub direct_io: bool,
+    pub max_key_len: usize,
+}
+
+impl Default for LocalFileSystemConfig {
+    fn default() -> Self {
+        Self {
+            create_if_missing: false,
+            mode: OpenMode::default(),
+            layout: Layout::default(),
+            durability: Durability::default(),
+            fd_cache: 0,
+            direct_io: false,
+            max_key_len: crate::DEFAULT_MAX_KEY_LEN,
+        }
+    }
+}
+
+impl LocalFileSystemConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: OpenMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub fn with_fd_cache(mut self, capacity: usize) -> Self {
+        self.fd_cache = capacity;
+        self
+    }
+
+    pub fn with_direct_io(mut self, direct_io: bool) -> Self {
+        self.direct_io = direct_io;
+        self
+    }
+
+    pub fn with_max_key_len(mut self, max_key_len: usize) -> Self {
+        self.max_key_len = max_key_len;
+        self
+    }
+
+    /// Open the store rooted at `root`.
+    ///
+    /// # Error
+    /// - Locked: another handle has the store open in a conflicting mode.
+    /// - Other: the mode is `Shared` with a descriptor cache.
+    pub fn open(&self, root: impl Into<PathBuf>) -> Result<LocalFileSystemBlobStore> {
+        // the cache would serve files replaced by the other handles
+        if self.mode == OpenMode::Shared && self.fd_cache > 0 {
+            return Err(Error::Other(anyhow::anyhow!(
+                "the descriptor cache is not supported in shared mode"
+            )));
+        }
+        let root = root.into();
+        if self.create_if_missing {
+            std::fs::create_dir_all(&root)?;
+        }
+        let mut store = LocalFileSystemBlobStore::open(root, self.mode, self.layout, true)?
+            .with_max_key_len(self.max_key_len)
+            .with_fd_cache(self.fd_cache)
+            .with_direct_io(self.direct_io);
+        store.durability = self.durability;
+        Ok(store)
+    }
+}
+
+/// A blob file kept open by the descriptor cache.
+struct OpenBlob {
+    file: std::fs::File,
+    size: usize,
+    /// hard linked by a copy or a snapshot, only written through its path
+    shared: bool,
 }
 
 impl LocalFileSystemBlobStore {
+    /// Open the store as its only handle.
+    ///
+    /// # Error
+    /// - Locked: another handle has the store open.
     pub fn connect(root: impl Into<PathBuf>) -> Result<Self> {
-        let root = root.into();
+        LocalFileSystemConfig::default().open(root)
+    }
+
+    /// Open the store for reading only: the files are opened with `O_RDONLY`,
+    /// writes fail with `Error::ReadOnly`, and the transactions left by a
+    /// crash are not recovered. Read-only and shared handles can have the
+    /// store open at the same time.
+    ///
+    /// # Error
+    /// - Locked: a handle opened with `connect` has the store open.
+    pub fn connect_read_only(root: impl Into<PathBuf>) -> Result<Self> {
+        LocalFileSystemConfig::default()
+            .with_mode(OpenMode::ReadOnly)
+            .open(root)
+    }
+
+    /// Open the store for reading and writing along with other shared and
+    /// read-only handles, in this process or others.
+    ///
+    /// Each write replaces or removes a file at once, or writes a range of a
+    /// file in place, so the handles see each other's writes. Transactions and
+    /// snapshots need the store to themselves and fail with `Error::Other`,
+    /// and the transactions left by a crash are only recovered by `connect`.
+    /// Don't use `with_fd_cache` on a shared handle, the cache would miss the
+    /// writes of the other handles.
+    ///
+    /// # Error
+    /// - Locked: a handle opened with `connect` has the store open.
+    pub fn connect_shared(root: impl Into<PathBuf>) -> Result<Self> {
+        LocalFileSystemConfig::default()
+            .with_mode(OpenMode::Shared)
+            .open(root)
+    }
+
+    fn open(root: PathBuf, mode: OpenMode, layout: Layout, lock: bool) -> Result<Self> {
         if !root.exists() {
             return Err(Error::Io(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
                 "dev path not found",
             )));
         }
-        Ok(Self { root })
+        let owner = lock;
+        let lock = if lock {
+            Some(StoreLock::acquire(&root, mode == OpenMode::Exclusive)?)
+        } else {
+            None
+        };
+        let mut store = Self {
+            root,
+            max_key_len: crate::DEFAULT_MAX_KEY_LEN,
+            commit_lock: Arc::default(),
+            snapshot_lock: Arc::default(),
+            bucket_locks: Arc::default(),
+            fd_cache: None,
+            direct_io: false,
+            #[cfg(feature = "io_uring")]
+            ring: None,
+            mode,
+            layout,
+            durability: Durability::None,
+            usage: None,
+            _lock: lock,
+        };
+        // the transactions and the snapshots left by a crash, no other handle
+        // can hold one
+        if mode == OpenMode::Exclusive && owner {
+            store.usage = Some(FsUsage::open(&store.root)?);
+            store.recover()?;
+            Self::remove_snapshots(&store.root)?;
+        }
+        Ok(store)
     }
 
-    fn key_to_path(&self, key: &Key) -> PathBuf {
-        use itertools::Itertools;
-        let mut path = self.root.clone();
-        let key_hex = hex::encode(key);
-        const DELIM: usize = 2;
-        key_hex
-            .chars()
-            .chunks(key_hex.len() / DELIM)
-            .into_iter()
-            .for_each(|chunk| {
-                let chunk: String = chunk.collect();
-                path.push(&chunk);
-            });
-        path
+    /// remove the snapshots of the store and of its buckets
+    fn remove_snapshots(root: &Path) -> Result<()> {
+        match std::fs::remove_dir_all(root.join(Self::SNAPSHOT_DIR)) {
+            Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+            _ => {}
+        }
+        for name in crate::store_impl::helpers::list_bucket_dirs(root)? {
+            Self::remove_snapshots(&crate::store_impl::helpers::bucket_dir(root, &name)?)?;
+        }
+        Ok(())
     }
-}
 
-impl BlobStore for LocalFileSystemBlobStore {
-    fn contains(&self, key: Key) -> Result<bool> {
-        let path = self.key_to_path(&key);
-        path.try_exists().map_err(Error::from)
+    fn writable(&self) -> Result<()> {
+        if self.mode == OpenMode::ReadOnly {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
     }
 
-    fn meta(&self, key: Key) -> Result<crate::BlobMeta> {
-        let path = self.key_to_path(&key);
-        let size = path
-            .metadata()
+    /// transactions and snapshots are only consistent within one handle
+    fn exclusive(&self) -> Result<()> {
+        self.writable()?;
+        if self.mode == OpenMode::Shared {
+            return Err(Error::other(anyhow::anyhow!(
+                "transactions and snapshots need exclusive access to the store"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Use variable-length keys of up to `max_key_len` bytes.
+    pub fn with_max_key_len(mut self, max_key_len: usize) -> Self {
+        self.max_key_len = max_key_len;
+        self
+    }
+
+    /// Keep the files of up to `capacity` blobs open, so that repeated
+    /// accesses to them skip the open and the `stat` of the file. A capacity of
+    /// 0 disables the cache.
+    ///
+    /// The cache is kept coherent with the writes made through this handle
+    /// only: use it when no other handle, bucket handle or process writes the
+    /// blobs of this store.
+    pub fn with_fd_cache(mut self, capacity: usize) -> Self {
+        self.fd_cache = NonZeroUsize::new(capacity).map(|cap| Mutex::new(lru::LruCache::new(cap)));
+        self
+    }
+
+    /// Read and write the blobs with `O_DIRECT`, bypassing the page cache.
+    /// Ranges that are not aligned to 4 KiB, and the tails of the blobs, go
+    /// through aligned bounce buffers: an unaligned write reads back the
+    /// blocks it partially covers. The file system must support `O_DIRECT`,
+    /// otherwise the accesses fail with `Error::Io`.
+    pub fn with_direct_io(mut self, direct_io: bool) -> Self {
+        self.direct_io = direct_io;
+        self
+    }
+
+    /// Serve `BatchBlobStore::get_many` and `put_many` with an io_uring of
+    /// `entries` entries: the opens, reads, writes and fsyncs of the blobs of
+    /// a batch are submitted together, a stage at a time. The batches of the
+    /// handle go through the ring one after the other. Writes other than
+    /// `PutOpt::Create` and `PutOpt::ReplaceOrCreate`, and direct I/O, are
+    /// not served by the ring.
+    ///
+    /// # Error
+    /// - Io: the kernel doesn't support io_uring or `entries` is invalid.
+    #[cfg(feature = "io_uring")]
+    pub fn with_io_uring(mut self, entries: u32) -> Result<Self> {
+        self.ring = Some(Mutex::new(crate::store_impl::uring::Ring::new(entries)?));
+        Ok(self)
+    }
+
+    /// the open file of a blob if it is in the cache
+    fn cached(&self, key: &Key) -> Option<Arc<OpenBlob>> {
+        self.fd_cache.as_ref()?.lock().get(key).cloned()
+    }
+
+    /// the open file of a blob, opened and cached on a miss
+    fn open_cached(&self, key: &Key) -> Result<Option<Arc<OpenBlob>>> {
+        use std::os::unix::fs::MetadataExt;
+        let Some(cache) = &self.fd_cache else {
+            return Ok(None);
+        };
+        // held while opening, so that a file can't be cached after the
+        // invalidation following its removal
+        let mut cache = cache.lock();
+        if let Some(blob) = cache.get(key) {
+            return Ok(Some(blob.clone()));
+        }
+        let file = Self::open_options(self.direct_io)
+            .write(self.mode != OpenMode::ReadOnly)
+            .open(self.key_to_path(key))
             .map_err(|e| {
                 if e.kind() == std::io::ErrorKind::NotFound {
                     Error::from(crate::error::BlobError::NotFound)
                 } else {
                     Error::from(e)
                 }
-            })?
-            .len()
-            .try_into()
-            .unwrap();
-        Ok(crate::BlobMeta { size })
+            })?;
+        let meta = file.metadata()?;
+        let blob = Arc::new(OpenBlob {
+            file,
+            size: meta.len().try_into().unwrap(),
+            shared: meta.nlink() > 1,
+        });
+        cache.put(*key, blob.clone());
+        Ok(Some(blob))
     }
 
-    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
-        let path = self.key_to_path(&key);
-        let mut open_opt = std::fs::OpenOptions::new();
-        open_opt.read(true).write(true);
+    /// drop the cached files of blobs whose file was replaced, resized,
+    /// linked or removed
+    fn invalidate(&self, keys: &[Key]) {
+        if let Some(cache) = &self.fd_cache {
+            let mut cache = cache.lock();
+            keys.iter().for_each(|key| {
+                cache.pop(key);
+            });
+        }
+    }
+
+    fn key_to_path(&self, key: &Key) -> PathBuf {
+        crate::store_impl::helpers::key_path(&self.root, key, self.layout)
+    }
+
+    /// flush a write in place through the descriptor cache
+    fn flush_cached(&self, blob: &OpenBlob) -> Result<()> {
+        if self.durability >= Durability::Data {
+            blob.file.sync_data()?;
+        }
+        Ok(())
+    }
+
+    /// flush a write to the file at `path` as `self.durability` asks: its
+    /// content if `content`, and the directories up to the root if an entry
+    /// was created, renamed or removed
+    fn flush(&self, path: &Path, content: bool, entry: bool) -> Result<()> {
+        Self::flush_path(&self.root, path, self.durability, content, entry)
+    }
+
+    pub(crate) fn flush_path(
+        root: &Path,
+        path: &Path,
+        durability: Durability,
+        content: bool,
+        entry: bool,
+    ) -> Result<()> {
+        if content && durability >= Durability::Data {
+            std::fs::File::open(path)?.sync_data()?;
+        }
+        if entry && durability == Durability::Full {
+            for dir in path
+                .ancestors()
+                .skip(1)
+                .take_while(|dir| dir.starts_with(root))
+            {
+                std::fs::File::open(dir)?.sync_all()?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// File operations on the blob at a path, shared by the fixed and variable-length keys.
+impl LocalFileSystemBlobStore {
+    /// hard link `dst` to `src`, fail if `dst` exists
+    fn link_at(src: &Path, dst: &Path) -> Result<()> {
+        std::fs::create_dir_all(dst.parent().unwrap())?;
+        std::fs::hard_link(src, dst).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => {
+                Error::from(crate::error::BlobError::AlreadyExists)
+            }
+            std::io::ErrorKind::NotFound => Error::from(crate::error::BlobError::NotFound),
+            _ => Error::from(e),
+        })
+    }
+
+    /// Give the file its own copy of its content if it is shared with a
+    /// snapshot, before it is written in place.
+    fn unshare(path: &Path) -> Result<()> {
+        use std::os::unix::fs::MetadataExt;
+        match path.metadata() {
+            Ok(meta) if meta.nlink() > 1 => {
+                let copy = path.with_extension(format!("cow{:08x}", rand::random::<u32>()));
+                std::fs::copy(path, &copy)?;
+                std::fs::rename(copy, path).map_err(Error::from)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn meta_at(path: &Path) -> Result<crate::BlobMeta> {
+        let meta = path.metadata().map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                Error::from(crate::error::BlobError::NotFound)
+            } else {
+                Error::from(e)
+            }
+        })?;
+        Ok(Self::blob_meta(&meta))
+    }
+
+    fn blob_meta(meta: &std::fs::Metadata) -> crate::BlobMeta {
+        use std::os::unix::fs::MetadataExt;
+        crate::BlobMeta {
+            size: meta.len().try_into().unwrap(),
+            // st_blocks counts 512-byte units whatever the block size
+            allocated: Some((meta.blocks() * 512).try_into().unwrap()),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn put_file(path: &Path, value: &[u8], opt: PutOpt, direct: bool) -> Result<()> {
+        if !matches!(opt, PutOpt::Create) {
+            Self::unshare(path)?;
+        }
+        let mut open_opt = Self::open_options(direct);
+        open_opt.write(true);
         match opt {
             PutOpt::Create => {
                 std::fs::create_dir_all(path.parent().unwrap())?;
@@ -79,38 +469,76 @@ impl BlobStore for LocalFileSystemBlobStore {
                 open_opt.create(true)
             }
         };
-        let mut file = open_opt.open(path).map_err(|e| match e.kind() {
+        let file = open_opt.open(path).map_err(|e| match e.kind() {
             std::io::ErrorKind::AlreadyExists => {
                 Error::from(crate::error::BlobError::AlreadyExists)
             }
             std::io::ErrorKind::NotFound => Error::from(crate::error::BlobError::NotFound),
             _ => Error::from(e),
         })?;
-        match opt {
-            PutOpt::Create => file.set_len(value.len().try_into().unwrap())?,
-            PutOpt::Replace(range) => {
-                // check range validity
-                let valid_range = 0..usize::try_from(file.metadata()?.len()).unwrap();
-                if !crate::store_impl::helpers::range_contains(&valid_range, &range) {
-                    return Err(Error::from(crate::error::BlobError::RangeError));
-                }
-                if range.len() != value.len() {
-                    return Err(Error::from(crate::error::BlobError::RangeError));
-                }
-                file.seek(std::io::SeekFrom::Start(range.start.try_into().unwrap()))?;
+        let (size, offset) = match opt {
+            PutOpt::Create | PutOpt::ReplaceOrCreate => {
+                // the old content is not read back by a direct write
+                file.set_len(if direct { 0 } else { value.len() as u64 })?;
+                (value.len(), 0)
             }
-            PutOpt::ReplaceOrCreate => {
-                file.set_len(value.len().try_into().unwrap())?;
-                file.seek(std::io::SeekFrom::Start(0))?;
+            PutOpt::Replace(range) => {
+                let file_size = usize::try_from(file.metadata()?.len()).unwrap();
+                Self::check_range(file_size, &range, value.len())?;
+                (file_size, range.start)
             }
+        };
+        Self::write_file_at(&file, value, offset, size, direct).map_err(Error::from)
+    }
+
+    /// check that `range` lies in a blob of `size` bytes and is `len` bytes long
+    fn check_range(size: usize, range: &BlobRange, len: usize) -> Result<()> {
+        if !crate::store_impl::helpers::range_contains(&(0..size), range) || range.len() != len {
+            return Err(Error::from(crate::error::BlobError::RangeError));
         }
-        file.write_all(value).map_err(Error::from)
+        Ok(())
     }
 
-    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
-        let path = self.key_to_path(&key);
-        let mut file = std::fs::OpenOptions::new()
-            .read(true)
+    /// the range of a blob of `size` bytes read by `opt` into a buffer of `len` bytes
+    fn read_range(size: usize, opt: GetOpt, len: usize) -> Result<BlobRange> {
+        let range = match opt {
+            GetOpt::All => 0..size,
+            GetOpt::Range(range) => range,
+        };
+        Self::check_range(size, &range, len)?;
+        Ok(range)
+    }
+
+    pub(crate) fn get_file(path: &Path, buf: &mut [u8], opt: GetOpt, direct: bool) -> Result<()> {
+        let file = Self::open_read(path, direct)?;
+        let file_size: usize = file.metadata()?.len().try_into().unwrap();
+        let range = Self::read_range(file_size, opt, buf.len())?;
+        Self::read_file_at(&file, buf, range.start, direct).map_err(Error::from)
+    }
+
+    /// one open of the file, then a positioned read per range
+    fn get_vectored_file(
+        path: &Path,
+        bufs: &mut [(BlobRange, &mut [u8])],
+        direct: bool,
+    ) -> Result<()> {
+        let file = Self::open_read(path, direct)?;
+        let file_size: usize = file.metadata()?.len().try_into().unwrap();
+        crate::check_ranges(
+            file_size,
+            bufs.iter().map(|(range, buf)| (range, buf.len())),
+        )?;
+        for (range, buf) in bufs.iter_mut() {
+            Self::read_file_at(&file, buf, range.start, direct)?;
+        }
+        Ok(())
+    }
+
+    /// one open of the file, then a positioned write per range
+    fn put_vectored_file(path: &Path, bufs: &[(BlobRange, &[u8])], direct: bool) -> Result<()> {
+        Self::unshare(path)?;
+        let file = Self::open_options(direct)
+            .write(true)
             .open(path)
             .map_err(|e| {
                 if e.kind() == std::io::ErrorKind::NotFound {
@@ -119,35 +547,244 @@ impl BlobStore for LocalFileSystemBlobStore {
                     Error::from(e)
                 }
             })?;
-        match opt {
-            GetOpt::All => {
-                let file_size: usize = file.metadata()?.len().try_into().unwrap();
-                if file_size != buf.len() {
-                    return Err(Error::from(crate::error::BlobError::RangeError));
-                }
-                file.seek(std::io::SeekFrom::Start(0))?;
-            }
-            GetOpt::Range(range) => {
-                let file_size: usize = file.metadata()?.len().try_into().unwrap();
-                let valid_range = 0..file_size;
-                if !crate::store_impl::helpers::range_contains(&valid_range, &range) {
-                    return Err(Error::Blob(crate::error::BlobError::RangeError));
+        let file_size: usize = file.metadata()?.len().try_into().unwrap();
+        crate::check_ranges(
+            file_size,
+            bufs.iter().map(|(range, buf)| (range, buf.len())),
+        )?;
+        for (range, buf) in bufs {
+            Self::write_file_at(&file, buf, range.start, file_size, direct)?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn create_sized_at(path: &Path, len: usize) -> Result<()> {
+        std::fs::create_dir_all(path.parent().unwrap())?;
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(path)
+            .map_err(|e| {
+                if e.kind() == std::io::ErrorKind::AlreadyExists {
+                    Error::from(crate::error::BlobError::AlreadyExists)
+                } else {
+                    Error::from(e)
                 }
-                let len = range.end - range.start;
-                if len != buf.len() {
-                    return Err(Error::Blob(crate::error::BlobError::RangeError));
+            })?;
+        Self::allocate(&file, len).map_err(Error::from)
+    }
+
+    pub(crate) fn punch_hole_at(path: &Path, range: BlobRange) -> Result<()> {
+        Self::unshare(path)?;
+        let file = std::fs::OpenOptions::new()
+            .write(true)
+            .open(path)
+            .map_err(|e| {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    Error::from(crate::error::BlobError::NotFound)
+                } else {
+                    Error::from(e)
                 }
-                file.seek(std::io::SeekFrom::Start(range.start.try_into().unwrap()))?;
+            })?;
+        let file_size: usize = file.metadata()?.len().try_into().unwrap();
+        Self::check_range(file_size, &range, range.len())?;
+        Self::deallocate(&file, range).map_err(Error::from)
+    }
+
+    /// allocate the blocks of a file of `len` bytes, or only set its size
+    /// if the file system can't
+    pub(crate) fn allocate(file: &std::fs::File, len: usize) -> std::io::Result<()> {
+        use std::os::fd::AsRawFd;
+        if len == 0 {
+            return Ok(());
+        }
+        // SAFETY: plain system call on an open file descriptor
+        let ret = unsafe { libc::fallocate(file.as_raw_fd(), 0, 0, len as libc::off_t) };
+        if ret == 0 {
+            return Ok(());
+        }
+        match std::io::Error::last_os_error() {
+            e if e.raw_os_error() == Some(libc::EOPNOTSUPP) => file.set_len(len as u64),
+            e => Err(e),
+        }
+    }
+
+    /// deallocate the blocks of a range of a file, which then reads as zeros,
+    /// or write the zeros if the file system can't
+    pub(crate) fn deallocate(file: &std::fs::File, range: BlobRange) -> std::io::Result<()> {
+        use std::os::fd::AsRawFd;
+        if range.is_empty() {
+            return Ok(());
+        }
+        // SAFETY: plain system call on an open file descriptor
+        let ret = unsafe {
+            libc::fallocate(
+                file.as_raw_fd(),
+                libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+                range.start as libc::off_t,
+                range.len() as libc::off_t,
+            )
+        };
+        if ret == 0 {
+            return Ok(());
+        }
+        match std::io::Error::last_os_error() {
+            e if e.raw_os_error() == Some(libc::EOPNOTSUPP) => {
+                file.write_all_at(&vec![0; range.len()], range.start as u64)
             }
+            e => Err(e),
         }
-        file.read_exact(buf).map_err(Error::from)
     }
 
-    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
-        let path = self.key_to_path(&key);
-        if let DeleteOpt::Interest(_) = &opt {
-            unimplemented!("Interest delete not implemented, use \"get\" before delete instead")
+    fn open_read(path: &Path, direct: bool) -> Result<std::fs::File> {
+        Self::open_options(direct).open(path).map_err(|e| {
+            if e.kind() == std::io::ErrorKind::NotFound {
+                Error::from(crate::error::BlobError::NotFound)
+            } else {
+                Error::from(e)
+            }
+        })
+    }
+
+    /// options opening a blob file for reading, bypassing the page cache if `direct`
+    fn open_options(direct: bool) -> std::fs::OpenOptions {
+        use std::os::unix::fs::OpenOptionsExt;
+        let mut open_opt = std::fs::OpenOptions::new();
+        open_opt.read(true);
+        if direct {
+            open_opt.custom_flags(libc::O_DIRECT);
+        }
+        open_opt
+    }
+}
+
+/// Direct I/O: the offset, the length and the memory of every read and
+/// write must be aligned to the logical block size of the device, so
+/// unaligned ranges go through bounce buffers.
+impl LocalFileSystemBlobStore {
+    /// alignment of direct I/O, a multiple of the usual logical block sizes
+    const DIRECT_IO_ALIGN: usize = 4096;
+
+    fn read_file_at(
+        file: &std::fs::File,
+        buf: &mut [u8],
+        offset: usize,
+        direct: bool,
+    ) -> std::io::Result<()> {
+        if !direct {
+            return file.read_exact_at(buf, offset as u64);
+        }
+        let start = Self::align_down(offset);
+        let mut bounce = AlignedBuf::new(Self::align_up(offset + buf.len()) - start);
+        let read = Self::read_blocks_at(file, &mut bounce, start)?;
+        if read < offset - start + buf.len() {
+            return Err(std::io::ErrorKind::UnexpectedEof.into());
+        }
+        buf.copy_from_slice(&bounce[offset - start..][..buf.len()]);
+        Ok(())
+    }
+
+    /// write `data` at `offset` of a blob of `size` bytes
+    fn write_file_at(
+        file: &std::fs::File,
+        data: &[u8],
+        offset: usize,
+        size: usize,
+        direct: bool,
+    ) -> std::io::Result<()> {
+        if !direct {
+            return file.write_all_at(data, offset as u64);
         }
+        let start = Self::align_down(offset);
+        let end = Self::align_up(offset + data.len());
+        let mut bounce = AlignedBuf::new(end - start);
+        // keep the content around the data in its first and last blocks
+        let block = Self::DIRECT_IO_ALIGN;
+        if start < offset {
+            Self::read_blocks_at(file, &mut bounce[..block], start)?;
+        }
+        if offset + data.len() < end && end - block >= offset {
+            Self::read_blocks_at(file, &mut bounce[end - block - start..], end - block)?;
+        }
+        bounce[offset - start..][..data.len()].copy_from_slice(data);
+        file.write_all_at(&bounce, start as u64)?;
+        // the last block is padded past the end of the blob
+        if end > size {
+            file.set_len(size as u64)?;
+        }
+        Ok(())
+    }
+
+    /// read aligned blocks up to the end of the file, returns the length read
+    fn read_blocks_at(
+        file: &std::fs::File,
+        blocks: &mut [u8],
+        offset: usize,
+    ) -> std::io::Result<usize> {
+        let mut read = 0;
+        while read < blocks.len() {
+            match file.read_at(&mut blocks[read..], (offset + read) as u64) {
+                Ok(0) => break,
+                Ok(len) => read += len,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => {}
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(read)
+    }
+
+    fn align_down(offset: usize) -> usize {
+        offset & !(Self::DIRECT_IO_ALIGN - 1)
+    }
+
+    fn align_up(offset: usize) -> usize {
+        Self::align_down(offset + Self::DIRECT_IO_ALIGN - 1)
+    }
+}
+
+/// zeroed buffer aligned for direct I/O
+struct AlignedBuf {
+    buf: Vec<u8>,
+    offset: usize,
+    len: usize,
+}
+
+impl AlignedBuf {
+    fn new(len: usize) -> Self {
+        let buf = vec![0_u8; len + LocalFileSystemBlobStore::DIRECT_IO_ALIGN];
+        let offset = buf
+            .as_ptr()
+            .align_offset(LocalFileSystemBlobStore::DIRECT_IO_ALIGN);
+        Self { buf, offset, len }
+    }
+}
+
+impl std::ops::Deref for AlignedBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf[self.offset..][..self.len]
+    }
+}
+
+impl std::ops::DerefMut for AlignedBuf {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf[self.offset..][..self.len]
+    }
+}
+
+impl LocalFileSystemBlobStore {
+    /// The content of interest is read before the file is removed, a bad
+    /// range keeps the file.
+    pub(crate) fn delete_at(path: &Path, opt: DeleteOpt, direct: bool) -> Result<Option<Vec<u8>>> {
+        let content = match opt {
+            DeleteOpt::Interest(range) => {
+                let mut buf = vec![0_u8; range.len()];
+                Self::get_file(path, &mut buf, GetOpt::Range(range), direct)?;
+                Some(buf)
+            }
+            DeleteOpt::Discard => None,
+        };
         std::fs::remove_file(path)
             .map_err(|e| {
                 if e.kind() == std::io::ErrorKind::NotFound {
@@ -156,6 +793,821 @@ impl BlobStore for LocalFileSystemBlobStore {
                     Error::from(e)
                 }
             })
-            .map(|_| None)
+            .map(|_| content)
+    }
+}
+
+#[cfg(not(feature = "io_uring"))]
+impl BatchBlobStore for LocalFileSystemBlobStore {}
+
+#[cfg(feature = "io_uring")]
+impl BatchBlobStore for LocalFileSystemBlobStore {
+    fn get_many(&self, keys: &[Key]) -> Vec<Result<Vec<u8>>> {
+        let Some(ring) = self.ring.as_ref().filter(|_| !self.direct_io) else {
+            return keys
+                .iter()
+                .map(|key| self.get_owned(*key, GetOpt::All))
+                .collect();
+        };
+        let paths = keys
+            .iter()
+            .map(|key| self.key_to_path(key))
+            .collect::<Vec<_>>();
+        ring.lock().read_files(&paths)
+    }
+
+    fn put_many(&self, blobs: &[(Key, &[u8])], opt: PutOpt) -> Vec<Result<()>> {
+        let flags = match opt {
+            PutOpt::Create => libc::O_EXCL,
+            PutOpt::ReplaceOrCreate => libc::O_TRUNC,
+            PutOpt::Replace(_) => 0,
+        };
+        let ring = match &self.ring {
+            Some(ring) if flags != 0 && !self.direct_io => ring,
+            _ => {
+                return blobs
+                    .iter()
+                    .map(|(key, value)| self.put(*key, value, opt.clone()))
+                    .collect()
+            }
+        };
+        if self.mode == OpenMode::ReadOnly {
+            return blobs.iter().map(|_| Err(Error::ReadOnly)).collect();
+        }
+        let _guard = self.snapshot_lock.read();
+        let paths = blobs
+            .iter()
+            .map(|(key, _)| self.key_to_path(key))
+            .collect::<Vec<_>>();
+        let tracked = paths.iter().map(PathBuf::as_path).collect::<Vec<_>>();
+        FsUsage::track(self.usage.as_ref(), &tracked, || {
+            Ok(self.put_ring(ring, blobs, &paths, flags))
+        })
+        .unwrap()
+    }
+}
+
+#[cfg(feature = "io_uring")]
+impl LocalFileSystemBlobStore {
+    /// the writes of `put_many` through the ring, to the files at `paths`
+    fn put_ring(
+        &self,
+        ring: &Mutex<crate::store_impl::uring::Ring>,
+        blobs: &[(Key, &[u8])],
+        paths: &[PathBuf],
+        flags: i32,
+    ) -> Vec<Result<()>> {
+        // the directories, and the copies of the blobs shared with a snapshot
+        let mut results = blobs
+            .iter()
+            .zip(paths)
+            .map(|(_, path)| {
+                std::fs::create_dir_all(path.parent().unwrap())?;
+                if flags == libc::O_TRUNC {
+                    Self::unshare(path)?;
+                }
+                Ok(path.clone())
+            })
+            .collect::<Vec<Result<PathBuf>>>();
+        let ready = results
+            .iter()
+            .zip(blobs)
+            .filter_map(|(path, (_, value))| Some((path.as_ref().ok()?.clone(), *value)))
+            .collect::<Vec<_>>();
+        let mut written = ring
+            .lock()
+            .write_files(&ready, flags, self.durability >= Durability::Data)
+            .into_iter();
+        for result in results.iter_mut().filter(|result| result.is_ok()) {
+            if let Err(e) = written.next().unwrap() {
+                *result = Err(e);
+            }
+        }
+        let keys = blobs.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+        self.invalidate(&keys);
+        results
+            .into_iter()
+            .map(|path| self.flush(&path?, false, true))
+            .collect()
+    }
+}
+
+impl BlobStore for LocalFileSystemBlobStore {
+    fn contains(&self, key: Key) -> Result<bool> {
+        if self.cached(&key).is_some() {
+            return Ok(true);
+        }
+        let path = self.key_to_path(&key);
+        path.try_exists().map_err(Error::from)
+    }
+
+    fn meta(&self, key: Key) -> Result<crate::BlobMeta> {
+        if let Some(blob) = self.cached(&key) {
+            return Ok(Self::blob_meta(&blob.file.metadata()?));
+        }
+        Self::meta_at(&self.key_to_path(&key))
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        self.writable()?;
+        let _guard = self.snapshot_lock.read();
+        let path = self.key_to_path(&key);
+        FsUsage::track(self.usage.as_ref(), &[&path], || {
+            if let PutOpt::Replace(range) = &opt {
+                match self.open_cached(&key)? {
+                    Some(blob) if !blob.shared => {
+                        Self::check_range(blob.size, range, value.len())?;
+                        Self::write_file_at(
+                            &blob.file,
+                            value,
+                            range.start,
+                            blob.size,
+                            self.direct_io,
+                        )?;
+                        return self.flush_cached(&blob);
+                    }
+                    _ => {}
+                }
+            }
+            let entry = !matches!(opt, PutOpt::Replace(_));
+            let result = Self::put_file(&path, value, opt, self.direct_io);
+            self.invalidate(&[key]);
+            result?;
+            self.flush(&path, true, entry)
+        })
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        match self.open_cached(&key)? {
+            Some(blob) => {
+                let range = Self::read_range(blob.size, opt, buf.len())?;
+                Self::read_file_at(&blob.file, buf, range.start, self.direct_io)
+                    .map_err(Error::from)
+            }
+            None => Self::get_file(&self.key_to_path(&key), buf, opt, self.direct_io),
+        }
+    }
+
+    fn get_vectored(&self, key: Key, bufs: &mut [(BlobRange, &mut [u8])]) -> Result<()> {
+        match self.open_cached(&key)? {
+            Some(blob) => {
+                crate::check_ranges(
+                    blob.size,
+                    bufs.iter().map(|(range, buf)| (range, buf.len())),
+                )?;
+                for (range, buf) in bufs.iter_mut() {
+                    Self::read_file_at(&blob.file, buf, range.start, self.direct_io)?;
+                }
+                Ok(())
+            }
+            None => Self::get_vectored_file(&self.key_to_path(&key), bufs, self.direct_io),
+        }
+    }
+
+    fn put_vectored(&self, key: Key, bufs: &[(BlobRange, &[u8])]) -> Result<()> {
+        self.writable()?;
+        let _guard = self.snapshot_lock.read();
+        let path = self.key_to_path(&key);
+        FsUsage::track(self.usage.as_ref(), &[&path], || {
+            match self.open_cached(&key)? {
+                Some(blob) if !blob.shared => {
+                    crate::check_ranges(
+                        blob.size,
+                        bufs.iter().map(|(range, buf)| (range, buf.len())),
+                    )?;
+                    for (range, buf) in bufs {
+                        Self::write_file_at(
+                            &blob.file,
+                            buf,
+                            range.start,
+                            blob.size,
+                            self.direct_io,
+                        )?;
+                    }
+                    self.flush_cached(&blob)
+                }
+                _ => {
+                    let result = Self::put_vectored_file(&path, bufs, self.direct_io);
+                    self.invalidate(&[key]);
+                    result?;
+                    self.flush(&path, true, false)
+                }
+            }
+        })
+    }
+
+    /// The blocks of the blob are allocated with `fallocate`.
+    fn create_sized(&self, key: Key, len: usize) -> Result<()> {
+        self.writable()?;
+        let _guard = self.snapshot_lock.read();
+        let path = self.key_to_path(&key);
+        FsUsage::track(self.usage.as_ref(), &[&path], || {
+            let result = Self::create_sized_at(&path, len);
+            self.invalidate(&[key]);
+            result?;
+            self.flush(&path, true, true)
+        })
+    }
+
+    /// The blocks of the range are deallocated with `FALLOC_FL_PUNCH_HOLE`.
+    fn punch_hole(&self, key: Key, range: BlobRange) -> Result<()> {
+        self.writable()?;
+        let _guard = self.snapshot_lock.read();
+        let path = self.key_to_path(&key);
+        FsUsage::track(self.usage.as_ref(), &[&path], || {
+            let result = Self::punch_hole_at(&path, range);
+            self.invalidate(&[key]);
+            result?;
+            self.flush(&path, true, false)
+        })
+    }
+
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        self.writable()?;
+        let _guard = self.snapshot_lock.read();
+        let path = self.key_to_path(&key);
+        FsUsage::track(self.usage.as_ref(), &[&path], || {
+            let result = Self::delete_at(&path, opt, self.direct_io);
+            self.invalidate(&[key]);
+            let content = result?;
+            self.flush(&path, false, true)?;
+            Ok(content)
+        })
+    }
+
+    /// Copies are hard links, a blob gets its own content at its first write.
+    ///
+    /// The link excludes the writes in place, which would otherwise land in
+    /// the content shared with the copy if they checked the link count before
+    /// it was created.
+    fn copy(&self, src: Key, dst: Key, opt: CopyOpt) -> Result<()> {
+        self.writable()?;
+        let _guard = self.snapshot_lock.write();
+        // the cached file of the source doesn't know it will be shared
+        self.invalidate(&[src]);
+        let dst_path = self.key_to_path(&dst);
+        FsUsage::track(self.usage.as_ref(), &[&dst_path], || {
+            let result = Self::copy_path(&self.key_to_path(&src), &dst_path, opt);
+            self.invalidate(&[dst]);
+            result?;
+            self.flush(&dst_path, false, true)
+        })
+    }
+
+    fn rename(&self, src: Key, dst: Key, opt: CopyOpt) -> Result<()> {
+        self.writable()?;
+        let _guard = self.snapshot_lock.read();
+        let (src_path, dst_path) = (self.key_to_path(&src), self.key_to_path(&dst));
+        FsUsage::track(self.usage.as_ref(), &[&src_path, &dst_path], || {
+            let result = Self::rename_path(&src_path, &dst_path, opt);
+            self.invalidate(&[src, dst]);
+            result?;
+            self.flush(&src_path, false, true)?;
+            self.flush(&dst_path, false, true)
+        })
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        crate::store_impl::helpers::list_hex_keys(&self.root, self.layout)
+    }
+
+    /// # Error
+    /// - Other: the store is not opened with `connect`, only the exclusive
+    ///   handles and their buckets keep the usage.
+    fn stats(&self) -> Result<crate::StoreStats> {
+        self.usage.as_ref().map(FsUsage::stats).ok_or_else(|| {
+            Error::other(anyhow::anyhow!(
+                "the usage is only kept by the exclusive handles"
+            ))
+        })
+    }
+
+    fn is_shared(&self) -> bool {
+        self.mode == OpenMode::Shared
+    }
+}
+
+impl LocalFileSystemBlobStore {
+    /// Copies are hard links, a blob gets its own content at its first write.
+    fn copy_path(src: &Path, dst: &Path, opt: CopyOpt) -> Result<()> {
+        if !src.try_exists()? {
+            return Err(crate::error::BlobError::NotFound.into());
+        }
+        match opt {
+            CopyOpt::Create => Self::link_at(src, dst),
+            CopyOpt::ReplaceOrCreate if src == dst => Ok(()),
+            CopyOpt::ReplaceOrCreate => {
+                let link = dst.with_extension(format!("lnk{:08x}", rand::random::<u32>()));
+                Self::link_at(src, &link)?;
+                std::fs::rename(link, dst).map_err(Error::from)
+            }
+        }
+    }
+
+    fn rename_path(src: &Path, dst: &Path, opt: CopyOpt) -> Result<()> {
+        if !src.try_exists()? {
+            return Err(crate::error::BlobError::NotFound.into());
+        }
+        match opt {
+            // linking fails if the destination exists, unlike renaming
+            CopyOpt::Create => {
+                Self::link_at(src, dst)?;
+                std::fs::remove_file(src).map_err(Error::from)
+            }
+            CopyOpt::ReplaceOrCreate => {
+                std::fs::create_dir_all(dst.parent().unwrap())?;
+                std::fs::rename(src, dst).map_err(Error::from)
+            }
+        }
+    }
+}
+
+impl LocalFileSystemBlobStore {
+    /// a bucket shares the settings and the lock of its store, and the locks
+    /// of the other handles on the bucket
+    fn open_bucket(&self, path: PathBuf) -> Result<Self> {
+        let (commit_lock, snapshot_lock) = self
+            .bucket_locks
+            .lock()
+            .entry(path.clone())
+            .or_default()
+            .clone();
+        let mut bucket = Self::open(path, self.mode, self.layout, false)?
+            .with_max_key_len(self.max_key_len)
+            .with_direct_io(self.direct_io);
+        bucket.durability = self.durability;
+        bucket.commit_lock = commit_lock;
+        bucket.snapshot_lock = snapshot_lock;
+        bucket.bucket_locks = self.bucket_locks.clone();
+        bucket.usage = self
+            .usage
+            .as_ref()
+            .map(|usage| usage.bucket(&bucket.root))
+            .transpose()?;
+        Ok(bucket)
+    }
+}
+
+impl BucketStore for LocalFileSystemBlobStore {
+    type Bucket<'a> = LocalFileSystemBlobStore;
+
+    fn create_bucket(&self, name: &str) -> Result<Self::Bucket<'_>> {
+        self.writable()?;
+        let path = crate::store_impl::helpers::create_bucket_dir(&self.root, name)?;
+        self.flush(&path, false, true)?;
+        self.open_bucket(path)
+    }
+
+    fn bucket(&self, name: &str) -> Result<Self::Bucket<'_>> {
+        let path = crate::store_impl::helpers::bucket_dir(&self.root, name)?;
+        self.open_bucket(path)
+    }
+
+    fn list_buckets(&self) -> Result<Vec<String>> {
+        crate::store_impl::helpers::list_bucket_dirs(&self.root)
+    }
+
+    fn drop_bucket(&self, name: &str) -> Result<()> {
+        self.writable()?;
+        let drop = || crate::store_impl::helpers::drop_bucket_dir(&self.root, name);
+        match &self.usage {
+            Some(usage) => usage.drop_bucket(
+                &crate::store_impl::helpers::bucket_dir(&self.root, name)?,
+                drop,
+            ),
+            None => drop(),
+        }
+    }
+}
+
+impl VarKeyBlobStore for LocalFileSystemBlobStore {
+    fn max_key_len(&self) -> usize {
+        self.max_key_len
+    }
+
+    fn contains_var(&self, key: &[u8]) -> Result<bool> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.contains(key),
+            VarKey::Var(key) => crate::store_impl::helpers::var_key_path(&self.root, key)
+                .try_exists()
+                .map_err(Error::from),
+        }
+    }
+
+    fn meta_var(&self, key: &[u8]) -> Result<crate::BlobMeta> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.meta(key),
+            VarKey::Var(key) => {
+                Self::meta_at(&crate::store_impl::helpers::var_key_path(&self.root, key))
+            }
+        }
+    }
+
+    fn put_var(&self, key: &[u8], value: &[u8], opt: PutOpt) -> Result<()> {
+        self.writable()?;
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.put(key, value, opt),
+            VarKey::Var(key) => {
+                let path = crate::store_impl::helpers::var_key_path(&self.root, key);
+                let entry = !matches!(opt, PutOpt::Replace(_));
+                FsUsage::track(self.usage.as_ref(), &[&path], || {
+                    Self::put_file(&path, value, opt, self.direct_io)?;
+                    self.flush(&path, true, entry)
+                })
+            }
+        }
+    }
+
+    fn get_var(&self, key: &[u8], buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.get(key, buf, opt),
+            VarKey::Var(key) => Self::get_file(
+                &crate::store_impl::helpers::var_key_path(&self.root, key),
+                buf,
+                opt,
+                self.direct_io,
+            ),
+        }
+    }
+
+    fn delete_var(&self, key: &[u8], opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        self.writable()?;
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.delete(key, opt),
+            VarKey::Var(key) => {
+                let path = crate::store_impl::helpers::var_key_path(&self.root, key);
+                FsUsage::track(self.usage.as_ref(), &[&path], || {
+                    let content = Self::delete_at(&path, opt, self.direct_io)?;
+                    self.flush(&path, false, true)?;
+                    Ok(content)
+                })
+            }
+        }
+    }
+}
+
+enum Staged {
+    /// the new content of the blob is in the transaction directory
+    Put,
+    Delete,
+}
+
+impl Staged {
+    const PUT: u8 = 1;
+    const DELETE: u8 = 2;
+}
+
+/// State of a blob in the store that a transaction staged a write against.
+enum Base {
+    /// created with `PutOpt::Create`
+    Absent,
+    /// replaced in a range, XXH3-128 of the content it was read with
+    Content(u128),
+}
+
+/// Transaction of a `LocalFileSystemBlobStore`.
+///
+/// The blobs written by a transaction are staged as whole files in a directory
+/// of its own under `root/txn`. Commit writes the intent log of the
+/// transaction, `op | key` records, which is the commit point, then renames the
+/// staged files over the blobs and removes the deleted blobs. `connect`
+/// replays the transactions that have an intent log and discards the others.
+///
+/// The blobs created or replaced in a range are checked again by `commit`,
+/// which excludes the other writes to the store meanwhile: if one of them was
+/// written since it was staged, the transaction is rolled back and `commit`
+/// fails with `BlobError::PreconditionFailed`.
+pub struct LocalFileSystemTransaction<'a> {
+    store: &'a LocalFileSystemBlobStore,
+    dir: PathBuf,
+    staged: BTreeMap<Key, Staged>,
+    /// state of the blobs that the staged writes rely on
+    bases: BTreeMap<Key, Base>,
+    /// the transaction is committed or rolled back
+    done: bool,
+}
+
+impl LocalFileSystemBlobStore {
+    const TXN_DIR: &'static str = "txn";
+    const INTENT_FILE: &'static str = "intent";
+    const INTENT_RECORD_LEN: usize = 1 + std::mem::size_of::<Key>();
+
+    /// complete the committed transactions of the store and of its buckets,
+    /// and discard the others
+    fn recover(&self) -> Result<()> {
+        let txn_root = self.root.join(Self::TXN_DIR);
+        if txn_root.exists() {
+            for dir in std::fs::read_dir(txn_root)? {
+                let dir = dir?.path();
+                if dir.join(Self::INTENT_FILE).exists() {
+                    self.replay(&dir)?;
+                } else {
+                    std::fs::remove_dir_all(dir)?;
+                }
+            }
+        }
+        for name in crate::store_impl::helpers::list_bucket_dirs(&self.root)? {
+            self.bucket(&name)?.recover()?;
+        }
+        Ok(())
+    }
+
+    /// apply the intent log of a transaction, then remove the transaction
+    ///
+    /// The directories it changed are flushed whatever the durability, before
+    /// the intent log is removed.
+    fn replay(&self, dir: &Path) -> Result<()> {
+        let intent = std::fs::read(dir.join(Self::INTENT_FILE))?;
+        let records = intent
+            .chunks_exact(Self::INTENT_RECORD_LEN)
+            .map(|record| {
+                let key: Key = record[1..].try_into().unwrap();
+                (record[0], key, self.key_to_path(&key))
+            })
+            .collect::<Vec<_>>();
+        let paths = records
+            .iter()
+            .map(|(_, _, path)| path.as_path())
+            .collect::<Vec<_>>();
+        let mut dirs = BTreeSet::new();
+        FsUsage::track(self.usage.as_ref(), &paths, || {
+            for (op, key, path) in &records {
+                dirs.extend(
+                    path.ancestors()
+                        .skip(1)
+                        .take_while(|dir| dir.starts_with(&self.root))
+                        .map(Path::to_path_buf),
+                );
+                match *op {
+                    Staged::PUT => {
+                        let staged = dir.join(hex::encode(key));
+                        // already renamed if the replay is resumed
+                        if staged.exists() {
+                            std::fs::create_dir_all(path.parent().unwrap())?;
+                            std::fs::rename(staged, path)?;
+                        }
+                    }
+                    Staged::DELETE => match std::fs::remove_file(path) {
+                        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e.into()),
+                        _ => {}
+                    },
+                    op => return Err(Error::other(anyhow::anyhow!("invalid intent op {op}"))),
+                }
+                self.invalidate(&[*key]);
+            }
+            Ok(())
+        })?;
+        dirs.insert(self.root.join(Self::TXN_DIR));
+        for dir in dirs {
+            std::fs::File::open(dir)?.sync_all()?;
+        }
+        std::fs::remove_dir_all(dir).map_err(Error::from)
+    }
+}
+
+impl LocalFileSystemTransaction<'_> {
+    fn staged_path(&self, key: &Key) -> PathBuf {
+        self.dir.join(hex::encode(key))
+    }
+
+    fn exists(&self, key: &Key) -> Result<bool> {
+        match self.staged.get(key) {
+            Some(Staged::Put) => Ok(true),
+            Some(Staged::Delete) => Ok(false),
+            None => self.store.contains(*key),
+        }
+    }
+
+    fn stage(&mut self, key: Key, value: &[u8]) -> Result<()> {
+        let mut file = std::fs::File::create(self.staged_path(&key))?;
+        file.write_all(value)?;
+        file.sync_all()?;
+        self.staged.insert(key, Staged::Put);
+        Ok(())
+    }
+
+    /// check that the blobs the staged writes rely on are unchanged, with the
+    /// writes to the store excluded
+    fn check_bases(&self) -> Result<()> {
+        for (key, base) in &self.bases {
+            let unchanged = match base {
+                Base::Absent => !self.store.contains(*key)?,
+                Base::Content(digest) => match std::fs::read(self.store.key_to_path(key)) {
+                    Ok(content) => xxhash_rust::xxh3::xxh3_128(&content) == *digest,
+                    Err(e) if e.kind() == std::io::ErrorKind::NotFound => false,
+                    Err(e) => return Err(e.into()),
+                },
+            };
+            if !unchanged {
+                return Err(crate::error::BlobError::PreconditionFailed.into());
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Transaction for LocalFileSystemTransaction<'_> {
+    fn put(&mut self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        match opt {
+            PutOpt::Create => {
+                if self.exists(&key)? {
+                    return Err(crate::error::BlobError::AlreadyExists.into());
+                }
+                if !self.staged.contains_key(&key) {
+                    self.bases.insert(key, Base::Absent);
+                }
+                self.stage(key, value)
+            }
+            PutOpt::ReplaceOrCreate => self.stage(key, value),
+            PutOpt::Replace(range) => {
+                let current = match self.staged.get(&key) {
+                    Some(Staged::Put) => self.staged_path(&key),
+                    Some(Staged::Delete) => {
+                        return Err(crate::error::BlobError::NotFound.into());
+                    }
+                    None => self.store.key_to_path(&key),
+                };
+                let mut content = std::fs::read(current).map_err(|e| {
+                    if e.kind() == std::io::ErrorKind::NotFound {
+                        Error::from(crate::error::BlobError::NotFound)
+                    } else {
+                        Error::from(e)
+                    }
+                })?;
+                if !crate::store_impl::helpers::range_contains(&(0..content.len()), &range) {
+                    return Err(crate::error::BlobError::RangeError.into());
+                }
+                if range.len() != value.len() {
+                    return Err(crate::error::BlobError::RangeError.into());
+                }
+                if !self.staged.contains_key(&key) {
+                    self.bases
+                        .insert(key, Base::Content(xxhash_rust::xxh3::xxh3_128(&content)));
+                }
+                content[range].copy_from_slice(value);
+                self.stage(key, &content)
+            }
+        }
+    }
+
+    fn delete(&mut self, key: Key) -> Result<()> {
+        if !self.exists(&key)? {
+            return Err(crate::error::BlobError::NotFound.into());
+        }
+        if let Some(Staged::Put) = self.staged.get(&key) {
+            std::fs::remove_file(self.staged_path(&key))?;
+        }
+        self.staged.insert(key, Staged::Delete);
+        Ok(())
+    }
+
+    fn commit(mut self) -> Result<()> {
+        let _guard = self.store.commit_lock.lock();
+        let _snapshot_guard = self.store.snapshot_lock.write();
+        self.check_bases()?;
+        let mut intent =
+            Vec::with_capacity(self.staged.len() * LocalFileSystemBlobStore::INTENT_RECORD_LEN);
+        self.staged.iter().for_each(|(key, staged)| {
+            intent.push(match staged {
+                Staged::Put => Staged::PUT,
+                Staged::Delete => Staged::DELETE,
+            });
+            intent.extend_from_slice(key);
+        });
+        let tmp = self.dir.join("intent.tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(&intent)?;
+        file.sync_all()?;
+        std::fs::rename(tmp, self.dir.join(LocalFileSystemBlobStore::INTENT_FILE))?;
+        std::fs::File::open(&self.dir)?.sync_all()?;
+        // committed, an interrupted replay is resumed at the next connect
+        self.done = true;
+        self.store.replay(&self.dir)
+    }
+
+    fn rollback(mut self) -> Result<()> {
+        self.done = true;
+        std::fs::remove_dir_all(&self.dir).map_err(Error::from)
+    }
+}
+
+impl Drop for LocalFileSystemTransaction<'_> {
+    fn drop(&mut self) {
+        if !self.done {
+            let _ = std::fs::remove_dir_all(&self.dir);
+        }
+    }
+}
+
+impl TransactionalBlobStore for LocalFileSystemBlobStore {
+    type Transaction<'a> = LocalFileSystemTransaction<'a>;
+
+    fn begin(&self) -> Result<Self::Transaction<'_>> {
+        self.exclusive()?;
+        let txn_root = self.root.join(Self::TXN_DIR);
+        std::fs::create_dir_all(&txn_root)?;
+        let dir = loop {
+            let dir = txn_root.join(format!("{:016x}", rand::random::<u64>()));
+            match std::fs::create_dir(&dir) {
+                Ok(()) => break dir,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        Ok(LocalFileSystemTransaction {
+            store: self,
+            dir,
+            staged: BTreeMap::new(),
+            bases: BTreeMap::new(),
+            done: false,
+        })
+    }
+}
+
+/// Snapshot of a `LocalFileSystemBlobStore`.
+///
+/// The snapshot is a tree of hard links to the blobs under `root/snapshots`,
+/// removed with the snapshot. The store gives a blob its own copy before
+/// writing to it in place, so the linked content doesn't change. Opening the
+/// store exclusively removes the snapshots left by a crash.
+///
+/// Only the blobs with fixed keys are linked: the blobs with variable-length
+/// keys and the buckets of the store are not part of the snapshot.
+pub struct LocalFileSystemSnapshot {
+    store: LocalFileSystemBlobStore,
+}
+
+impl LocalFileSystemSnapshot {
+    /// directory of the snapshot, e.g. to back it up
+    pub fn path(&self) -> &Path {
+        &self.store.root
+    }
+}
+
+impl Drop for LocalFileSystemSnapshot {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.store.root);
+    }
+}
+
+impl BlobStore for LocalFileSystemSnapshot {
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.store.contains(key)
+    }
+
+    fn meta(&self, key: Key) -> Result<crate::BlobMeta> {
+        self.store.meta(key)
+    }
+
+    fn put(&self, _key: Key, _value: &[u8], _opt: PutOpt) -> Result<()> {
+        Err(Error::ReadOnly)
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        self.store.get(key, buf, opt)
+    }
+
+    fn delete(&self, _key: Key, _opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        Err(Error::ReadOnly)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.store.keys()
+    }
+}
+
+impl LocalFileSystemBlobStore {
+    const SNAPSHOT_DIR: &'static str = "snapshots";
+}
+
+impl SnapshotBlobStore for LocalFileSystemBlobStore {
+    type Snapshot<'a> = LocalFileSystemSnapshot;
+
+    fn snapshot(&self) -> Result<Self::Snapshot<'_>> {
+        self.exclusive()?;
+        let _guard = self.snapshot_lock.write();
+        let snapshots = self.root.join(Self::SNAPSHOT_DIR);
+        std::fs::create_dir_all(&snapshots)?;
+        let dir = loop {
+            let dir = snapshots.join(format!("{:016x}", rand::random::<u64>()));
+            match std::fs::create_dir(&dir) {
+                Ok(()) => break dir,
+                Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e.into()),
+            }
+        };
+        let snapshot = LocalFileSystemSnapshot {
+            store: Self::open(dir, OpenMode::ReadOnly, self.layout, false)?,
+        };
+        for key in self.keys()? {
+            let link = snapshot.store.key_to_path(&key);
+            std::fs::create_dir_all(link.parent().unwrap())?;
+            std::fs::hard_link(self.key_to_path(&key), link)?;
+        }
+        // the cached files are now shared with the snapshot
+        if let Some(cache) = &self.fd_cache {
+            cache.lock().clear();
+        }
+        Ok(snapshot)
     }
 }
@@ -1,96 +1,298 @@
 use std::{cell::RefCell, num::NonZeroUsize};
 
 use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
 
 use crate::{
     error::{Error, Result},
-    BlobStore, Key,
+    store_impl::{
+        config::{Durability, Layout, OpenMode},
+        fs_usage::FsUsage,
+        helpers::{StoreLock, VarKey},
+        local_filesystem::LocalFileSystemBlobStore,
+    },
+    BlobStore, BucketStore, Key, VarKeyBlobStore,
 };
 
-type MappedFile = memmap2::MmapMut;
+/// Mapping of a blob file, read-only in a read-only store.
+enum MappedFile {
+    ReadWrite(memmap2::MmapMut),
+    ReadOnly(memmap2::Mmap),
+}
+
+impl std::ops::Deref for MappedFile {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            MappedFile::ReadWrite(page) => page,
+            MappedFile::ReadOnly(page) => page,
+        }
+    }
+}
+
+impl std::ops::DerefMut for MappedFile {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        match self {
+            MappedFile::ReadWrite(page) => page,
+            // writes are refused before they reach a mapping
+            MappedFile::ReadOnly(_) => unreachable!("write to a read-only mapping"),
+        }
+    }
+}
 
 pub struct MemMapStore {
     root: std::path::PathBuf,
     cache: RefCell<lru::LruCache<Key, MappedFile>>,
+    max_key_len: usize,
+    read_only: bool,
+    layout: Layout,
+    durability: Durability,
+    /// usage of the blobs, kept by the writable handles and their buckets
+    usage: Option<FsUsage>,
+    /// lock on the root, `None` for the buckets of a store
+    _lock: Option<StoreLock>,
+}
+
+/// Configuration of a `MemMapStore`.
+///
+/// It is built in code or deserialized, e.g. from a TOML or JSON file, where
+/// the missing fields take their default. The default configuration opens the
+/// store as `MemMapStore::connect` does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MemMapConfig {
+    /// Create the root directory if it doesn't exist.
+    pub create_if_missing: bool,
+    /// `Shared` is not supported: the mappings are cached.
+    pub mode: OpenMode,
+    pub layout: Layout,
+    pub durability: Durability,
+    /// Number of blobs kept mapped.
+    pub cache_size: usize,
+    pub max_key_len: usize,
+}
+
+impl Default for MemMapConfig {
+    fn default() -> Self {
+        Self {
+            create_if_missing: false,
+            mode: OpenMode::default(),
+            layout: Layout::default(),
+            durability: Durability::default(),
+            cache_size: MemMapStore::DEFAULT_CACHE_SIZE,
+            max_key_len: crate::DEFAULT_MAX_KEY_LEN,
+        }
+    }
+}
+
+impl MemMapConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: OpenMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_layout(mut self, layout: Layout) -> Self {
+        self.layout = layout;
+        self
+    }
+
+    pub fn with_durability(mut self, durability: Durability) -> Self {
+        self.durability = durability;
+        self
+    }
+
+    pub fn with_cache_size(mut self, cache_size: usize) -> Self {
+        self.cache_size = cache_size;
+        self
+    }
+
+    pub fn with_max_key_len(mut self, max_key_len: usize) -> Self {
+        self.max_key_len = max_key_len;
+        self
+    }
+
+    /// Open the store rooted at `root`.
+    ///
+    /// # Error
+    /// - Locked: another handle has the store open in a conflicting mode.
+    /// - Other: the cache size is 0 or the mode is `Shared`.
+    pub fn open(&self, root: impl Into<std::path::PathBuf>) -> Result<MemMapStore> {
+        let root = root.into();
+        let cache_size = NonZeroUsize::new(self.cache_size)
+            .ok_or(Error::Other(anyhow!("invalid cache size")))?;
+        let read_only = match self.mode {
+            OpenMode::Exclusive => false,
+            OpenMode::ReadOnly => true,
+            OpenMode::Shared => return Err(Error::Other(anyhow!("shared mode not supported"))),
+        };
+        if self.create_if_missing {
+            std::fs::create_dir_all(&root)?;
+        }
+        let mut store = MemMapStore::open(root, cache_size, read_only, self.layout, true)?
+            .with_max_key_len(self.max_key_len);
+        store.durability = self.durability;
+        Ok(store)
+    }
 }
 
 impl MemMapStore {
     const DEFAULT_CACHE_SIZE: usize = 64;
+
+    /// Open the store as its only handle: the mappings are cached, so writes
+    /// through other handles would be lost.
+    ///
+    /// # Error
+    /// - Locked: another handle has the store open.
     pub fn connect(root: impl Into<std::path::PathBuf>) -> Result<Self> {
-        let root = root.into();
-        if !root.exists() {
-            return Err(Error::Io(std::io::Error::new(
-                std::io::ErrorKind::NotFound,
-                "dev path not found",
-            )));
-        }
-        Ok(Self {
-            root,
-            cache: RefCell::new(lru::LruCache::new(
-                NonZeroUsize::new(Self::DEFAULT_CACHE_SIZE).unwrap(),
-            )),
-        })
+        MemMapConfig::default().open(root)
     }
 
     pub fn connect_with_cache_size(
         root: impl Into<std::path::PathBuf>,
         cache_size: usize,
     ) -> Result<Self> {
-        let root = root.into();
+        MemMapConfig::default()
+            .with_cache_size(cache_size)
+            .open(root)
+    }
+
+    /// Open the store for reading only: the blobs are mapped read-only, and
+    /// writes fail with `Error::ReadOnly`. Several read-only handles can have
+    /// the store open at the same time.
+    ///
+    /// # Error
+    /// - Locked: a handle opened for writing has the store open.
+    pub fn connect_read_only(root: impl Into<std::path::PathBuf>) -> Result<Self> {
+        MemMapConfig::default()
+            .with_mode(OpenMode::ReadOnly)
+            .open(root)
+    }
+
+    fn open(
+        root: std::path::PathBuf,
+        cache_size: NonZeroUsize,
+        read_only: bool,
+        layout: Layout,
+        lock: bool,
+    ) -> Result<Self> {
         if !root.exists() {
             return Err(Error::Io(std::io::Error::new(
                 std::io::ErrorKind::NotFound,
                 "dev path not found",
             )));
         }
-        let cache_size =
-            NonZeroUsize::new(cache_size).ok_or(Error::Other(anyhow!("invalid cache size")))?;
+        let owner = lock;
+        let lock = if lock {
+            Some(StoreLock::acquire(&root, !read_only)?)
+        } else {
+            None
+        };
+        let usage = if owner && !read_only {
+            Some(FsUsage::open(&root)?)
+        } else {
+            None
+        };
         Ok(Self {
             root,
             cache: RefCell::new(lru::LruCache::new(cache_size)),
+            max_key_len: crate::DEFAULT_MAX_KEY_LEN,
+            read_only,
+            layout,
+            durability: Durability::None,
+            usage,
+            _lock: lock,
         })
     }
 
-    fn key_to_path(&self, key: &Key) -> std::path::PathBuf {
-        use itertools::Itertools;
-        let mut path = self.root.clone();
-        let key_hex = hex::encode(key);
-        const DELIM: usize = 2;
-        key_hex
-            .chars()
-            .chunks(key_hex.len() / DELIM)
-            .into_iter()
-            .for_each(|chunk| {
-                let chunk: String = chunk.collect();
-                path.push(&chunk);
-            });
-        path
+    /// a bucket shares the settings and the lock of its store
+    fn open_bucket(&self, path: std::path::PathBuf) -> Result<Self> {
+        let cache_size = self.cache.borrow().cap();
+        let mut bucket = Self::open(path, cache_size, self.read_only, self.layout, false)?
+            .with_max_key_len(self.max_key_len);
+        bucket.durability = self.durability;
+        bucket.usage = self
+            .usage
+            .as_ref()
+            .map(|usage| usage.bucket(&bucket.root))
+            .transpose()?;
+        Ok(bucket)
     }
-}
 
-impl BlobStore for MemMapStore {
-    fn contains(&self, key: Key) -> Result<bool> {
-        self.key_to_path(&key).try_exists().map_err(Error::from)
+    /// flush a write to the blob at `path`, see `LocalFileSystemBlobStore::flush_path`
+    fn flush(&self, path: &std::path::Path, content: bool, entry: bool) -> Result<()> {
+        LocalFileSystemBlobStore::flush_path(&self.root, path, self.durability, content, entry)
     }
 
-    fn meta(&self, key: Key) -> Result<crate::BlobMeta> {
-        let path = self.key_to_path(&key);
-        let size = path
-            .metadata()
-            .map_err(|e| {
-                if e.kind() == std::io::ErrorKind::NotFound {
-                    Error::from(crate::error::BlobError::NotFound)
-                } else {
-                    Error::from(e)
+    fn writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// Use variable-length keys of up to `max_key_len` bytes.
+    pub fn with_max_key_len(mut self, max_key_len: usize) -> Self {
+        self.max_key_len = max_key_len;
+        self
+    }
+
+    /// get the mapping of a blob, mapping it on a cache miss
+    fn load<'c>(
+        &self,
+        cache: &'c mut lru::LruCache<Key, MappedFile>,
+        key: Key,
+    ) -> Result<&'c mut MappedFile> {
+        if !cache.contains(&key) {
+            let file = std::fs::File::options()
+                .read(true)
+                .write(!self.read_only)
+                .open(self.key_to_path(&key))
+                .map_err(|e| {
+                    if e.kind() == std::io::ErrorKind::NotFound {
+                        Error::from(crate::error::BlobError::NotFound)
+                    } else {
+                        Error::from(e)
+                    }
+                })?;
+            let page = if self.read_only {
+                MappedFile::ReadOnly(unsafe { memmap2::MmapOptions::default().map(&file) }?)
+            } else {
+                MappedFile::ReadWrite(unsafe { memmap2::MmapOptions::default().map_mut(&file) }?)
+            };
+            cache.put(key, page);
+        }
+        Ok(cache.get_mut(&key).unwrap())
+    }
+
+    /// the part of a mapping selected by `opt`
+    fn slice(page: &[u8], opt: crate::GetOpt) -> Result<&[u8]> {
+        match opt {
+            crate::GetOpt::All => Ok(page),
+            crate::GetOpt::Range(range) => {
+                if !crate::store_impl::helpers::range_contains(&(0..page.len()), &range) {
+                    return Err(Error::from(crate::error::BlobError::RangeError));
                 }
-            })?
-            .len()
-            .try_into()
-            .unwrap();
-        Ok(crate::BlobMeta { size })
+                Ok(&page[range])
+            }
+        }
     }
 
-    fn put(&self, key: Key, value: &[u8], opt: crate::PutOpt) -> Result<()> {
+    fn key_to_path(&self, key: &Key) -> std::path::PathBuf {
+        crate::store_impl::helpers::key_path(&self.root, key, self.layout)
+    }
+
+    /// write a blob through its mapping
+    fn put_mapped(&self, key: Key, value: &[u8], opt: crate::PutOpt) -> Result<()> {
         let mut cache = self.cache.borrow_mut();
         match opt {
             crate::PutOpt::Create => {
@@ -112,8 +314,8 @@ impl BlobStore for MemMapStore {
                 file.set_len(value.len().try_into().unwrap())?;
                 let mut page = unsafe { memmap2::MmapOptions::default().map_mut(&file) }?;
                 page.copy_from_slice(value);
-                cache.put(key, page);
-                return Ok(());
+                cache.put(key, MappedFile::ReadWrite(page));
+                Ok(())
             }
             crate::PutOpt::Replace(range) => {
                 if let Some(page) = cache.get_mut(&key) {
@@ -150,71 +352,322 @@ impl BlobStore for MemMapStore {
                     return Err(Error::from(crate::error::BlobError::RangeError));
                 }
                 page[range].copy_from_slice(value);
-                cache.put(key, page);
-                return Ok(());
+                cache.put(key, MappedFile::ReadWrite(page));
+                Ok(())
+            }
+            crate::PutOpt::ReplaceOrCreate => {
+                // the size may change, drop the stale mapping
+                cache.pop(&key);
+                let path = self.key_to_path(&key);
+                std::fs::create_dir_all(path.parent().unwrap())?;
+                let file = std::fs::File::options()
+                    .create(true)
+                    .truncate(true)
+                    .write(true)
+                    .read(true)
+                    .open(path)?;
+                file.set_len(value.len().try_into().unwrap())?;
+                let mut page = unsafe { memmap2::MmapOptions::default().map_mut(&file) }?;
+                page.copy_from_slice(value);
+                cache.put(key, MappedFile::ReadWrite(page));
+                Ok(())
             }
-            crate::PutOpt::ReplaceOrCreate => unimplemented!(),
         }
     }
+}
+
+impl BlobStore for MemMapStore {
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.key_to_path(&key).try_exists().map_err(Error::from)
+    }
+
+    fn meta(&self, key: Key) -> Result<crate::BlobMeta> {
+        LocalFileSystemBlobStore::meta_at(&self.key_to_path(&key))
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: crate::PutOpt) -> Result<()> {
+        self.writable()?;
+        let entry = !matches!(opt, crate::PutOpt::Replace(_));
+        let path = self.key_to_path(&key);
+        FsUsage::track(self.usage.as_ref(), &[&path], || {
+            self.put_mapped(key, value, opt)?;
+            self.flush(&path, true, entry)
+        })
+    }
 
     fn get(&self, key: Key, buf: &mut [u8], opt: crate::GetOpt) -> Result<()> {
         let mut cache = self.cache.borrow_mut();
-        let page = if cache.contains(&key) {
-            cache.get_mut(&key).unwrap()
-        } else {
+        let page = Self::slice(self.load(&mut cache, key)?, opt)?;
+        if buf.len() != page.len() {
+            return Err(Error::from(crate::error::BlobError::RangeError));
+        }
+        buf.copy_from_slice(page);
+        Ok(())
+    }
+
+    fn with_blob(&self, key: Key, opt: crate::GetOpt, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        let mut cache = self.cache.borrow_mut();
+        f(Self::slice(self.load(&mut cache, key)?, opt)?);
+        Ok(())
+    }
+
+    fn get_vectored(&self, key: Key, bufs: &mut [(crate::BlobRange, &mut [u8])]) -> Result<()> {
+        let mut cache = self.cache.borrow_mut();
+        let page = self.load(&mut cache, key)?;
+        crate::check_ranges(
+            page.len(),
+            bufs.iter().map(|(range, buf)| (range, buf.len())),
+        )?;
+        for (range, buf) in bufs.iter_mut() {
+            buf.copy_from_slice(&page[range.clone()]);
+        }
+        Ok(())
+    }
+
+    fn put_vectored(&self, key: Key, bufs: &[(crate::BlobRange, &[u8])]) -> Result<()> {
+        self.writable()?;
+        let path = self.key_to_path(&key);
+        FsUsage::track(self.usage.as_ref(), &[&path], || {
+            let mut cache = self.cache.borrow_mut();
+            let page = self.load(&mut cache, key)?;
+            crate::check_ranges(
+                page.len(),
+                bufs.iter().map(|(range, buf)| (range, buf.len())),
+            )?;
+            for (range, buf) in bufs {
+                page[range.clone()].copy_from_slice(buf);
+            }
+            self.flush(&path, true, false)
+        })
+    }
+
+    /// The blocks of the blob are allocated with `fallocate`.
+    fn create_sized(&self, key: Key, len: usize) -> Result<()> {
+        self.writable()?;
+        let mut cache = self.cache.borrow_mut();
+        let path = self.key_to_path(&key);
+        FsUsage::track(self.usage.as_ref(), &[&path], || {
+            LocalFileSystemBlobStore::create_sized_at(&path, len)?;
             let file = std::fs::File::options()
                 .read(true)
                 .write(true)
-                .open(self.key_to_path(&key))
-                .map_err(|e| {
-                    if e.kind() == std::io::ErrorKind::NotFound {
-                        Error::from(crate::error::BlobError::NotFound)
-                    } else {
-                        Error::from(e)
-                    }
-                })?;
-            let page = unsafe {
-                memmap2::MmapOptions::default()
-                    .map_mut(&file)
-                    .map_err(Error::from)?
-            };
-            cache.put(key, page);
-            cache.get_mut(&key).unwrap()
-        };
-        match opt {
-            crate::GetOpt::All => {
-                if buf.len() != page.len() {
-                    return Err(Error::from(crate::error::BlobError::RangeError));
+                .open(&path)?;
+            let page = unsafe { memmap2::MmapOptions::default().map_mut(&file) }?;
+            cache.put(key, MappedFile::ReadWrite(page));
+            self.flush(&path, true, true)
+        })
+    }
+
+    /// The blocks of the range are deallocated with `FALLOC_FL_PUNCH_HOLE`,
+    /// the mapping of the blob then reads zeros in the range.
+    fn punch_hole(&self, key: Key, range: crate::BlobRange) -> Result<()> {
+        self.writable()?;
+        let path = self.key_to_path(&key);
+        FsUsage::track(self.usage.as_ref(), &[&path], || {
+            LocalFileSystemBlobStore::punch_hole_at(&path, range)?;
+            self.flush(&path, true, false)
+        })
+    }
+
+    fn delete(&self, key: Key, opt: crate::DeleteOpt) -> Result<Option<Vec<u8>>> {
+        self.writable()?;
+        let content = crate::store_impl::helpers::interest(self, key, &opt)?;
+        self.cache.borrow_mut().pop(&key);
+        let path = self.key_to_path(&key);
+        FsUsage::track(self.usage.as_ref(), &[&path], || {
+            std::fs::remove_file(&path).map_err(|e| {
+                if e.kind() == std::io::ErrorKind::NotFound {
+                    Error::from(crate::error::BlobError::NotFound)
+                } else {
+                    Error::from(e)
                 }
-                buf.copy_from_slice(page);
+            })?;
+            self.flush(&path, false, true)
+        })?;
+        Ok(content)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        crate::store_impl::helpers::list_hex_keys(&self.root, self.layout)
+    }
+
+    /// # Error
+    /// - Other: the store is opened for reading only.
+    fn stats(&self) -> Result<crate::StoreStats> {
+        self.usage.as_ref().map(FsUsage::stats).ok_or_else(|| {
+            Error::other(anyhow::anyhow!(
+                "the usage is only kept by the writable handles"
+            ))
+        })
+    }
+
+    /// Copies are made by the kernel, without mapping the blobs.
+    fn copy(&self, src: Key, dst: Key, opt: crate::CopyOpt) -> Result<()> {
+        self.writable()?;
+        let (src_path, dst_path) = (self.key_to_path(&src), self.key_to_path(&dst));
+        if !src_path.try_exists()? {
+            return Err(crate::error::BlobError::NotFound.into());
+        }
+        if src == dst {
+            return match opt {
+                crate::CopyOpt::Create => Err(crate::error::BlobError::AlreadyExists.into()),
+                crate::CopyOpt::ReplaceOrCreate => Ok(()),
+            };
+        }
+        if let crate::CopyOpt::Create = opt {
+            if dst_path.try_exists()? {
+                return Err(crate::error::BlobError::AlreadyExists.into());
             }
-            crate::GetOpt::Range(range) => {
-                if !crate::store_impl::helpers::range_contains(&(0..page.len()), &range) {
-                    return Err(Error::from(crate::error::BlobError::RangeError));
+        }
+        // copy aside and rename, a mapping of the destination keeps its content
+        FsUsage::track(self.usage.as_ref(), &[&dst_path], || {
+            std::fs::create_dir_all(dst_path.parent().unwrap())?;
+            let copy = dst_path.with_extension(format!("cpy{:08x}", rand::random::<u32>()));
+            std::fs::copy(&src_path, &copy)?;
+            self.cache.borrow_mut().pop(&dst);
+            std::fs::rename(copy, &dst_path)?;
+            self.flush(&dst_path, false, true)
+        })
+    }
+
+    fn rename(&self, src: Key, dst: Key, opt: crate::CopyOpt) -> Result<()> {
+        self.writable()?;
+        let (src_path, dst_path) = (self.key_to_path(&src), self.key_to_path(&dst));
+        if !src_path.try_exists()? {
+            return Err(crate::error::BlobError::NotFound.into());
+        }
+        if src == dst {
+            return match opt {
+                crate::CopyOpt::Create => Err(crate::error::BlobError::AlreadyExists.into()),
+                crate::CopyOpt::ReplaceOrCreate => Ok(()),
+            };
+        }
+        FsUsage::track(self.usage.as_ref(), &[&src_path, &dst_path], || {
+            std::fs::create_dir_all(dst_path.parent().unwrap())?;
+            match opt {
+                // linking fails if the destination exists, unlike renaming
+                crate::CopyOpt::Create => {
+                    std::fs::hard_link(&src_path, &dst_path).map_err(|e| {
+                        if e.kind() == std::io::ErrorKind::AlreadyExists {
+                            Error::from(crate::error::BlobError::AlreadyExists)
+                        } else {
+                            Error::from(e)
+                        }
+                    })?;
+                    std::fs::remove_file(&src_path).map_err(Error::from)
                 }
-                if buf.len() != range.len() {
-                    return Err(Error::from(crate::error::BlobError::RangeError));
+                crate::CopyOpt::ReplaceOrCreate => {
+                    std::fs::rename(&src_path, &dst_path).map_err(Error::from)
                 }
-                buf.copy_from_slice(&page[range]);
             }
+        })?;
+        let mut cache = self.cache.borrow_mut();
+        cache.pop(&dst);
+        // the mapping follows the file
+        if let Some(page) = cache.pop(&src) {
+            cache.put(dst, page);
         }
-        return Ok(());
+        drop(cache);
+        self.flush(&src_path, false, true)?;
+        self.flush(&dst_path, false, true)
     }
+}
 
-    fn delete(&self, key: Key, opt: crate::DeleteOpt) -> Result<Option<Vec<u8>>> {
-        match opt {
-            crate::DeleteOpt::Interest(_) => unimplemented!(),
-            crate::DeleteOpt::Discard => {
-                self.cache.borrow_mut().pop(&key);
-                let path = self.key_to_path(&key);
-                std::fs::remove_file(path).map_err(|e| {
-                    if e.kind() == std::io::ErrorKind::NotFound {
-                        Error::from(crate::error::BlobError::NotFound)
-                    } else {
-                        Error::from(e)
-                    }
-                })?;
-                return Ok(None);
+impl BucketStore for MemMapStore {
+    type Bucket<'a> = MemMapStore;
+
+    fn create_bucket(&self, name: &str) -> Result<Self::Bucket<'_>> {
+        self.writable()?;
+        let path = crate::store_impl::helpers::create_bucket_dir(&self.root, name)?;
+        self.flush(&path, false, true)?;
+        self.open_bucket(path)
+    }
+
+    fn bucket(&self, name: &str) -> Result<Self::Bucket<'_>> {
+        let path = crate::store_impl::helpers::bucket_dir(&self.root, name)?;
+        self.open_bucket(path)
+    }
+
+    fn list_buckets(&self) -> Result<Vec<String>> {
+        crate::store_impl::helpers::list_bucket_dirs(&self.root)
+    }
+
+    fn drop_bucket(&self, name: &str) -> Result<()> {
+        self.writable()?;
+        let drop = || crate::store_impl::helpers::drop_bucket_dir(&self.root, name);
+        match &self.usage {
+            Some(usage) => usage.drop_bucket(
+                &crate::store_impl::helpers::bucket_dir(&self.root, name)?,
+                drop,
+            ),
+            None => drop(),
+        }
+    }
+}
+
+/// Variable-length keys are read and written with plain file I/O, only the
+/// fixed keys are mapped and cached.
+impl VarKeyBlobStore for MemMapStore {
+    fn max_key_len(&self) -> usize {
+        self.max_key_len
+    }
+
+    fn contains_var(&self, key: &[u8]) -> Result<bool> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.contains(key),
+            VarKey::Var(key) => crate::store_impl::helpers::var_key_path(&self.root, key)
+                .try_exists()
+                .map_err(Error::from),
+        }
+    }
+
+    fn meta_var(&self, key: &[u8]) -> Result<crate::BlobMeta> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.meta(key),
+            VarKey::Var(key) => LocalFileSystemBlobStore::meta_at(
+                &crate::store_impl::helpers::var_key_path(&self.root, key),
+            ),
+        }
+    }
+
+    fn put_var(&self, key: &[u8], value: &[u8], opt: crate::PutOpt) -> Result<()> {
+        self.writable()?;
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.put(key, value, opt),
+            VarKey::Var(key) => {
+                let path = crate::store_impl::helpers::var_key_path(&self.root, key);
+                let entry = !matches!(opt, crate::PutOpt::Replace(_));
+                FsUsage::track(self.usage.as_ref(), &[&path], || {
+                    LocalFileSystemBlobStore::put_file(&path, value, opt, false)?;
+                    self.flush(&path, true, entry)
+                })
+            }
+        }
+    }
+
+    fn get_var(&self, key: &[u8], buf: &mut [u8], opt: crate::GetOpt) -> Result<()> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.get(key, buf, opt),
+            VarKey::Var(key) => LocalFileSystemBlobStore::get_file(
+                &crate::store_impl::helpers::var_key_path(&self.root, key),
+                buf,
+                opt,
+                false,
+            ),
+        }
+    }
+
+    fn delete_var(&self, key: &[u8], opt: crate::DeleteOpt) -> Result<Option<Vec<u8>>> {
+        self.writable()?;
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.delete(key, opt),
+            VarKey::Var(key) => {
+                let path = crate::store_impl::helpers::var_key_path(&self.root, key);
+                FsUsage::track(self.usage.as_ref(), &[&path], || {
+                    let content = LocalFileSystemBlobStore::delete_at(&path, opt, false)?;
+                    self.flush(&path, false, true)?;
+                    Ok(content)
+                })
             }
         }
     }
@@ -0,0 +1,174 @@
+use dashmap::{mapref::entry::Entry, DashMap};
+use parking_lot::Mutex;
+
+use crate::{
+    error::{BlobError, Error, Result},
+    BlobRange, BlobStore, DeleteOpt, GetOpt, Key, PutOpt, StoreStats,
+};
+
+/// A store keeping all the blobs in memory, nothing is persisted.
+#[derive(Default)]
+pub struct MemoryBlobStore {
+    blobs: DashMap<Key, Vec<u8>>,
+    /// updated under the lock of the shard of the blob written
+    stats: Mutex<StoreStats>,
+}
+
+impl MemoryBlobStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// account for a blob of `old` bytes becoming one of `new` bytes
+    fn account(&self, old: Option<usize>, new: Option<usize>) {
+        let usage = |len: Option<usize>| StoreStats {
+            blobs: len.is_some().into(),
+            logical_bytes: len.unwrap_or(0) as u64,
+            physical_bytes: len.unwrap_or(0) as u64,
+        };
+        let mut stats = self.stats.lock();
+        stats.sub(&usage(old));
+        stats.add(&usage(new));
+    }
+
+    /// the part of a blob selected by `opt`
+    fn slice(blob: &[u8], opt: GetOpt) -> Result<&[u8]> {
+        match opt {
+            GetOpt::All => Ok(blob),
+            GetOpt::Range(range) => {
+                if !crate::store_impl::helpers::range_contains(&(0..blob.len()), &range) {
+                    return Err(Error::from(BlobError::RangeError));
+                }
+                Ok(&blob[range])
+            }
+        }
+    }
+}
+
+impl BlobStore for MemoryBlobStore {
+    fn contains(&self, key: Key) -> Result<bool> {
+        Ok(self.blobs.contains_key(&key))
+    }
+
+    fn meta(&self, key: Key) -> Result<crate::BlobMeta> {
+        let blob = self.blobs.get(&key).ok_or(BlobError::NotFound)?;
+        Ok(crate::BlobMeta {
+            size: blob.len(),
+            ..Default::default()
+        })
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        match (self.blobs.entry(key), opt) {
+            (Entry::Occupied(_), PutOpt::Create) => Err(BlobError::AlreadyExists.into()),
+            (Entry::Vacant(_), PutOpt::Replace(_)) => Err(BlobError::NotFound.into()),
+            (Entry::Occupied(mut entry), PutOpt::Replace(range)) => {
+                let blob = entry.get_mut();
+                if !crate::store_impl::helpers::range_contains(&(0..blob.len()), &range)
+                    || value.len() != range.len()
+                {
+                    return Err(BlobError::RangeError.into());
+                }
+                blob[range].copy_from_slice(value);
+                Ok(())
+            }
+            (Entry::Occupied(mut entry), PutOpt::ReplaceOrCreate) => {
+                let old = entry.insert(value.to_vec());
+                self.account(Some(old.len()), Some(value.len()));
+                Ok(())
+            }
+            (Entry::Vacant(entry), _) => {
+                entry.insert(value.to_vec());
+                self.account(None, Some(value.len()));
+                Ok(())
+            }
+        }
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        let blob = self.blobs.get(&key).ok_or(BlobError::NotFound)?;
+        let blob = Self::slice(&blob, opt)?;
+        if buf.len() != blob.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        buf.copy_from_slice(blob);
+        Ok(())
+    }
+
+    /// The blob is lent in place, `f` holds a read lock on its shard.
+    fn with_blob(&self, key: Key, opt: GetOpt, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        let blob = self.blobs.get(&key).ok_or(BlobError::NotFound)?;
+        f(Self::slice(&blob, opt)?);
+        Ok(())
+    }
+
+    fn get_vectored(&self, key: Key, bufs: &mut [(BlobRange, &mut [u8])]) -> Result<()> {
+        let blob = self.blobs.get(&key).ok_or(BlobError::NotFound)?;
+        crate::check_ranges(
+            blob.len(),
+            bufs.iter().map(|(range, buf)| (range, buf.len())),
+        )?;
+        for (range, buf) in bufs.iter_mut() {
+            buf.copy_from_slice(&blob[range.clone()]);
+        }
+        Ok(())
+    }
+
+    /// The ranges are written under one lock, readers see all of them or none.
+    fn put_vectored(&self, key: Key, bufs: &[(BlobRange, &[u8])]) -> Result<()> {
+        let mut blob = self.blobs.get_mut(&key).ok_or(BlobError::NotFound)?;
+        crate::check_ranges(
+            blob.len(),
+            bufs.iter().map(|(range, buf)| (range, buf.len())),
+        )?;
+        for (range, buf) in bufs {
+            blob[range.clone()].copy_from_slice(buf);
+        }
+        Ok(())
+    }
+
+    fn punch_hole(&self, key: Key, range: BlobRange) -> Result<()> {
+        let mut blob = self.blobs.get_mut(&key).ok_or(BlobError::NotFound)?;
+        if !crate::store_impl::helpers::range_contains(&(0..blob.len()), &range) {
+            return Err(BlobError::RangeError.into());
+        }
+        blob[range].fill(0);
+        Ok(())
+    }
+
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        let range = match opt {
+            DeleteOpt::Interest(range) => Some(range),
+            DeleteOpt::Discard => None,
+        };
+        // a bad range keeps the blob
+        let (_, blob) = self
+            .blobs
+            .remove_if(&key, |_, blob| {
+                let remove = range.as_ref().is_none_or(|range| {
+                    crate::store_impl::helpers::range_contains(&(0..blob.len()), range)
+                });
+                if remove {
+                    self.account(Some(blob.len()), None);
+                }
+                remove
+            })
+            .ok_or_else(|| {
+                if self.blobs.contains_key(&key) {
+                    BlobError::RangeError
+                } else {
+                    BlobError::NotFound
+                }
+            })?;
+        Ok(range.map(|range| blob[range].to_vec()))
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        Ok(self.blobs.iter().map(|entry| *entry.key()).collect())
+    }
+
+    /// The blobs take their size in memory.
+    fn stats(&self) -> Result<StoreStats> {
+        Ok(*self.stats.lock())
+    }
+}
@@ -1,16 +1,47 @@
 // mod cache;
+mod checksum;
+#[cfg(feature = "compress")]
+mod compressed;
+mod config;
+#[cfg(feature = "dedup")]
+mod dedup;
+#[cfg(feature = "encrypt")]
+mod encrypted;
+mod expiring;
+mod fs_usage;
 mod local_filesystem;
 #[cfg(feature = "memmap")]
 mod mapped_file;
+mod memory;
+mod named;
+mod quota;
+mod sharded;
 #[cfg(feature = "sqlite")]
 mod sqlite;
+#[cfg(feature = "io_uring")]
+mod uring;
+mod versioned;
 
 pub mod prelude {
+    pub use super::checksum::*;
+    #[cfg(feature = "compress")]
+    pub use super::compressed::*;
+    pub use super::config::*;
+    #[cfg(feature = "dedup")]
+    pub use super::dedup::*;
+    #[cfg(feature = "encrypt")]
+    pub use super::encrypted::*;
+    pub use super::expiring::*;
     pub use super::local_filesystem::*;
     #[cfg(feature = "memmap")]
     pub use super::mapped_file::*;
+    pub use super::memory::*;
+    pub use super::named::*;
+    pub use super::quota::*;
+    pub use super::sharded::*;
     #[cfg(feature = "sqlite")]
     pub use super::sqlite::*;
+    pub use super::versioned::*;
 }
 
 mod helpers {
@@ -22,4 +53,249 @@ mod helpers {
     ) -> bool {
         this.start <= that.start && this.end >= that.end
     }
+
+    /// path of the file of a blob in a directory tree laid out as `layout`
+    pub(crate) fn key_path(
+        root: &std::path::Path,
+        key: &crate::Key,
+        layout: super::config::Layout,
+    ) -> std::path::PathBuf {
+        let key_hex = hex::encode(key);
+        match layout {
+            super::config::Layout::Nested => {
+                let (prefix, suffix) = key_hex.split_at(key_hex.len() / 2);
+                root.join(prefix).join(suffix)
+            }
+            super::config::Layout::Flat => root.join(key_hex),
+        }
+    }
+
+    /// list the keys of a directory tree laid out as `layout`, skipping any
+    /// entry that is not a key path
+    pub(crate) fn list_hex_keys(
+        root: &std::path::Path,
+        layout: super::config::Layout,
+    ) -> crate::error::Result<Vec<crate::Key>> {
+        const HALF: usize = std::mem::size_of::<crate::Key>();
+        let mut keys = Vec::new();
+        if layout == super::config::Layout::Flat {
+            for file in std::fs::read_dir(root)? {
+                let file = file?;
+                let mut key = crate::Key::default();
+                if let Some(name) = file.file_name().to_str() {
+                    if name.len() == 2 * HALF
+                        && file.file_type()?.is_file()
+                        && hex::decode_to_slice(name, &mut key).is_ok()
+                    {
+                        keys.push(key);
+                    }
+                }
+            }
+            return Ok(keys);
+        }
+        for dir in std::fs::read_dir(root)? {
+            let dir = dir?;
+            let prefix = dir.file_name();
+            let prefix = match prefix.to_str() {
+                Some(prefix) if prefix.len() == HALF && dir.file_type()?.is_dir() => prefix,
+                _ => continue,
+            };
+            for file in std::fs::read_dir(dir.path())? {
+                let file = file?;
+                let suffix = file.file_name();
+                let Some(suffix) = suffix.to_str() else {
+                    continue;
+                };
+                let mut key = crate::Key::default();
+                if suffix.len() == HALF
+                    && file.file_type()?.is_file()
+                    && hex::decode_to_slice(format!("{prefix}{suffix}"), &mut key).is_ok()
+                {
+                    keys.push(key);
+                }
+            }
+        }
+        Ok(keys)
+    }
+
+    pub(crate) fn check_bucket_name(name: &str) -> crate::error::Result<()> {
+        let valid = (1..=63).contains(&name.len())
+            && !name.starts_with('.')
+            && name
+                .bytes()
+                .all(|b| matches!(b, b'a'..=b'z' | b'0'..=b'9' | b'.' | b'_' | b'-'));
+        if valid {
+            Ok(())
+        } else {
+            Err(crate::error::BucketError::InvalidName.into())
+        }
+    }
+
+    /// directories of the buckets of a store rooted at `root`
+    pub(crate) fn bucket_root(root: &std::path::Path) -> std::path::PathBuf {
+        root.join("buckets")
+    }
+
+    pub(crate) fn create_bucket_dir(
+        root: &std::path::Path,
+        name: &str,
+    ) -> crate::error::Result<std::path::PathBuf> {
+        check_bucket_name(name)?;
+        let buckets = bucket_root(root);
+        std::fs::create_dir_all(&buckets)?;
+        let path = buckets.join(name);
+        std::fs::create_dir(&path).map_err(|e| match e.kind() {
+            std::io::ErrorKind::AlreadyExists => crate::error::BucketError::AlreadyExists.into(),
+            _ => crate::error::Error::from(e),
+        })?;
+        Ok(path)
+    }
+
+    pub(crate) fn bucket_dir(
+        root: &std::path::Path,
+        name: &str,
+    ) -> crate::error::Result<std::path::PathBuf> {
+        let path = bucket_root(root).join(name);
+        if check_bucket_name(name).is_err() || !path.is_dir() {
+            return Err(crate::error::BucketError::NotFound.into());
+        }
+        Ok(path)
+    }
+
+    pub(crate) fn list_bucket_dirs(root: &std::path::Path) -> crate::error::Result<Vec<String>> {
+        let buckets = bucket_root(root);
+        if !buckets.exists() {
+            return Ok(vec![]);
+        }
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(buckets)? {
+            let entry = entry?;
+            if let Some(name) = entry.file_name().to_str() {
+                // dotted names are buckets being dropped
+                if check_bucket_name(name).is_ok() && entry.file_type()?.is_dir() {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        Ok(names)
+    }
+
+    /// Rename the bucket out of the way first so that it disappears at once,
+    /// then remove its tree.
+    pub(crate) fn drop_bucket_dir(root: &std::path::Path, name: &str) -> crate::error::Result<()> {
+        let path = bucket_dir(root, name)?;
+        let trash = bucket_root(root).join(format!(".{name}.{}", std::process::id()));
+        std::fs::rename(&path, &trash)?;
+        std::fs::remove_dir_all(trash).map_err(crate::error::Error::from)
+    }
+
+    /// Advisory lock on the root of a store, released when dropped.
+    ///
+    /// The lock is a `flock` on `root/store.lock`, so it conflicts between
+    /// handles of the same process too.
+    pub(crate) struct StoreLock {
+        _file: std::fs::File,
+    }
+
+    impl StoreLock {
+        const FILE: &'static str = "store.lock";
+
+        /// lock `root` exclusively, or shared with the other shared handles
+        ///
+        /// # Error
+        /// - Locked: another handle holds a conflicting lock.
+        pub(crate) fn acquire(
+            root: &std::path::Path,
+            exclusive: bool,
+        ) -> crate::error::Result<Self> {
+            use std::os::fd::AsRawFd;
+            let path = root.join(Self::FILE);
+            let file = match std::fs::File::options()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(&path)
+            {
+                Ok(file) => file,
+                // a read-only store may not be able to create the file
+                Err(e) if !exclusive => std::fs::File::open(&path).map_err(|_| e)?,
+                Err(e) => return Err(e.into()),
+            };
+            let op = if exclusive {
+                libc::LOCK_EX
+            } else {
+                libc::LOCK_SH
+            };
+            // SAFETY: plain system call on an open file descriptor
+            if unsafe { libc::flock(file.as_raw_fd(), op | libc::LOCK_NB) } != 0 {
+                let e = std::io::Error::last_os_error();
+                if e.kind() == std::io::ErrorKind::WouldBlock {
+                    return Err(crate::error::Error::Locked);
+                }
+                return Err(e.into());
+            }
+            Ok(Self { _file: file })
+        }
+    }
+
+    /// The content a delete with `opt` returns, read through the wrapper `store`
+    /// before it deletes the blob from its inner store with `DeleteOpt::Discard`.
+    pub(crate) fn interest<S: crate::BlobStore + ?Sized>(
+        store: &S,
+        key: crate::Key,
+        opt: &crate::DeleteOpt,
+    ) -> crate::error::Result<Option<Vec<u8>>> {
+        match opt {
+            crate::DeleteOpt::Interest(range) => store
+                .get_owned(key, crate::GetOpt::Range(range.clone()))
+                .map(Some),
+            crate::DeleteOpt::Discard => Ok(None),
+        }
+    }
+
+    /// A variable-length key, classified for dispatch.
+    pub(crate) enum VarKey<'k> {
+        /// a key of the size of `Key`, served by the fixed key path
+        Fixed(crate::Key),
+        Var(&'k [u8]),
+    }
+
+    pub(crate) fn var_key(key: &[u8], max_key_len: usize) -> crate::error::Result<VarKey<'_>> {
+        if let Ok(key) = crate::Key::try_from(key) {
+            return Ok(VarKey::Fixed(key));
+        }
+        if key.is_empty() || key.len() > max_key_len {
+            return Err(crate::error::BlobError::InvalidKey.into());
+        }
+        Ok(VarKey::Var(key))
+    }
+
+    /// Path of a variable-length key under `root/varkeys`.
+    ///
+    /// Bytes outside of `[A-Za-z0-9_-]` are escaped as `%XX`, and the escaped
+    /// key is split into segments that fit in a file name, directory segments
+    /// ending with `+` so that they never clash with the file of a shorter key.
+    /// A first directory level taken from the hash of the key spreads the keys.
+    pub(crate) fn var_key_path(root: &std::path::Path, key: &[u8]) -> std::path::PathBuf {
+        const SEGMENT_LEN: usize = 192;
+        let mut path = root.join("varkeys");
+        path.push(format!("{:02x}", xxhash_rust::xxh3::xxh3_64(key) as u8));
+        let mut segment = String::with_capacity(SEGMENT_LEN + 1);
+        for byte in key {
+            let escaped = if byte.is_ascii_alphanumeric() || matches!(byte, b'_' | b'-') {
+                char::from(*byte).to_string()
+            } else {
+                format!("%{byte:02X}")
+            };
+            if segment.len() + escaped.len() > SEGMENT_LEN {
+                segment.push('+');
+                path.push(&segment);
+                segment.clear();
+            }
+            segment.push_str(&escaped);
+        }
+        path.push(segment);
+        path
+    }
 }
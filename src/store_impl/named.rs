@@ -0,0 +1,254 @@
+use anyhow::anyhow;
+
+use crate::{
+    error::{BlobError, Error, Result},
+    store_impl::helpers::VarKey,
+    BlobMeta, BlobRange, BlobStore, DeleteOpt, GetOpt, Key, PutOpt, VarKeyBlobStore,
+};
+
+const MAGIC: [u8; 4] = *b"BSN1";
+/// `magic | name length`, followed by the name
+const PREFIX_LEN: usize = 8;
+
+/// Store blobs under the hash of their name, and detect hash collisions.
+///
+/// The name is stored in front of the blob and verified on every access: a
+/// blob whose key is taken by another name is reported as
+/// `BlobError::KeyCollision` instead of being read or overwritten. A fixed key
+/// is its own name and is stored under itself.
+///
+/// Names are hashed like `str::as_key`, so `put_var(b"name", ..)` stores the
+/// blob under `"name".as_key()` in the inner store.
+pub struct NamedStore<S>
+where
+    S: BlobStore,
+{
+    store: S,
+    max_key_len: usize,
+}
+
+impl<S> NamedStore<S>
+where
+    S: BlobStore,
+{
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            max_key_len: crate::DEFAULT_MAX_KEY_LEN,
+        }
+    }
+
+    /// Use names of up to `max_key_len` bytes.
+    pub fn with_max_key_len(mut self, max_key_len: usize) -> Self {
+        self.max_key_len = max_key_len;
+        self
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// List the names of all the blobs, in no particular order.
+    pub fn names(&self) -> Result<Vec<Vec<u8>>> {
+        self.store
+            .keys()?
+            .into_iter()
+            .map(|key| self.read_name(key))
+            .collect()
+    }
+
+    fn locate<'k>(&self, key: &'k [u8]) -> Result<(Key, &'k [u8])> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(fixed) => Ok((fixed, key)),
+            VarKey::Var(name) => Ok((crate::hash_key(name), name)),
+        }
+    }
+
+    fn read_name(&self, key: Key) -> Result<Vec<u8>> {
+        let mut prefix = [0_u8; PREFIX_LEN];
+        self.store
+            .get(key, &mut prefix, GetOpt::Range(0..PREFIX_LEN))?;
+        if prefix[0..4] != MAGIC {
+            return Err(Error::other(anyhow!("not a named blob")));
+        }
+        let len = u32::from_le_bytes(prefix[4..8].try_into().unwrap()) as usize;
+        self.store
+            .get_owned(key, GetOpt::Range(PREFIX_LEN..PREFIX_LEN + len))
+    }
+
+    /// check that the blob under `key` is named `name`, return the offset of its content
+    fn verify(&self, key: Key, name: &[u8]) -> Result<usize> {
+        if self.read_name(key)? != name {
+            return Err(BlobError::KeyCollision.into());
+        }
+        Ok(PREFIX_LEN + name.len())
+    }
+
+    /// content range of a blob in the inner store
+    fn stored_range(&self, key: Key, offset: usize, range: &BlobRange) -> Result<BlobRange> {
+        let size = self.store.meta(key)?.size - offset;
+        if !crate::store_impl::helpers::range_contains(&(0..size), range) {
+            return Err(BlobError::RangeError.into());
+        }
+        Ok(offset + range.start..offset + range.end)
+    }
+
+    fn contains_named(&self, key: Key, name: &[u8]) -> Result<bool> {
+        match self.verify(key, name) {
+            Ok(_) => Ok(true),
+            Err(Error::Blob(BlobError::NotFound | BlobError::KeyCollision)) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn meta_named(&self, key: Key, name: &[u8]) -> Result<BlobMeta> {
+        let offset = self.verify(key, name)?;
+        let size = self.store.meta(key)?.size - offset;
+        Ok(BlobMeta {
+            size,
+            ..Default::default()
+        })
+    }
+
+    fn put_named(&self, key: Key, name: &[u8], value: &[u8], opt: PutOpt) -> Result<()> {
+        match opt {
+            PutOpt::Create | PutOpt::ReplaceOrCreate => {
+                if let PutOpt::ReplaceOrCreate = opt {
+                    // never overwrite the blob of another name
+                    match self.verify(key, name) {
+                        Ok(_) | Err(Error::Blob(BlobError::NotFound)) => {}
+                        Err(e) => return Err(e),
+                    }
+                }
+                let mut blob = Vec::with_capacity(PREFIX_LEN + name.len() + value.len());
+                blob.extend_from_slice(&MAGIC);
+                blob.extend_from_slice(&u32::try_from(name.len()).unwrap().to_le_bytes());
+                blob.extend_from_slice(name);
+                blob.extend_from_slice(value);
+                match self.store.put(key, &blob, opt) {
+                    Err(Error::Blob(BlobError::AlreadyExists)) => {
+                        self.verify(key, name)?;
+                        Err(BlobError::AlreadyExists.into())
+                    }
+                    result => result,
+                }
+            }
+            PutOpt::Replace(range) => {
+                let offset = self.verify(key, name)?;
+                if value.len() != range.len() {
+                    return Err(BlobError::RangeError.into());
+                }
+                let range = self.stored_range(key, offset, &range)?;
+                self.store.put(key, value, PutOpt::Replace(range))
+            }
+        }
+    }
+
+    fn get_named(&self, key: Key, name: &[u8], buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        let offset = self.verify(key, name)?;
+        let range = match opt {
+            GetOpt::All => offset..self.store.meta(key)?.size,
+            GetOpt::Range(range) => self.stored_range(key, offset, &range)?,
+        };
+        if buf.len() != range.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        self.store.get(key, buf, GetOpt::Range(range))
+    }
+
+    fn delete_named(&self, key: Key, name: &[u8], opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        let offset = self.verify(key, name)?;
+        let content = match opt {
+            DeleteOpt::Interest(range) => {
+                let range = self.stored_range(key, offset, &range)?;
+                Some(self.store.get_owned(key, GetOpt::Range(range))?)
+            }
+            DeleteOpt::Discard => None,
+        };
+        self.store.delete(key, DeleteOpt::Discard)?;
+        Ok(content)
+    }
+}
+
+impl<S> BlobStore for NamedStore<S>
+where
+    S: BlobStore,
+{
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.contains_named(key, &key)
+    }
+
+    fn meta(&self, key: Key) -> Result<BlobMeta> {
+        self.meta_named(key, &key)
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        self.put_named(key, &key, value, opt)
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        self.get_named(key, &key, buf, opt)
+    }
+
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        self.delete_named(key, &key, opt)
+    }
+
+    /// Keys of the blobs named by a fixed key, use `names` to list all the names.
+    fn keys(&self) -> Result<Vec<Key>> {
+        let mut keys = self.store.keys()?;
+        let mut names = keys
+            .iter()
+            .map(|key| self.read_name(*key))
+            .collect::<Result<Vec<_>>>()?
+            .into_iter();
+        keys.retain(|key| names.next().unwrap() == key);
+        Ok(keys)
+    }
+
+    fn stats(&self) -> Result<crate::StoreStats> {
+        self.store.stats()
+    }
+
+    fn is_shared(&self) -> bool {
+        self.store.is_shared()
+    }
+}
+
+impl<S> VarKeyBlobStore for NamedStore<S>
+where
+    S: BlobStore,
+{
+    fn max_key_len(&self) -> usize {
+        self.max_key_len
+    }
+
+    fn contains_var(&self, key: &[u8]) -> Result<bool> {
+        let (key, name) = self.locate(key)?;
+        self.contains_named(key, name)
+    }
+
+    fn meta_var(&self, key: &[u8]) -> Result<BlobMeta> {
+        let (key, name) = self.locate(key)?;
+        self.meta_named(key, name)
+    }
+
+    fn put_var(&self, key: &[u8], value: &[u8], opt: PutOpt) -> Result<()> {
+        let (key, name) = self.locate(key)?;
+        self.put_named(key, name, value, opt)
+    }
+
+    fn get_var(&self, key: &[u8], buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        let (key, name) = self.locate(key)?;
+        self.get_named(key, name, buf, opt)
+    }
+
+    fn delete_var(&self, key: &[u8], opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        let (key, name) = self.locate(key)?;
+        self.delete_named(key, name, opt)
+    }
+}
@@ -0,0 +1,168 @@
+use parking_lot::Mutex;
+
+use crate::{
+    error::{BlobError, Error, Result},
+    BlobMeta, BlobRange, BlobStore, CopyOpt, DeleteOpt, GetOpt, Key, PutOpt, StoreStats,
+};
+
+/// Limits of a `QuotaStore`, `None` is unlimited.
+#[derive(Debug, Clone, Default)]
+pub struct Quota {
+    /// Maximum of `StoreStats::logical_bytes`.
+    pub max_bytes: Option<u64>,
+    pub max_blobs: Option<u64>,
+}
+
+/// Limit the space used by the inner store with a quota.
+///
+/// The usage is the one the inner store keeps, see `BlobStore::stats`, so it
+/// covers its blobs with variable-length keys and its buckets too. A write
+/// that would grow the usage over the quota fails with
+/// `BlobError::QuotaExceeded` before it reaches the inner store; writes that
+/// shrink it are always allowed. The writes that can grow the usage are
+/// serialized, so that each one is checked against the usage left by the
+/// previous ones.
+///
+/// The writes made through another handle of the inner store are not
+/// checked, so a shared store, see `BlobStore::is_shared`, is refused.
+pub struct QuotaStore<S>
+where
+    S: BlobStore,
+{
+    store: S,
+    quota: Quota,
+    /// held by the writes that can grow the usage
+    write_lock: Mutex<()>,
+}
+
+impl<S> QuotaStore<S>
+where
+    S: BlobStore,
+{
+    /// # Error
+    /// - Other: the inner store is shared or doesn't keep its usage.
+    pub fn new(store: S, quota: Quota) -> Result<Self> {
+        if store.is_shared() {
+            return Err(Error::other(anyhow::anyhow!(
+                "the usage of a shared store can't be accounted for"
+            )));
+        }
+        store.stats()?;
+        Ok(Self {
+            store,
+            quota,
+            write_lock: Mutex::new(()),
+        })
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    pub fn quota(&self) -> &Quota {
+        &self.quota
+    }
+
+    /// check that `key` can become a blob of `len` bytes
+    fn reserve(&self, key: Key, len: usize) -> Result<()> {
+        let stats = self.store.stats()?;
+        let old = match self.store.meta(key) {
+            Ok(meta) => Some(meta.size as u64),
+            Err(Error::Blob(BlobError::NotFound)) => None,
+            Err(e) => return Err(e),
+        };
+        let blobs = stats.blobs + u64::from(old.is_none());
+        let bytes = (stats.logical_bytes + len as u64).saturating_sub(old.unwrap_or(0));
+        let exceeds = |max: Option<u64>, new: u64, current: u64| {
+            max.is_some_and(|max| new > max && new > current)
+        };
+        if exceeds(self.quota.max_blobs, blobs, stats.blobs)
+            || exceeds(self.quota.max_bytes, bytes, stats.logical_bytes)
+        {
+            return Err(BlobError::QuotaExceeded.into());
+        }
+        Ok(())
+    }
+}
+
+impl<S> BlobStore for QuotaStore<S>
+where
+    S: BlobStore,
+{
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.store.contains(key)
+    }
+
+    fn meta(&self, key: Key) -> Result<BlobMeta> {
+        self.store.meta(key)
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        if matches!(opt, PutOpt::Replace(_)) {
+            return self.store.put(key, value, opt);
+        }
+        let _guard = self.write_lock.lock();
+        self.reserve(key, value.len())?;
+        self.store.put(key, value, opt)
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        self.store.get(key, buf, opt)
+    }
+
+    fn get_owned(&self, key: Key, opt: GetOpt) -> Result<Vec<u8>> {
+        self.store.get_owned(key, opt)
+    }
+
+    fn with_blob(&self, key: Key, opt: GetOpt, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.store.with_blob(key, opt, f)
+    }
+
+    fn get_vectored(&self, key: Key, bufs: &mut [(BlobRange, &mut [u8])]) -> Result<()> {
+        self.store.get_vectored(key, bufs)
+    }
+
+    fn put_vectored(&self, key: Key, bufs: &[(BlobRange, &[u8])]) -> Result<()> {
+        self.store.put_vectored(key, bufs)
+    }
+
+    fn create_sized(&self, key: Key, len: usize) -> Result<()> {
+        let _guard = self.write_lock.lock();
+        self.reserve(key, len)?;
+        self.store.create_sized(key, len)
+    }
+
+    fn punch_hole(&self, key: Key, range: BlobRange) -> Result<()> {
+        self.store.punch_hole(key, range)
+    }
+
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        self.store.delete(key, opt)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.store.keys()
+    }
+
+    fn stats(&self) -> Result<StoreStats> {
+        self.store.stats()
+    }
+
+    fn is_shared(&self) -> bool {
+        self.store.is_shared()
+    }
+
+    fn copy(&self, src: Key, dst: Key, opt: CopyOpt) -> Result<()> {
+        let _guard = self.write_lock.lock();
+        self.reserve(dst, self.store.meta(src)?.size)?;
+        self.store.copy(src, dst, opt)
+    }
+
+    fn rename(&self, src: Key, dst: Key, opt: CopyOpt) -> Result<()> {
+        self.store.rename(src, dst, opt)
+    }
+}
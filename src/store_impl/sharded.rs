@@ -0,0 +1,258 @@
+use std::{collections::BTreeMap, num::NonZeroUsize};
+
+use anyhow::anyhow;
+
+use crate::{
+    error::{BlobError, Error, Result},
+    BlobRange, BlobStore, DeleteOpt, GetOpt, Key, PutOpt,
+};
+
+pub type ShardId = String;
+
+/// Blobs and bytes moved between shards by a rebalance.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RebalanceStats {
+    pub moved_blobs: usize,
+    pub moved_bytes: usize,
+}
+
+/// Distribute blobs over several child stores with consistent hashing.
+///
+/// Every shard owns `virtual_nodes` points on a hash ring, and a key belongs to
+/// the first point found clockwise from the hash of the key. Adding or removing
+/// a shard therefore only changes the owner of the keys next to its points.
+pub struct ShardedStore<S>
+where
+    S: BlobStore,
+{
+    shards: BTreeMap<ShardId, S>,
+    ring: BTreeMap<u64, ShardId>,
+    virtual_nodes: NonZeroUsize,
+}
+
+impl<S> ShardedStore<S>
+where
+    S: BlobStore,
+{
+    pub const DEFAULT_VIRTUAL_NODES: usize = 128;
+
+    /// create an empty store with `virtual_nodes` ring points per shard
+    pub fn new(virtual_nodes: NonZeroUsize) -> Self {
+        Self {
+            shards: BTreeMap::new(),
+            ring: BTreeMap::new(),
+            virtual_nodes,
+        }
+    }
+
+    /// Attach a shard without moving any blob.
+    ///
+    /// This is meant for reopening a sharded store whose shards already hold
+    /// their blobs, use `add_shard` to grow a live store.
+    pub fn attach(&mut self, id: impl Into<ShardId>, store: S) -> Result<()> {
+        let id = id.into();
+        if self.shards.contains_key(&id) {
+            return Err(Error::other(anyhow!("shard \"{id}\" already exists")));
+        }
+        (0..self.virtual_nodes.get()).for_each(|vnode| {
+            self.ring
+                .entry(Self::vnode_hash(&id, vnode))
+                .or_insert_with(|| id.clone());
+        });
+        self.shards.insert(id, store);
+        Ok(())
+    }
+
+    /// Add a shard and move the blobs it now owns from the other shards. The
+    /// blobs misplaced on the other shards beforehand stay where they are, see
+    /// `rebalance`.
+    pub fn add_shard(&mut self, id: impl Into<ShardId>, store: S) -> Result<RebalanceStats> {
+        let id = id.into();
+        self.attach(id.clone(), store)?;
+        let mut stats = RebalanceStats::default();
+        for (_, from) in self.shards.iter().filter(|(from_id, _)| **from_id != id) {
+            for key in from.keys()? {
+                if self.owner(&key) == Some(&id) {
+                    let size = Self::move_blob(from, &self.shards[&id], key)?;
+                    stats.moved_blobs += 1;
+                    stats.moved_bytes += size;
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Remove a shard, moving all of its blobs to their new owners.
+    ///
+    /// # Error
+    /// - Other: the shard doesn't exist, or it is the last shard and still holds blobs.
+    pub fn remove_shard(&mut self, id: &str) -> Result<(S, RebalanceStats)> {
+        let store = self
+            .shards
+            .remove(id)
+            .ok_or_else(|| Error::other(anyhow!("shard \"{id}\" not found")))?;
+        self.ring.retain(|_, owner| owner != id);
+        let mut stats = RebalanceStats::default();
+        let result = store.keys().and_then(|keys| {
+            keys.into_iter().try_for_each(|key| {
+                let to = self.route(&key)?;
+                stats.moved_bytes += Self::move_blob(&store, to, key)?;
+                stats.moved_blobs += 1;
+                Ok(())
+            })
+        });
+        match result {
+            Ok(()) => Ok((store, stats)),
+            Err(e) => {
+                // put the shard back so that the blobs left on it stay reachable
+                self.attach(id, store)?;
+                Err(e)
+            }
+        }
+    }
+
+    /// Move every misplaced blob to its owner, e.g. after an interrupted
+    /// `add_shard` or `remove_shard`, or after attaching shards in a different
+    /// configuration.
+    pub fn rebalance(&mut self) -> Result<RebalanceStats> {
+        let mut stats = RebalanceStats::default();
+        for (from_id, from) in self.shards.iter() {
+            for key in from.keys()? {
+                let owner = self.owner(&key).expect("ring contains at least this shard");
+                if owner != from_id {
+                    stats.moved_bytes += Self::move_blob(from, &self.shards[owner], key)?;
+                    stats.moved_blobs += 1;
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// id of the shard owning the key, if there is any shard
+    pub fn shard_for(&self, key: Key) -> Option<&str> {
+        self.owner(&key).map(String::as_str)
+    }
+
+    pub fn shard(&self, id: &str) -> Option<&S> {
+        self.shards.get(id)
+    }
+
+    pub fn shard_ids(&self) -> impl Iterator<Item = &str> {
+        self.shards.keys().map(String::as_str)
+    }
+
+    fn vnode_hash(id: &str, vnode: usize) -> u64 {
+        xxhash_rust::xxh3::xxh3_64(format!("{id}#{vnode}").as_bytes())
+    }
+
+    fn owner(&self, key: &Key) -> Option<&ShardId> {
+        let hash = xxhash_rust::xxh3::xxh3_64(key);
+        self.ring
+            .range(hash..)
+            .next()
+            .or_else(|| self.ring.iter().next())
+            .map(|(_, id)| id)
+    }
+
+    fn route(&self, key: &Key) -> Result<&S> {
+        self.owner(key)
+            .map(|id| &self.shards[id])
+            .ok_or_else(|| Error::other(anyhow!("sharded store has no shard")))
+    }
+
+    /// copy a blob to another shard and then delete it from the original one,
+    /// return the size of the blob
+    fn move_blob(from: &S, to: &S, key: Key) -> Result<usize> {
+        let data = from.get_owned(key, GetOpt::All)?;
+        match to.put(key, &data, PutOpt::Create) {
+            // left over by an interrupted move, the source is still authoritative
+            Err(Error::Blob(BlobError::AlreadyExists)) => {
+                to.delete(key, DeleteOpt::Discard)?;
+                to.put(key, &data, PutOpt::Create)?;
+            }
+            result => result?,
+        }
+        from.delete(key, DeleteOpt::Discard)?;
+        Ok(data.len())
+    }
+}
+
+impl<S> Default for ShardedStore<S>
+where
+    S: BlobStore,
+{
+    fn default() -> Self {
+        Self::new(NonZeroUsize::new(Self::DEFAULT_VIRTUAL_NODES).unwrap())
+    }
+}
+
+impl<S> BlobStore for ShardedStore<S>
+where
+    S: BlobStore,
+{
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.route(&key)?.contains(key)
+    }
+
+    fn meta(&self, key: Key) -> Result<crate::BlobMeta> {
+        self.route(&key)?.meta(key)
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        self.route(&key)?.put(key, value, opt)
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        self.route(&key)?.get(key, buf, opt)
+    }
+
+    fn get_owned(&self, key: Key, opt: GetOpt) -> Result<Vec<u8>> {
+        self.route(&key)?.get_owned(key, opt)
+    }
+
+    fn with_blob(&self, key: Key, opt: GetOpt, f: &mut dyn FnMut(&[u8])) -> Result<()> {
+        self.route(&key)?.with_blob(key, opt, f)
+    }
+
+    fn get_vectored(&self, key: Key, bufs: &mut [(BlobRange, &mut [u8])]) -> Result<()> {
+        self.route(&key)?.get_vectored(key, bufs)
+    }
+
+    fn create_sized(&self, key: Key, len: usize) -> Result<()> {
+        self.route(&key)?.create_sized(key, len)
+    }
+
+    fn punch_hole(&self, key: Key, range: BlobRange) -> Result<()> {
+        self.route(&key)?.punch_hole(key, range)
+    }
+
+    fn put_vectored(&self, key: Key, bufs: &[(BlobRange, &[u8])]) -> Result<()> {
+        self.route(&key)?.put_vectored(key, bufs)
+    }
+
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        self.route(&key)?.delete(key, opt)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.shards
+            .values()
+            .try_fold(Vec::new(), |mut keys, shard| {
+                keys.extend(shard.keys()?);
+                Ok(keys)
+            })
+    }
+
+    fn stats(&self) -> Result<crate::StoreStats> {
+        self.shards
+            .values()
+            .try_fold(crate::StoreStats::default(), |mut stats, shard| {
+                stats.add(&shard.stats()?);
+                Ok(stats)
+            })
+    }
+
+    fn is_shared(&self) -> bool {
+        self.shards.values().any(|shard| shard.is_shared())
+    }
+}
@@ -1,40 +1,346 @@
 use std::{
-    cell::RefCell,
+    cell::{Cell, RefCell, RefMut},
     collections::HashMap,
     io::prelude::{Seek, Write},
     path::{self, PathBuf},
 };
 
-use rusqlite::blob::{Blob, ZeroBlob};
+use rusqlite::{
+    blob::{Blob, ZeroBlob},
+    OptionalExtension,
+};
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    error::{Error, Result},
-    BlobStore, Key,
+    error::{BucketError, Error, Result},
+    store_impl::{
+        config::OpenMode,
+        helpers::{StoreLock, VarKey},
+    },
+    BlobStore, BucketStore, Key, SnapshotBlobStore, Transaction, TransactionalBlobStore,
+    VarKeyBlobStore,
 };
 
 type RowID = i64;
 type Map<K, V> = HashMap<K, V>;
 type KeyToRowIDMap = RefCell<Map<Key, RowID>>;
+type BucketMaps = RefCell<Map<String, Map<Key, RowID>>>;
 
 pub struct SqliteBlobStore {
     root: path::PathBuf,
     conn: rusqlite::Connection,
     key_to_row_map: KeyToRowIDMap,
+    bucket_maps: BucketMaps,
+    max_key_len: usize,
+    /// opened with `connect_read_only`
+    read_only: bool,
+    /// writes between two checkpoints of the WAL, never if `None`
+    checkpoint_interval: Option<u64>,
+    /// writes since the last checkpoint
+    writes: Cell<u64>,
+    /// changes of the key maps since the open transaction began, in order,
+    /// `None` without a transaction
+    undo: RefCell<Option<Vec<MapChange>>>,
+    _lock: StoreLock,
+}
+
+/// A change of the key maps, with what it replaced.
+enum MapChange {
+    Row {
+        bucket: Option<String>,
+        key: Key,
+        before: Option<RowID>,
+    },
+    Bucket {
+        name: String,
+        before: Option<Map<Key, RowID>>,
+    },
+}
+
+/// Configuration of a `SqliteBlobStore`.
+///
+/// It is built in code or deserialized, e.g. from a TOML or JSON file, where
+/// the missing fields take their default. The default configuration opens the
+/// store as `SqliteBlobStore::connect` does.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SqliteConfig {
+    /// Create the root directory if it doesn't exist.
+    pub create_if_missing: bool,
+    /// `Shared` is not supported: the rows of the fixed keys are kept in memory.
+    pub mode: OpenMode,
+    /// Snapshots need `Wal`, the other modes block the writes while a
+    /// snapshot is open.
+    pub journal_mode: JournalMode,
+    /// The durability of the writes, SQLite's default when `None`.
+    pub synchronous: Option<Synchronous>,
+    /// Page size of a new database, an existing one keeps its own.
+    pub page_size: Option<u32>,
+    /// The `cache_size` pragma: pages if positive, KiB if negative.
+    pub cache_size: Option<i64>,
+    /// Bytes of the database read through a memory mapping, the `mmap_size`
+    /// pragma. SQLite caps it with its compile-time `SQLITE_MAX_MMAP_SIZE`.
+    pub mmap_size: Option<u64>,
+    /// Run a passive checkpoint of the WAL every `checkpoint_interval` writes
+    /// made outside of a transaction, on top of SQLite's own automatic
+    /// checkpoints.
+    ///
+    /// Checkpoints bound the size of the WAL, they don't make the store crash
+    /// safe: the rows of the fixed keys, of the store and of its buckets, are
+    /// only written to the map files when the store is dropped. After a crash
+    /// the store reopens with the map files of the last drop, which miss or
+    /// misplace the fixed keys written since. The blobs with variable-length
+    /// keys are looked up in the database and survive.
+    pub checkpoint_interval: Option<u64>,
+    pub max_key_len: usize,
+}
+
+/// The `journal_mode` pragma.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JournalMode {
+    Delete,
+    Truncate,
+    Persist,
+    Memory,
+    #[default]
+    Wal,
+    Off,
+}
+
+/// The `synchronous` pragma.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Synchronous {
+    Off,
+    Normal,
+    Full,
+    Extra,
+}
+
+impl JournalMode {
+    fn as_str(self) -> &'static str {
+        match self {
+            JournalMode::Delete => "DELETE",
+            JournalMode::Truncate => "TRUNCATE",
+            JournalMode::Persist => "PERSIST",
+            JournalMode::Memory => "MEMORY",
+            JournalMode::Wal => "WAL",
+            JournalMode::Off => "OFF",
+        }
+    }
+}
+
+impl Synchronous {
+    fn as_str(self) -> &'static str {
+        match self {
+            Synchronous::Off => "OFF",
+            Synchronous::Normal => "NORMAL",
+            Synchronous::Full => "FULL",
+            Synchronous::Extra => "EXTRA",
+        }
+    }
+}
+
+impl Default for SqliteConfig {
+    fn default() -> Self {
+        Self {
+            create_if_missing: false,
+            mode: OpenMode::default(),
+            journal_mode: JournalMode::default(),
+            synchronous: None,
+            page_size: None,
+            cache_size: None,
+            mmap_size: None,
+            checkpoint_interval: None,
+            max_key_len: crate::DEFAULT_MAX_KEY_LEN,
+        }
+    }
+}
+
+impl SqliteConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_create_if_missing(mut self, create_if_missing: bool) -> Self {
+        self.create_if_missing = create_if_missing;
+        self
+    }
+
+    pub fn with_mode(mut self, mode: OpenMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_journal_mode(mut self, journal_mode: JournalMode) -> Self {
+        self.journal_mode = journal_mode;
+        self
+    }
+
+    pub fn with_synchronous(mut self, synchronous: Synchronous) -> Self {
+        self.synchronous = Some(synchronous);
+        self
+    }
+
+    pub fn with_page_size(mut self, page_size: u32) -> Self {
+        self.page_size = Some(page_size);
+        self
+    }
+
+    pub fn with_cache_size(mut self, cache_size: i64) -> Self {
+        self.cache_size = Some(cache_size);
+        self
+    }
+
+    pub fn with_mmap_size(mut self, mmap_size: u64) -> Self {
+        self.mmap_size = Some(mmap_size);
+        self
+    }
+
+    pub fn with_checkpoint_interval(mut self, checkpoint_interval: u64) -> Self {
+        self.checkpoint_interval = Some(checkpoint_interval);
+        self
+    }
+
+    pub fn with_max_key_len(mut self, max_key_len: usize) -> Self {
+        self.max_key_len = max_key_len;
+        self
+    }
+
+    /// Open the store rooted at `path`.
+    ///
+    /// # Error
+    /// - Locked: another handle has the store open in a conflicting mode.
+    /// - Other: the mode is `Shared`, or the checkpoint interval is 0.
+    pub fn open(&self, path: impl Into<PathBuf>) -> Result<SqliteBlobStore> {
+        let path = path.into();
+        if self.mode == OpenMode::Shared {
+            return Err(Error::other(anyhow::anyhow!("shared mode not supported")));
+        }
+        if self.checkpoint_interval == Some(0) {
+            return Err(Error::other(anyhow::anyhow!(
+                "checkpoint interval must be positive"
+            )));
+        }
+        if self.create_if_missing {
+            std::fs::create_dir_all(&path)?;
+        }
+        SqliteBlobStore::open(path, self)
+    }
+}
+
+/// Key of a blob within a namespace.
+///
+/// Fixed keys are mapped to their rows in memory, variable-length keys are
+/// looked up in the key column.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RowKey<'k> {
+    Fixed(Key),
+    Var(&'k [u8]),
+}
+
+/// A namespace of a `SqliteBlobStore`, sharing its database.
+///
+/// The blobs of a bucket are the rows of the blob table tagged with the name of the bucket.
+pub struct SqliteBucket<'a> {
+    store: &'a SqliteBlobStore,
+    name: String,
+}
+
+impl<'k> RowKey<'k> {
+    fn from_var(key: &'k [u8], max_key_len: usize) -> Result<Self> {
+        Ok(
+            match crate::store_impl::helpers::var_key(key, max_key_len)? {
+                VarKey::Fixed(key) => RowKey::Fixed(key),
+                VarKey::Var(key) => RowKey::Var(key),
+            },
+        )
+    }
 }
 
 impl SqliteBlobStore {
     const DATABASE_NAME: rusqlite::DatabaseName<'static> = rusqlite::MAIN_DB;
     const TABLE_NAME: &'static str = "blobs";
     const COLUMN_NAME: &'static str = "content";
-    const SQL_INSERT: &'static str = concat!("INSERT INTO blobs (content) VALUES (?)",);
-    const SQL_DELETE: &'static str = concat!("DELETE FROM blobs WHERE rowid = (?)",);
+    const SQL_INSERT: &'static str = "INSERT INTO blobs (content, namespace, key) VALUES (?, ?, ?)";
+    const SQL_SELECT_VAR_KEY: &'static str =
+        "SELECT rowid FROM blobs WHERE key = (?1) AND namespace IS (?2)";
+    const SQL_UPDATE: &'static str = "UPDATE blobs SET content = (?) WHERE rowid = (?)";
+    const SQL_DELETE: &'static str = "DELETE FROM blobs WHERE rowid = (?)";
+    const SQL_COPY: &'static str = "INSERT INTO blobs (content, namespace, key) \
+        SELECT content, namespace, (?2) FROM blobs WHERE rowid = (?1)";
+    const SQL_COPY_CONTENT: &'static str =
+        "UPDATE blobs SET content = (SELECT content FROM blobs WHERE rowid = (?1)) WHERE rowid = (?2)";
+    const SQL_SET_KEY: &'static str = "UPDATE blobs SET key = (?) WHERE rowid = (?)";
     const SQL_CREATE_TABLE: &'static str =
-        concat!("CREATE TABLE IF NOT EXISTS blobs ( content BLOB NOT NULL )",);
+        "CREATE TABLE IF NOT EXISTS blobs ( content BLOB NOT NULL )";
+    const SQL_CREATE_NAMESPACE_INDEX: &'static str =
+        "CREATE INDEX IF NOT EXISTS blobs_namespace ON blobs ( namespace )";
+    const SQL_CREATE_KEY_INDEX: &'static str =
+        "CREATE INDEX IF NOT EXISTS blobs_key ON blobs ( key ) WHERE key IS NOT NULL";
+    const SQL_CREATE_BUCKET_TABLE: &'static str =
+        "CREATE TABLE IF NOT EXISTS buckets ( name TEXT PRIMARY KEY )";
+    const SQL_INSERT_BUCKET: &'static str = "INSERT INTO buckets (name) VALUES (?)";
+    const SQL_DELETE_BUCKET: &'static str = "DELETE FROM buckets WHERE name = (?)";
+    const SQL_DELETE_NAMESPACE: &'static str = "DELETE FROM blobs WHERE namespace = (?)";
+    const SQL_CHECKPOINT: &'static str = "PRAGMA wal_checkpoint(PASSIVE)";
+    /// usage of each namespace, `''` for the root one, kept by the triggers
+    /// below
+    const SQL_CREATE_USAGE: &'static str = "
+        CREATE TABLE usage ( namespace TEXT PRIMARY KEY, blobs INTEGER NOT NULL, bytes INTEGER NOT NULL );
+        INSERT INTO usage SELECT COALESCE(namespace, ''), count(*), sum(length(content))
+            FROM blobs GROUP BY 1;
+        CREATE TRIGGER usage_insert AFTER INSERT ON blobs BEGIN
+            INSERT OR IGNORE INTO usage VALUES (COALESCE(new.namespace, ''), 0, 0);
+            UPDATE usage SET blobs = blobs + 1, bytes = bytes + length(new.content)
+                WHERE namespace = COALESCE(new.namespace, '');
+        END;
+        CREATE TRIGGER usage_delete AFTER DELETE ON blobs BEGIN
+            UPDATE usage SET blobs = blobs - 1, bytes = bytes - length(old.content)
+                WHERE namespace = COALESCE(old.namespace, '');
+        END;
+        CREATE TRIGGER usage_update AFTER UPDATE OF content ON blobs BEGIN
+            UPDATE usage SET bytes = bytes + length(new.content) - length(old.content)
+                WHERE namespace = COALESCE(new.namespace, '');
+        END;";
+    const SQL_SELECT_USAGE: &'static str =
+        "SELECT COALESCE(sum(blobs), 0), COALESCE(sum(bytes), 0) FROM usage";
+    const SQL_SELECT_NAMESPACE_USAGE: &'static str =
+        "SELECT COALESCE(sum(blobs), 0), COALESCE(sum(bytes), 0) FROM usage WHERE namespace = (?)";
+    const SQL_DELETE_NAMESPACE_USAGE: &'static str = "DELETE FROM usage WHERE namespace = (?)";
     const DB_FILE: &'static str = "blobs.db";
     const MAP_FILE: &'static str = "blobs.map.dump";
+    const BUCKET_MAP_FILE: &'static str = "buckets.map.dump";
 
+    /// Open the store as its only handle.
+    ///
+    /// The rows of the fixed keys are kept in memory and written back to the
+    /// map files on drop, so writers can't share a store even though SQLite
+    /// could share the database.
+    ///
+    /// # Error
+    /// - Locked: another handle has the store open.
     pub fn connect(path: impl Into<PathBuf>) -> Result<Self> {
-        let path = path.into();
+        SqliteConfig::default().open(path)
+    }
+
+    /// Open the store for reading only: the database is opened with
+    /// `SQLITE_OPEN_READ_ONLY`, writes fail with `Error::ReadOnly`, and the key
+    /// maps are not written back on drop. Several read-only handles can have
+    /// the store open at the same time.
+    ///
+    /// # Error
+    /// - Locked: a handle opened with `connect` has the store open.
+    pub fn connect_read_only(path: impl Into<PathBuf>) -> Result<Self> {
+        SqliteConfig::default()
+            .with_mode(OpenMode::ReadOnly)
+            .open(path)
+    }
+
+    fn open(path: PathBuf, config: &SqliteConfig) -> Result<Self> {
+        let read_only = config.mode == OpenMode::ReadOnly;
+        let lock = StoreLock::acquire(&path, !read_only)?;
         let db_path = {
             let mut path = path.clone();
             path.push(Self::DB_FILE);
@@ -46,75 +352,340 @@ impl SqliteBlobStore {
             path.push(Self::MAP_FILE);
             path
         };
-        let conn = rusqlite::Connection::open(db_path.as_path())?;
-        conn.execute(Self::SQL_CREATE_TABLE, [])?;
+        let bucket_map_path = {
+            let mut path = path.clone();
+            path.push(Self::BUCKET_MAP_FILE);
+            path
+        };
+        let conn = if read_only {
+            // the schema is left as it is
+            rusqlite::Connection::open_with_flags(
+                db_path.as_path(),
+                rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY
+                    | rusqlite::OpenFlags::SQLITE_OPEN_URI
+                    | rusqlite::OpenFlags::SQLITE_OPEN_NO_MUTEX,
+            )?
+        } else {
+            let conn = rusqlite::Connection::open(db_path.as_path())?;
+            // before the first table is created
+            if let Some(page_size) = config.page_size {
+                conn.pragma_update(None, "page_size", page_size)?;
+            }
+            conn.pragma_update(None, "journal_mode", config.journal_mode.as_str())?;
+            conn.execute(Self::SQL_CREATE_TABLE, [])?;
+            // databases created by older versions lack the later columns
+            Self::add_column(&conn, "namespace", "TEXT")?;
+            Self::add_column(&conn, "key", "BLOB")?;
+            conn.execute(Self::SQL_CREATE_NAMESPACE_INDEX, [])?;
+            conn.execute(Self::SQL_CREATE_KEY_INDEX, [])?;
+            conn.execute(Self::SQL_CREATE_BUCKET_TABLE, [])?;
+            Self::create_usage(&conn)?;
+            conn
+        };
+        if let Some(synchronous) = config.synchronous {
+            conn.pragma_update(None, "synchronous", synchronous.as_str())?;
+        }
+        if let Some(cache_size) = config.cache_size {
+            conn.pragma_update(None, "cache_size", cache_size)?;
+        }
+        if let Some(mmap_size) = config.mmap_size {
+            // the pragma returns the size in effect
+            conn.pragma_update_and_check(None, "mmap_size", mmap_size, |_| Ok(()))?;
+        }
         let map = if map_path.exists() {
-            bincode::deserialize_from(std::fs::File::open(map_path)?)
-                .map_err(|e| anyhow::Error::new(e))?
+            bincode::deserialize_from(std::fs::File::open(map_path)?).map_err(anyhow::Error::new)?
         } else {
             KeyToRowIDMap::default()
         };
+        let bucket_maps = if bucket_map_path.exists() {
+            bincode::deserialize_from(std::fs::File::open(bucket_map_path)?)
+                .map_err(anyhow::Error::new)?
+        } else {
+            BucketMaps::default()
+        };
         Ok(Self {
             conn,
             key_to_row_map: map,
-            root: path.into(),
+            bucket_maps,
+            root: path,
+            max_key_len: config.max_key_len,
+            read_only,
+            checkpoint_interval: config.checkpoint_interval,
+            writes: Cell::new(0),
+            undo: RefCell::new(None),
+            _lock: lock,
         })
     }
 
-    fn open_blob(&self, key: &Key, read_only: bool) -> Result<Blob> {
-        self.key_to_row_map
-            .borrow()
-            .get(key)
-            .copied()
-            .map(|row_id| {
+    fn writable(&self) -> Result<()> {
+        if self.read_only {
+            return Err(Error::ReadOnly);
+        }
+        Ok(())
+    }
+
+    /// run `sql` through the statement cache of the connection
+    fn execute(&self, sql: &str, params: impl rusqlite::Params) -> Result<usize> {
+        Ok(self.conn.prepare_cached(sql)?.execute(params)?)
+    }
+
+    /// run `f` in a savepoint, nested in the open transaction if any, so that
+    /// its statements all apply or none does
+    fn atomic<T>(&self, f: impl FnOnce() -> Result<T>) -> Result<T> {
+        self.conn.execute_batch("SAVEPOINT atomic")?;
+        match f() {
+            Ok(value) => {
+                self.conn.execute_batch("RELEASE atomic")?;
+                Ok(value)
+            }
+            Err(e) => {
                 self.conn
-                    .blob_open(
-                        Self::DATABASE_NAME,
-                        Self::TABLE_NAME,
-                        Self::COLUMN_NAME,
-                        row_id,
-                        read_only,
-                    )
+                    .execute_batch("ROLLBACK TO atomic; RELEASE atomic")?;
+                Err(e)
+            }
+        }
+    }
+
+    /// count a write, and checkpoint the WAL when the interval is reached
+    fn wrote(&self) -> Result<()> {
+        let Some(interval) = self.checkpoint_interval else {
+            return Ok(());
+        };
+        // the writes of a transaction are checkpointed once it commits
+        if !self.conn.is_autocommit() {
+            return Ok(());
+        }
+        let writes = self.writes.get() + 1;
+        if writes < interval {
+            self.writes.set(writes);
+            return Ok(());
+        }
+        self.checkpoint()
+    }
+
+    /// Copy the content of the WAL back to the database, as far as the open
+    /// snapshots allow, without waiting for them. A no-op in the journal modes
+    /// other than `Wal`.
+    /// The key maps are not written, see `SqliteConfig::checkpoint_interval`.
+    ///
+    /// # Error
+    /// - ReadOnly: the store is opened with `connect_read_only`.
+    pub fn checkpoint(&self) -> Result<()> {
+        self.writable()?;
+        // the pragma returns whether it was blocked and the pages it copied
+        self.conn
+            .prepare_cached(Self::SQL_CHECKPOINT)?
+            .query_row([], |_| Ok(()))?;
+        self.writes.set(0);
+        Ok(())
+    }
+
+    /// Use variable-length keys of up to `max_key_len` bytes.
+    pub fn with_max_key_len(mut self, max_key_len: usize) -> Self {
+        self.max_key_len = max_key_len;
+        self
+    }
+
+    fn add_column(conn: &rusqlite::Connection, name: &str, decl: &str) -> Result<()> {
+        let exists = conn
+            .prepare("SELECT name FROM pragma_table_info('blobs') WHERE name = (?)")?
+            .exists([name])?;
+        if !exists {
+            conn.execute(&format!("ALTER TABLE blobs ADD COLUMN {name} {decl}"), [])?;
+        }
+        Ok(())
+    }
+
+    /// create the usage table of a database, counting the blobs it already has
+    fn create_usage(conn: &rusqlite::Connection) -> Result<()> {
+        let exists = conn
+            .prepare("SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'usage'")?
+            .exists([])?;
+        if !exists {
+            conn.execute_batch(&format!("BEGIN; {} COMMIT;", Self::SQL_CREATE_USAGE))?;
+        }
+        Ok(())
+    }
+
+    /// usage of a bucket, or of the whole store if `bucket` is `None`
+    fn stats_in(&self, bucket: Option<&str>) -> Result<crate::StoreStats> {
+        let row = |row: &rusqlite::Row| Ok((row.get::<_, i64>(0)?, row.get::<_, i64>(1)?));
+        let (blobs, bytes) = match bucket {
+            None => self
+                .conn
+                .prepare_cached(Self::SQL_SELECT_USAGE)?
+                .query_row([], row)?,
+            Some(name) => self
+                .conn
+                .prepare_cached(Self::SQL_SELECT_NAMESPACE_USAGE)?
+                .query_row([name], row)?,
+        };
+        Ok(crate::StoreStats {
+            blobs: blobs as u64,
+            logical_bytes: bytes as u64,
+            physical_bytes: bytes as u64,
+        })
+    }
+
+    /// key to row id map of a bucket, or of the root namespace if `bucket` is `None`
+    fn map(&self, bucket: Option<&str>) -> Result<RefMut<'_, Map<Key, RowID>>> {
+        match bucket {
+            None => Ok(self.key_to_row_map.borrow_mut()),
+            Some(name) => {
+                RefMut::filter_map(self.bucket_maps.borrow_mut(), |maps| maps.get_mut(name))
+                    .map_err(|_| BucketError::NotFound.into())
+            }
+        }
+    }
+
+    /// set the row of `key` in `map`, the key map of `bucket`, or remove it if
+    /// `row` is `None`
+    fn set_row(
+        &self,
+        map: &mut Map<Key, RowID>,
+        bucket: Option<&str>,
+        key: Key,
+        row: Option<RowID>,
+    ) {
+        let before = match row {
+            Some(row) => map.insert(key, row),
+            None => map.remove(&key),
+        };
+        self.record(MapChange::Row {
+            bucket: bucket.map(str::to_string),
+            key,
+            before,
+        });
+    }
+
+    /// record a change of the key maps for the open transaction
+    fn record(&self, change: MapChange) {
+        if let Some(undo) = self.undo.borrow_mut().as_mut() {
+            undo.push(change);
+        }
+    }
+
+    /// revert the key maps to the start of the transaction
+    fn undo_map_changes(&self) {
+        let Some(undo) = self.undo.borrow_mut().take() else {
+            return;
+        };
+        undo.into_iter().rev().for_each(|change| match change {
+            MapChange::Row {
+                bucket,
+                key,
+                before,
+            } => {
+                if let Ok(mut map) = self.map(bucket.as_deref()) {
+                    match before {
+                        Some(row) => map.insert(key, row),
+                        None => map.remove(&key),
+                    };
+                }
+            }
+            MapChange::Bucket { name, before } => {
+                let mut maps = self.bucket_maps.borrow_mut();
+                match before {
+                    Some(map) => maps.insert(name, map),
+                    None => maps.remove(&name),
+                };
+            }
+        });
+    }
+
+    /// row of a blob, `None` if the blob doesn't exist
+    fn row_of(&self, bucket: Option<&str>, key: RowKey<'_>) -> Result<Option<RowID>> {
+        let map = self.map(bucket)?;
+        match key {
+            RowKey::Fixed(key) => Ok(map.get(&key).copied()),
+            RowKey::Var(key) => {
+                drop(map);
+                self.conn
+                    .prepare_cached(Self::SQL_SELECT_VAR_KEY)?
+                    .query_row(rusqlite::params![key, bucket], |row| row.get(0))
+                    .optional()
                     .map_err(Error::from)
-            })
-            .ok_or_else(|| crate::error::BlobError::NotFound)
+            }
+        }
+    }
+
+    /// insert a zeroed blob of `len` bytes and record its row
+    fn insert_row(&self, bucket: Option<&str>, key: RowKey<'_>, len: usize) -> Result<RowID> {
+        let mut map = self.map(bucket)?;
+        let zero_blob = ZeroBlob(len.try_into().unwrap());
+        let var_key = match key {
+            RowKey::Fixed(_) => None,
+            RowKey::Var(key) => Some(key),
+        };
+        self.execute(
+            Self::SQL_INSERT,
+            rusqlite::params![zero_blob, bucket, var_key],
+        )?;
+        let row_id = self.conn.last_insert_rowid();
+        if let RowKey::Fixed(key) = key {
+            self.set_row(&mut map, bucket, key, Some(row_id));
+        }
+        Ok(row_id)
+    }
+
+    fn open_row(&self, row_id: RowID, read_only: bool) -> Result<Blob<'_>> {
+        self.conn
+            .blob_open(
+                Self::DATABASE_NAME,
+                Self::TABLE_NAME,
+                Self::COLUMN_NAME,
+                row_id,
+                read_only,
+            )
             .map_err(Error::from)
-            .and_then(std::convert::identity)
     }
-}
 
-impl BlobStore for SqliteBlobStore {
-    fn contains(&self, key: Key) -> crate::error::Result<bool> {
-        Ok(self.key_to_row_map.borrow().contains_key(&key))
+    fn open_blob(
+        &self,
+        bucket: Option<&str>,
+        key: RowKey<'_>,
+        read_only: bool,
+    ) -> Result<Blob<'_>> {
+        let row_id = self
+            .row_of(bucket, key)?
+            .ok_or(crate::error::BlobError::NotFound)?;
+        self.open_row(row_id, read_only)
+    }
+
+    fn contains_in(&self, bucket: Option<&str>, key: RowKey<'_>) -> Result<bool> {
+        Ok(self.row_of(bucket, key)?.is_some())
     }
 
-    fn meta(&self, key: Key) -> crate::error::Result<crate::BlobMeta> {
-        let size = self.open_blob(&key, true)?.len();
-        Ok(crate::BlobMeta { size })
+    fn meta_in(&self, bucket: Option<&str>, key: RowKey<'_>) -> Result<crate::BlobMeta> {
+        let size = self.open_blob(bucket, key, true)?.len();
+        Ok(crate::BlobMeta {
+            size,
+            ..Default::default()
+        })
     }
 
-    fn put(&self, key: Key, value: &[u8], opt: crate::PutOpt) -> crate::error::Result<()> {
+    fn put_in(
+        &self,
+        bucket: Option<&str>,
+        key: RowKey<'_>,
+        value: &[u8],
+        opt: crate::PutOpt,
+    ) -> Result<()> {
+        self.writable()?;
         let mut blob = match &opt {
             crate::PutOpt::Create => {
-                let mut key_to_row_map = self.key_to_row_map.borrow_mut();
-                if key_to_row_map.contains_key(&key) {
+                if self.row_of(bucket, key)?.is_some() {
                     return Err(crate::error::BlobError::AlreadyExists.into());
                 }
-                self.conn.execute(
-                    Self::SQL_INSERT,
-                    [ZeroBlob(value.len().try_into().unwrap())],
-                )?;
-                let row_id = self.conn.last_insert_rowid();
-                key_to_row_map.insert(key, row_id);
-                drop(key_to_row_map);
-                self.open_blob(&key, false)?
+                let row_id = self.insert_row(bucket, key, value.len())?;
+                self.open_row(row_id, false)?
             }
             crate::PutOpt::Replace(range) => {
-                let mut blob = self.open_blob(&key, false)?;
+                let mut blob = self.open_blob(bucket, key, false)?;
                 // check range
                 let size = blob.len();
                 let valid_range = 0..size;
-                if !crate::store_impl::helpers::range_contains(&valid_range, &range) {
+                if !crate::store_impl::helpers::range_contains(&valid_range, range) {
                     return Err(crate::error::BlobError::RangeError.into());
                 }
                 if value.len() != range.len() {
@@ -123,15 +694,99 @@ impl BlobStore for SqliteBlobStore {
                 blob.seek(std::io::SeekFrom::Start((range.start).try_into().unwrap()))?;
                 blob
             }
-            crate::PutOpt::ReplaceOrCreate => unimplemented!(),
+            crate::PutOpt::ReplaceOrCreate => {
+                let row_id = match self.row_of(bucket, key)? {
+                    Some(row_id) => {
+                        let zero_blob = ZeroBlob(value.len().try_into().unwrap());
+                        self.execute(Self::SQL_UPDATE, rusqlite::params![zero_blob, row_id])?;
+                        row_id
+                    }
+                    None => self.insert_row(bucket, key, value.len())?,
+                };
+                self.open_row(row_id, false)?
+            }
         };
         blob.write_all(value)?;
+        // the write commits when the blob is closed
+        drop(blob);
+        self.wrote()
+    }
+
+    fn get_in(
+        &self,
+        bucket: Option<&str>,
+        key: RowKey<'_>,
+        buf: &mut [u8],
+        opt: crate::GetOpt,
+    ) -> Result<()> {
+        let mut blob = self.open_blob(bucket, key, true)?;
+        Self::read_blob(&mut blob, buf, opt)
+    }
+
+    fn get_vectored_in(
+        &self,
+        bucket: Option<&str>,
+        key: RowKey<'_>,
+        bufs: &mut [(crate::BlobRange, &mut [u8])],
+    ) -> Result<()> {
+        let blob = self.open_blob(bucket, key, true)?;
+        crate::check_ranges(
+            blob.len(),
+            bufs.iter().map(|(range, buf)| (range, buf.len())),
+        )?;
+        for (range, buf) in bufs.iter_mut() {
+            blob.read_at_exact(buf, range.start)?;
+        }
         Ok(())
     }
 
-    fn get(&self, key: Key, buf: &mut [u8], opt: crate::GetOpt) -> crate::error::Result<()> {
-        let key = key;
-        let mut blob = self.open_blob(&key, true)?;
+    fn put_vectored_in(
+        &self,
+        bucket: Option<&str>,
+        key: RowKey<'_>,
+        bufs: &[(crate::BlobRange, &[u8])],
+    ) -> Result<()> {
+        self.writable()?;
+        let mut blob = self.open_blob(bucket, key, false)?;
+        crate::check_ranges(
+            blob.len(),
+            bufs.iter().map(|(range, buf)| (range, buf.len())),
+        )?;
+        for (range, buf) in bufs {
+            blob.write_at(buf, range.start)?;
+        }
+        drop(blob);
+        self.wrote()
+    }
+
+    fn create_sized_in(&self, bucket: Option<&str>, key: RowKey<'_>, len: usize) -> Result<()> {
+        self.writable()?;
+        if self.row_of(bucket, key)?.is_some() {
+            return Err(crate::error::BlobError::AlreadyExists.into());
+        }
+        self.insert_row(bucket, key, len)?;
+        self.wrote()
+    }
+
+    /// SQLite can't deallocate a part of a blob, the range is overwritten
+    /// with zeros
+    fn punch_hole_in(
+        &self,
+        bucket: Option<&str>,
+        key: RowKey<'_>,
+        range: crate::BlobRange,
+    ) -> Result<()> {
+        self.writable()?;
+        let mut blob = self.open_blob(bucket, key, false)?;
+        if !crate::store_impl::helpers::range_contains(&(0..blob.len()), &range) {
+            return Err(crate::error::BlobError::RangeError.into());
+        }
+        blob.write_at(&vec![0; range.len()], range.start)?;
+        drop(blob);
+        self.wrote()
+    }
+
+    fn read_blob(blob: &mut Blob<'_>, buf: &mut [u8], opt: crate::GetOpt) -> Result<()> {
         match &opt {
             crate::GetOpt::All => {
                 if blob.len() != buf.len() {
@@ -145,7 +800,7 @@ impl BlobStore for SqliteBlobStore {
                     return Err(crate::error::BlobError::RangeError.into());
                 }
                 let valid_range = 0..blob.len();
-                if !crate::store_impl::helpers::range_contains(&valid_range, &range) {
+                if !crate::store_impl::helpers::range_contains(&valid_range, range) {
                     return Err(crate::error::BlobError::RangeError.into());
                 }
                 blob.seek(std::io::SeekFrom::Start(range.start.try_into().unwrap()))?;
@@ -155,26 +810,553 @@ impl BlobStore for SqliteBlobStore {
         Ok(())
     }
 
-    fn delete(&self, key: Key, opt: crate::DeleteOpt) -> crate::error::Result<Option<Vec<u8>>> {
-        let key = key;
-        let row_id = match self.key_to_row_map.borrow_mut().remove(&key) {
-            Some(row_id) => row_id,
-            None => return Err(crate::error::BlobError::NotFound.into()),
+    fn delete_in(
+        &self,
+        bucket: Option<&str>,
+        key: RowKey<'_>,
+        opt: crate::DeleteOpt,
+    ) -> Result<Option<Vec<u8>>> {
+        self.writable()?;
+        let Some(row_id) = self.row_of(bucket, key)? else {
+            return Err(crate::error::BlobError::NotFound.into());
+        };
+        let content = match opt {
+            crate::DeleteOpt::Interest(range) => {
+                let mut buf = vec![0_u8; range.len()];
+                let mut blob = self.open_row(row_id, true)?;
+                Self::read_blob(&mut blob, &mut buf, crate::GetOpt::Range(range))?;
+                Some(buf)
+            }
+            crate::DeleteOpt::Discard => None,
         };
-        if let crate::DeleteOpt::Interest(_) = &opt {
-            unimplemented!("Interest delete not implemented, use \"get\" before delete instead");
-            // let mut blob = self.open_blob(&key, false)?;
-            // let size = blob.len();
-            // interest.reserve_exact(size - interest.len());
-            // blob.raw_read_at_exact()?;
+        self.execute(Self::SQL_DELETE, [row_id])?;
+        // the key map follows the database once the row is gone
+        if let RowKey::Fixed(key) = key {
+            let mut map = self.map(bucket)?;
+            self.set_row(&mut map, bucket, key, None);
+        }
+        self.wrote()?;
+        Ok(content)
+    }
+
+    /// copy a blob within the database
+    fn copy_in(
+        &self,
+        bucket: Option<&str>,
+        src: RowKey<'_>,
+        dst: RowKey<'_>,
+        opt: crate::CopyOpt,
+    ) -> Result<()> {
+        self.writable()?;
+        let src_row = self
+            .row_of(bucket, src)?
+            .ok_or(crate::error::BlobError::NotFound)?;
+        match (self.row_of(bucket, dst)?, opt) {
+            (Some(_), crate::CopyOpt::Create) => Err(crate::error::BlobError::AlreadyExists.into()),
+            (Some(dst_row), crate::CopyOpt::ReplaceOrCreate) => {
+                if dst_row != src_row {
+                    self.execute(Self::SQL_COPY_CONTENT, [src_row, dst_row])?;
+                }
+                self.wrote()
+            }
+            (None, _) => {
+                let mut map = self.map(bucket)?;
+                let var_key = match dst {
+                    RowKey::Fixed(_) => None,
+                    RowKey::Var(key) => Some(key),
+                };
+                self.execute(Self::SQL_COPY, rusqlite::params![src_row, var_key])?;
+                if let RowKey::Fixed(key) = dst {
+                    self.set_row(&mut map, bucket, key, Some(self.conn.last_insert_rowid()));
+                }
+                drop(map);
+                self.wrote()
+            }
+        }
+    }
+
+    /// move a blob to another key, the row of the blob is kept
+    fn rename_in(
+        &self,
+        bucket: Option<&str>,
+        src: RowKey<'_>,
+        dst: RowKey<'_>,
+        opt: crate::CopyOpt,
+    ) -> Result<()> {
+        self.writable()?;
+        let src_row = self
+            .row_of(bucket, src)?
+            .ok_or(crate::error::BlobError::NotFound)?;
+        if src == dst {
+            return self.copy_in(bucket, src, dst, opt);
+        }
+        if self.row_of(bucket, dst)?.is_some() {
+            if let crate::CopyOpt::Create = opt {
+                return Err(crate::error::BlobError::AlreadyExists.into());
+            }
+            self.delete_in(bucket, dst, crate::DeleteOpt::Discard)?;
+        }
+        let mut map = self.map(bucket)?;
+        if let RowKey::Fixed(key) = src {
+            self.set_row(&mut map, bucket, key, None);
+        }
+        if let RowKey::Fixed(key) = dst {
+            self.set_row(&mut map, bucket, key, Some(src_row));
+        }
+        // fixed keys are only in the key map
+        if matches!(src, RowKey::Var(_)) || matches!(dst, RowKey::Var(_)) {
+            let var_key = match dst {
+                RowKey::Fixed(_) => None,
+                RowKey::Var(key) => Some(key),
+            };
+            self.execute(Self::SQL_SET_KEY, rusqlite::params![var_key, src_row])?;
+        }
+        drop(map);
+        self.wrote()
+    }
+
+    fn keys_in(&self, bucket: Option<&str>) -> Result<Vec<Key>> {
+        Ok(self.map(bucket)?.keys().copied().collect())
+    }
+}
+
+impl BlobStore for SqliteBlobStore {
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.contains_in(None, RowKey::Fixed(key))
+    }
+
+    fn meta(&self, key: Key) -> Result<crate::BlobMeta> {
+        self.meta_in(None, RowKey::Fixed(key))
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: crate::PutOpt) -> Result<()> {
+        self.put_in(None, RowKey::Fixed(key), value, opt)
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: crate::GetOpt) -> Result<()> {
+        self.get_in(None, RowKey::Fixed(key), buf, opt)
+    }
+
+    fn delete(&self, key: Key, opt: crate::DeleteOpt) -> Result<Option<Vec<u8>>> {
+        self.delete_in(None, RowKey::Fixed(key), opt)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.keys_in(None)
+    }
+
+    /// The usage is kept in a table of the database by triggers on the blob
+    /// table. The bytes are the sizes of the blobs, the pages of the database
+    /// are not counted.
+    fn stats(&self) -> Result<crate::StoreStats> {
+        self.stats_in(None)
+    }
+
+    fn get_vectored(&self, key: Key, bufs: &mut [(crate::BlobRange, &mut [u8])]) -> Result<()> {
+        self.get_vectored_in(None, RowKey::Fixed(key), bufs)
+    }
+
+    fn put_vectored(&self, key: Key, bufs: &[(crate::BlobRange, &[u8])]) -> Result<()> {
+        self.put_vectored_in(None, RowKey::Fixed(key), bufs)
+    }
+
+    /// The blob is inserted as a `ZeroBlob`.
+    fn create_sized(&self, key: Key, len: usize) -> Result<()> {
+        self.create_sized_in(None, RowKey::Fixed(key), len)
+    }
+
+    fn punch_hole(&self, key: Key, range: crate::BlobRange) -> Result<()> {
+        self.punch_hole_in(None, RowKey::Fixed(key), range)
+    }
+
+    fn copy(&self, src: Key, dst: Key, opt: crate::CopyOpt) -> Result<()> {
+        self.copy_in(None, RowKey::Fixed(src), RowKey::Fixed(dst), opt)
+    }
+
+    fn rename(&self, src: Key, dst: Key, opt: crate::CopyOpt) -> Result<()> {
+        self.rename_in(None, RowKey::Fixed(src), RowKey::Fixed(dst), opt)
+    }
+}
+
+impl BucketStore for SqliteBlobStore {
+    type Bucket<'a> = SqliteBucket<'a>;
+
+    fn create_bucket(&self, name: &str) -> Result<Self::Bucket<'_>> {
+        self.writable()?;
+        crate::store_impl::helpers::check_bucket_name(name)?;
+        let mut bucket_maps = self.bucket_maps.borrow_mut();
+        if bucket_maps.contains_key(name) {
+            return Err(BucketError::AlreadyExists.into());
+        }
+        self.execute(Self::SQL_INSERT_BUCKET, [name])?;
+        bucket_maps.insert(name.to_string(), Map::new());
+        self.record(MapChange::Bucket {
+            name: name.to_string(),
+            before: None,
+        });
+        Ok(SqliteBucket {
+            store: self,
+            name: name.to_string(),
+        })
+    }
+
+    fn bucket(&self, name: &str) -> Result<Self::Bucket<'_>> {
+        if !self.bucket_maps.borrow().contains_key(name) {
+            return Err(BucketError::NotFound.into());
         }
-        self.conn.execute(Self::SQL_DELETE, [row_id])?;
-        Ok(None)
+        Ok(SqliteBucket {
+            store: self,
+            name: name.to_string(),
+        })
+    }
+
+    fn list_buckets(&self) -> Result<Vec<String>> {
+        Ok(self.bucket_maps.borrow().keys().cloned().collect())
+    }
+
+    fn drop_bucket(&self, name: &str) -> Result<()> {
+        self.writable()?;
+        if !self.bucket_maps.borrow().contains_key(name) {
+            return Err(BucketError::NotFound.into());
+        }
+        self.atomic(|| {
+            self.execute(Self::SQL_DELETE_NAMESPACE, [name])?;
+            self.execute(Self::SQL_DELETE_NAMESPACE_USAGE, [name])?;
+            self.execute(Self::SQL_DELETE_BUCKET, [name])?;
+            Ok(())
+        })?;
+        let before = self.bucket_maps.borrow_mut().remove(name);
+        self.record(MapChange::Bucket {
+            name: name.to_string(),
+            before,
+        });
+        self.wrote()
+    }
+}
+
+impl BlobStore for SqliteBucket<'_> {
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.store.contains_in(Some(&self.name), RowKey::Fixed(key))
+    }
+
+    fn meta(&self, key: Key) -> Result<crate::BlobMeta> {
+        self.store.meta_in(Some(&self.name), RowKey::Fixed(key))
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: crate::PutOpt) -> Result<()> {
+        self.store
+            .put_in(Some(&self.name), RowKey::Fixed(key), value, opt)
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: crate::GetOpt) -> Result<()> {
+        self.store
+            .get_in(Some(&self.name), RowKey::Fixed(key), buf, opt)
+    }
+
+    fn delete(&self, key: Key, opt: crate::DeleteOpt) -> Result<Option<Vec<u8>>> {
+        self.store
+            .delete_in(Some(&self.name), RowKey::Fixed(key), opt)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.store.keys_in(Some(&self.name))
+    }
+
+    fn stats(&self) -> Result<crate::StoreStats> {
+        self.store.stats_in(Some(&self.name))
+    }
+
+    fn get_vectored(&self, key: Key, bufs: &mut [(crate::BlobRange, &mut [u8])]) -> Result<()> {
+        self.store
+            .get_vectored_in(Some(&self.name), RowKey::Fixed(key), bufs)
+    }
+
+    fn put_vectored(&self, key: Key, bufs: &[(crate::BlobRange, &[u8])]) -> Result<()> {
+        self.store
+            .put_vectored_in(Some(&self.name), RowKey::Fixed(key), bufs)
+    }
+
+    fn create_sized(&self, key: Key, len: usize) -> Result<()> {
+        self.store
+            .create_sized_in(Some(&self.name), RowKey::Fixed(key), len)
+    }
+
+    fn punch_hole(&self, key: Key, range: crate::BlobRange) -> Result<()> {
+        self.store
+            .punch_hole_in(Some(&self.name), RowKey::Fixed(key), range)
+    }
+
+    fn copy(&self, src: Key, dst: Key, opt: crate::CopyOpt) -> Result<()> {
+        self.store.copy_in(
+            Some(&self.name),
+            RowKey::Fixed(src),
+            RowKey::Fixed(dst),
+            opt,
+        )
+    }
+
+    fn rename(&self, src: Key, dst: Key, opt: crate::CopyOpt) -> Result<()> {
+        self.store.rename_in(
+            Some(&self.name),
+            RowKey::Fixed(src),
+            RowKey::Fixed(dst),
+            opt,
+        )
+    }
+}
+
+impl VarKeyBlobStore for SqliteBlobStore {
+    fn max_key_len(&self) -> usize {
+        self.max_key_len
+    }
+
+    fn contains_var(&self, key: &[u8]) -> Result<bool> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.contains(key),
+            VarKey::Var(key) => self.contains_in(None, RowKey::Var(key)),
+        }
+    }
+
+    fn meta_var(&self, key: &[u8]) -> Result<crate::BlobMeta> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.meta(key),
+            VarKey::Var(key) => self.meta_in(None, RowKey::Var(key)),
+        }
+    }
+
+    fn put_var(&self, key: &[u8], value: &[u8], opt: crate::PutOpt) -> Result<()> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.put(key, value, opt),
+            VarKey::Var(key) => self.put_in(None, RowKey::Var(key), value, opt),
+        }
+    }
+
+    fn get_var(&self, key: &[u8], buf: &mut [u8], opt: crate::GetOpt) -> Result<()> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.get(key, buf, opt),
+            VarKey::Var(key) => self.get_in(None, RowKey::Var(key), buf, opt),
+        }
+    }
+
+    fn delete_var(&self, key: &[u8], opt: crate::DeleteOpt) -> Result<Option<Vec<u8>>> {
+        match crate::store_impl::helpers::var_key(key, self.max_key_len)? {
+            VarKey::Fixed(key) => self.delete(key, opt),
+            VarKey::Var(key) => self.delete_in(None, RowKey::Var(key), opt),
+        }
+    }
+
+    fn copy_var(&self, src: &[u8], dst: &[u8], opt: crate::CopyOpt) -> Result<()> {
+        let src = RowKey::from_var(src, self.max_key_len)?;
+        let dst = RowKey::from_var(dst, self.max_key_len)?;
+        self.copy_in(None, src, dst, opt)
+    }
+
+    fn rename_var(&self, src: &[u8], dst: &[u8], opt: crate::CopyOpt) -> Result<()> {
+        let src = RowKey::from_var(src, self.max_key_len)?;
+        let dst = RowKey::from_var(dst, self.max_key_len)?;
+        self.rename_in(None, src, dst, opt)
+    }
+}
+
+impl VarKeyBlobStore for SqliteBucket<'_> {
+    fn max_key_len(&self) -> usize {
+        self.store.max_key_len
+    }
+
+    fn contains_var(&self, key: &[u8]) -> Result<bool> {
+        match crate::store_impl::helpers::var_key(key, self.store.max_key_len)? {
+            VarKey::Fixed(key) => self.contains(key),
+            VarKey::Var(key) => self.store.contains_in(Some(&self.name), RowKey::Var(key)),
+        }
+    }
+
+    fn meta_var(&self, key: &[u8]) -> Result<crate::BlobMeta> {
+        match crate::store_impl::helpers::var_key(key, self.store.max_key_len)? {
+            VarKey::Fixed(key) => self.meta(key),
+            VarKey::Var(key) => self.store.meta_in(Some(&self.name), RowKey::Var(key)),
+        }
+    }
+
+    fn put_var(&self, key: &[u8], value: &[u8], opt: crate::PutOpt) -> Result<()> {
+        match crate::store_impl::helpers::var_key(key, self.store.max_key_len)? {
+            VarKey::Fixed(key) => self.put(key, value, opt),
+            VarKey::Var(key) => self
+                .store
+                .put_in(Some(&self.name), RowKey::Var(key), value, opt),
+        }
+    }
+
+    fn get_var(&self, key: &[u8], buf: &mut [u8], opt: crate::GetOpt) -> Result<()> {
+        match crate::store_impl::helpers::var_key(key, self.store.max_key_len)? {
+            VarKey::Fixed(key) => self.get(key, buf, opt),
+            VarKey::Var(key) => self
+                .store
+                .get_in(Some(&self.name), RowKey::Var(key), buf, opt),
+        }
+    }
+
+    fn delete_var(&self, key: &[u8], opt: crate::DeleteOpt) -> Result<Option<Vec<u8>>> {
+        match crate::store_impl::helpers::var_key(key, self.store.max_key_len)? {
+            VarKey::Fixed(key) => self.delete(key, opt),
+            VarKey::Var(key) => self
+                .store
+                .delete_in(Some(&self.name), RowKey::Var(key), opt),
+        }
+    }
+
+    fn copy_var(&self, src: &[u8], dst: &[u8], opt: crate::CopyOpt) -> Result<()> {
+        let src = RowKey::from_var(src, self.store.max_key_len)?;
+        let dst = RowKey::from_var(dst, self.store.max_key_len)?;
+        self.store.copy_in(Some(&self.name), src, dst, opt)
+    }
+
+    fn rename_var(&self, src: &[u8], dst: &[u8], opt: crate::CopyOpt) -> Result<()> {
+        let src = RowKey::from_var(src, self.store.max_key_len)?;
+        let dst = RowKey::from_var(dst, self.store.max_key_len)?;
+        self.store.rename_in(Some(&self.name), src, dst, opt)
+    }
+}
+
+/// Transaction of a `SqliteBlobStore`, an SQLite transaction on its connection.
+///
+/// The store shares its connection with the transaction, so the operations
+/// made on the store while the transaction is open are part of it, and are
+/// undone with it in the key maps too if it doesn't commit.
+pub struct SqliteTransaction<'a> {
+    store: &'a SqliteBlobStore,
+    txn: Option<rusqlite::Transaction<'a>>,
+}
+
+impl Transaction for SqliteTransaction<'_> {
+    fn put(&mut self, key: Key, value: &[u8], opt: crate::PutOpt) -> Result<()> {
+        self.store.put_in(None, RowKey::Fixed(key), value, opt)
+    }
+
+    fn delete(&mut self, key: Key) -> Result<()> {
+        self.store
+            .delete_in(None, RowKey::Fixed(key), crate::DeleteOpt::Discard)
+            .map(|_| ())
+    }
+
+    fn commit(mut self) -> Result<()> {
+        self.txn.take().unwrap().commit()?;
+        self.store.undo.take();
+        self.store.wrote()
+    }
+
+    fn rollback(mut self) -> Result<()> {
+        self.txn.take().unwrap().rollback()?;
+        Ok(())
+    }
+}
+
+impl Drop for SqliteTransaction<'_> {
+    fn drop(&mut self) {
+        // rolled back, or about to be by dropping `txn`
+        self.store.undo_map_changes();
+    }
+}
+
+impl TransactionalBlobStore for SqliteBlobStore {
+    type Transaction<'a> = SqliteTransaction<'a>;
+
+    fn begin(&self) -> Result<Self::Transaction<'_>> {
+        self.writable()?;
+        let txn = self.conn.unchecked_transaction()?;
+        self.undo.replace(Some(Vec::new()));
+        Ok(SqliteTransaction {
+            store: self,
+            txn: Some(txn),
+        })
+    }
+}
+
+/// Snapshot of a `SqliteBlobStore`: a read transaction on a connection of its
+/// own, together with a copy of the key map.
+pub struct SqliteSnapshot {
+    conn: rusqlite::Connection,
+    key_to_row_map: Map<Key, RowID>,
+}
+
+impl SqliteSnapshot {
+    fn open_blob(&self, key: &Key) -> Result<Blob<'_>> {
+        let row_id = self
+            .key_to_row_map
+            .get(key)
+            .copied()
+            .ok_or(crate::error::BlobError::NotFound)?;
+        self.conn
+            .blob_open(
+                SqliteBlobStore::DATABASE_NAME,
+                SqliteBlobStore::TABLE_NAME,
+                SqliteBlobStore::COLUMN_NAME,
+                row_id,
+                true,
+            )
+            .map_err(Error::from)
+    }
+}
+
+impl BlobStore for SqliteSnapshot {
+    fn contains(&self, key: Key) -> Result<bool> {
+        Ok(self.key_to_row_map.contains_key(&key))
+    }
+
+    fn meta(&self, key: Key) -> Result<crate::BlobMeta> {
+        let size = self.open_blob(&key)?.len();
+        Ok(crate::BlobMeta {
+            size,
+            ..Default::default()
+        })
+    }
+
+    fn put(&self, _key: Key, _value: &[u8], _opt: crate::PutOpt) -> Result<()> {
+        Err(Error::ReadOnly)
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: crate::GetOpt) -> Result<()> {
+        SqliteBlobStore::read_blob(&mut self.open_blob(&key)?, buf, opt)
+    }
+
+    fn delete(&self, _key: Key, _opt: crate::DeleteOpt) -> Result<Option<Vec<u8>>> {
+        Err(Error::ReadOnly)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        Ok(self.key_to_row_map.keys().copied().collect())
+    }
+}
+
+impl SnapshotBlobStore for SqliteBlobStore {
+    type Snapshot<'a> = SqliteSnapshot;
+
+    /// # Error
+    /// - Other: a transaction is open on the store.
+    fn snapshot(&self) -> Result<Self::Snapshot<'_>> {
+        if !self.conn.is_autocommit() {
+            return Err(Error::other(anyhow::anyhow!(
+                "cannot take a snapshot in a transaction"
+            )));
+        }
+        let conn = rusqlite::Connection::open_with_flags(
+            self.root.join(Self::DB_FILE),
+            rusqlite::OpenFlags::SQLITE_OPEN_READ_ONLY,
+        )?;
+        // the read transaction starts with its first read
+        conn.execute_batch("BEGIN")?;
+        conn.query_row("SELECT count(*) FROM blobs", [], |_| Ok(()))?;
+        Ok(SqliteSnapshot {
+            conn,
+            key_to_row_map: self.key_to_row_map.borrow().clone(),
+        })
     }
 }
 
 impl Drop for SqliteBlobStore {
     fn drop(&mut self) {
+        if self.read_only {
+            return;
+        }
         let map_path = {
             let mut path = self.root.clone();
             path.push(Self::MAP_FILE);
@@ -192,5 +1374,21 @@ impl Drop for SqliteBlobStore {
             &self.key_to_row_map,
         )
         .unwrap();
+        let bucket_map_path = {
+            let mut path = self.root.clone();
+            path.push(Self::BUCKET_MAP_FILE);
+            path
+        };
+        bincode::serialize_into(
+            std::fs::File::options()
+                .truncate(true)
+                .read(true)
+                .write(true)
+                .create(true)
+                .open(bucket_map_path)
+                .expect("failed to open bucket map file"),
+            &self.bucket_maps,
+        )
+        .unwrap();
     }
 }
@@ -0,0 +1,376 @@
+use std::{
+    ffi::CString,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::ffi::OsStrExt,
+    },
+    path::PathBuf,
+};
+
+use io_uring::{opcode, squeue, types, IoUring};
+
+use crate::error::{BlobError, Error, Result};
+
+/// longest read or write submitted at once, the kernel caps them anyway
+const MAX_IO_LEN: usize = 1 << 30;
+
+/// io_uring engine of `LocalFileSystemBlobStore`, see `with_io_uring`.
+///
+/// A batch goes through the ring in stages: the opens of all the files, then
+/// the stat, the reads or the writes, and the fsyncs, each stage submitted at
+/// once. A file that fails at a stage is left out of the next ones. The files
+/// are closed when they are dropped.
+///
+/// A ring whose submission fails for another reason than being busy or
+/// interrupted waits for the operations already submitted, then refuses the
+/// next batches: the operations left in its queue point to buffers that are
+/// gone.
+pub(crate) struct Ring {
+    ring: IoUring,
+    /// a submission failed, see above
+    broken: bool,
+}
+
+/// a file of the batch, open after the first stage
+struct Slot<B> {
+    fd: OwnedFd,
+    buf: B,
+    /// bytes read or written
+    done: usize,
+}
+
+impl Ring {
+    pub(crate) fn new(entries: u32) -> Result<Self> {
+        Ok(Self {
+            ring: IoUring::new(entries)?,
+            broken: false,
+        })
+    }
+
+    /// Read the whole files at `paths`, one result per path.
+    pub(crate) fn read_files(&mut self, paths: &[PathBuf]) -> Vec<Result<Vec<u8>>> {
+        let flags = libc::O_RDONLY | libc::O_CLOEXEC;
+        let mut slots = self
+            .open(paths, flags)
+            .into_iter()
+            .map(|fd| fd.map(|fd| (fd, Box::new(zeroed_statx()))))
+            .collect::<Vec<_>>();
+        let empty = CString::default();
+        let ops = slots
+            .iter_mut()
+            .map(|slot| {
+                let (fd, stat) = slot.as_mut().ok()?;
+                Some(
+                    opcode::Statx::new(
+                        types::Fd(fd.as_raw_fd()),
+                        empty.as_ptr(),
+                        &mut **stat as *mut libc::statx as *mut types::statx,
+                    )
+                    .flags(libc::AT_EMPTY_PATH)
+                    .mask(libc::STATX_SIZE)
+                    .build(),
+                )
+            })
+            .collect::<Vec<_>>();
+        // SAFETY: the path and the stat buffers outlive the run
+        let results = unsafe { self.run(&ops) };
+        let mut slots = slots
+            .into_iter()
+            .zip(results)
+            .map(|(slot, result)| {
+                let (fd, stat) = slot?;
+                result?;
+                let len = usize::try_from(stat.stx_size).map_err(Error::other)?;
+                Ok(Slot {
+                    fd,
+                    buf: vec![0_u8; len],
+                    done: 0,
+                })
+            })
+            .collect::<Vec<_>>();
+        while slots
+            .iter()
+            .any(|slot| matches!(slot, Ok(slot) if slot.done < slot.buf.len()))
+        {
+            let ops = slots
+                .iter_mut()
+                .map(|slot| {
+                    let slot = slot
+                        .as_mut()
+                        .ok()
+                        .filter(|slot| slot.done < slot.buf.len())?;
+                    let rest = &mut slot.buf[slot.done..];
+                    let len = rest.len().min(MAX_IO_LEN);
+                    Some(
+                        opcode::Read::new(
+                            types::Fd(slot.fd.as_raw_fd()),
+                            rest.as_mut_ptr(),
+                            len as u32,
+                        )
+                        .offset(slot.done as u64)
+                        .build(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            // SAFETY: the buffers outlive the run
+            let results = unsafe { self.run(&ops) };
+            Self::advance(&mut slots, &ops, results);
+        }
+        slots
+            .into_iter()
+            .map(|slot| slot.map(|slot| slot.buf))
+            .collect()
+    }
+
+    /// Write the files of `blobs`, opened with `O_WRONLY | O_CREAT | flags`,
+    /// flushed with `fdatasync` if `sync`. One result per blob.
+    pub(crate) fn write_files(
+        &mut self,
+        blobs: &[(PathBuf, &[u8])],
+        flags: i32,
+        sync: bool,
+    ) -> Vec<Result<()>> {
+        let paths = blobs
+            .iter()
+            .map(|(path, _)| path.clone())
+            .collect::<Vec<_>>();
+        let flags = libc::O_WRONLY | libc::O_CREAT | libc::O_CLOEXEC | flags;
+        let mut slots = self
+            .open(&paths, flags)
+            .into_iter()
+            .zip(blobs)
+            .map(|(fd, (_, value))| {
+                fd.map(|fd| Slot {
+                    fd,
+                    buf: *value,
+                    done: 0,
+                })
+            })
+            .collect::<Vec<_>>();
+        while slots
+            .iter()
+            .any(|slot| matches!(slot, Ok(slot) if slot.done < slot.buf.len()))
+        {
+            let ops = slots
+                .iter()
+                .map(|slot| {
+                    let slot = slot
+                        .as_ref()
+                        .ok()
+                        .filter(|slot| slot.done < slot.buf.len())?;
+                    let rest = &slot.buf[slot.done..];
+                    let len = rest.len().min(MAX_IO_LEN);
+                    Some(
+                        opcode::Write::new(
+                            types::Fd(slot.fd.as_raw_fd()),
+                            rest.as_ptr(),
+                            len as u32,
+                        )
+                        .offset(slot.done as u64)
+                        .build(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            // SAFETY: the values outlive the run
+            let results = unsafe { self.run(&ops) };
+            Self::advance(&mut slots, &ops, results);
+        }
+        if sync {
+            let ops = slots
+                .iter()
+                .map(|slot| {
+                    let slot = slot.as_ref().ok()?;
+                    Some(
+                        opcode::Fsync::new(types::Fd(slot.fd.as_raw_fd()))
+                            .flags(types::FsyncFlags::DATASYNC)
+                            .build(),
+                    )
+                })
+                .collect::<Vec<_>>();
+            // SAFETY: no buffer is involved
+            let results = unsafe { self.run(&ops) };
+            slots = slots
+                .into_iter()
+                .zip(results)
+                .map(|(slot, result)| slot.and_then(|slot| result.map(|_| slot)))
+                .collect();
+        }
+        slots.into_iter().map(|slot| slot.map(|_| ())).collect()
+    }
+
+    /// open the files at `paths` with `flags`, one result per path
+    fn open(&mut self, paths: &[PathBuf], flags: i32) -> Vec<Result<OwnedFd>> {
+        let paths = paths
+            .iter()
+            .map(|path| CString::new(path.as_os_str().as_bytes()).map_err(Error::other))
+            .collect::<Vec<_>>();
+        let ops = paths
+            .iter()
+            .map(|path| {
+                let path = path.as_ref().ok()?;
+                Some(
+                    opcode::OpenAt::new(types::Fd(libc::AT_FDCWD), path.as_ptr())
+                        .flags(flags)
+                        .mode(0o666)
+                        .build(),
+                )
+            })
+            .collect::<Vec<_>>();
+        // SAFETY: the paths outlive the run
+        let results = unsafe { self.run(&ops) };
+        paths
+            .into_iter()
+            .zip(results)
+            .map(|(path, result)| {
+                path?;
+                let fd = result.map_err(|e| match e {
+                    Error::Io(e) if e.kind() == std::io::ErrorKind::NotFound => {
+                        Error::from(BlobError::NotFound)
+                    }
+                    Error::Io(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                        Error::from(BlobError::AlreadyExists)
+                    }
+                    e => e,
+                })?;
+                // SAFETY: the descriptor was just opened and is owned by no one else
+                Ok(unsafe { OwnedFd::from_raw_fd(fd as i32) })
+            })
+            .collect()
+    }
+
+    /// count the bytes moved by a round of reads or writes, a file that
+    /// moved none is shorter than expected
+    fn advance<B: AsRef<[u8]>>(
+        slots: &mut [Result<Slot<B>>],
+        ops: &[Option<squeue::Entry>],
+        results: Vec<Result<u32>>,
+    ) {
+        for ((slot, op), result) in slots.iter_mut().zip(ops).zip(results) {
+            if op.is_none() {
+                continue;
+            }
+            match (slot.as_mut(), result) {
+                (Ok(_), Ok(0)) => {
+                    *slot = Err(Error::Io(std::io::ErrorKind::UnexpectedEof.into()));
+                }
+                (Ok(moving), Ok(len)) => moving.done += len as usize,
+                (Ok(_), Err(e)) => *slot = Err(e),
+                (Err(_), _) => {}
+            }
+        }
+    }
+
+    /// Submit the operations and wait for all of them. One result per
+    /// operation, `Ok(0)` where there is none.
+    ///
+    /// # Safety
+    /// The buffers and the paths the operations point to must stay valid
+    /// until `run` returns.
+    unsafe fn run(&mut self, ops: &[Option<squeue::Entry>]) -> Vec<Result<u32>> {
+        let mut results = ops.iter().map(|_| Ok(0)).collect::<Vec<_>>();
+        let capacity = self.ring.params().sq_entries() as usize;
+        let pending = ops
+            .iter()
+            .enumerate()
+            .filter_map(|(i, op)| Some(op.as_ref()?.clone().user_data(i as u64)))
+            .collect::<Vec<_>>();
+        for chunk in pending.chunks(capacity) {
+            if let Err(e) = self.run_chunk(chunk, &mut results) {
+                // the operations of the chunk that didn't complete
+                for op in chunk {
+                    let i = op.get_user_data() as usize;
+                    if matches!(results[i], Ok(0)) {
+                        results[i] = Err(Error::Io(std::io::Error::new(e.kind(), e.to_string())));
+                    }
+                }
+            }
+        }
+        results
+    }
+
+    /// # Safety
+    /// See `run`.
+    unsafe fn run_chunk(
+        &mut self,
+        chunk: &[squeue::Entry],
+        results: &mut [Result<u32>],
+    ) -> std::io::Result<()> {
+        if self.broken {
+            return Err(std::io::Error::other("io_uring submission failed earlier"));
+        }
+        // the queue is empty between the chunks and a chunk fits in it
+        self.ring
+            .submission()
+            .push_multiple(chunk)
+            .map_err(|_| std::io::Error::other("submission queue full"))?;
+        let mut completed = 0;
+        while completed < chunk.len() {
+            // the submitted operations may still use the buffers, retry
+            // until they complete unless the call itself is invalid
+            match self.ring.submit_and_wait(chunk.len() - completed) {
+                Err(e) if Self::retriable(&e) => continue,
+                Err(e) => {
+                    self.broken = true;
+                    // the operations the kernel didn't take stay in the queue
+                    let queued = self.ring.submission().len();
+                    self.drain((chunk.len() - completed).saturating_sub(queued), results);
+                    return Err(e);
+                }
+                Ok(_) => {}
+            };
+            completed += self.reap(results);
+        }
+        Ok(())
+    }
+
+    /// wait for the `in_flight` operations submitted before a failed
+    /// submission, without submitting the queued ones
+    fn drain(&mut self, mut in_flight: usize, results: &mut [Result<u32>]) {
+        while in_flight > 0 {
+            // SAFETY: no argument is passed
+            let waited = unsafe {
+                self.ring.submitter().enter::<libc::sigset_t>(
+                    0,
+                    1,
+                    io_uring::EnterFlags::GETEVENTS.bits(),
+                    None,
+                )
+            };
+            match waited {
+                Err(e) if !Self::retriable(&e) => {
+                    // the kernel may still write to the buffers of the batch
+                    // once they are freed
+                    std::process::abort();
+                }
+                _ => in_flight = in_flight.saturating_sub(self.reap(results)),
+            }
+        }
+    }
+
+    /// record the results of the completed operations, return their number
+    fn reap(&mut self, results: &mut [Result<u32>]) -> usize {
+        let mut completed = 0;
+        for cqe in self.ring.completion() {
+            results[cqe.user_data() as usize] = match cqe.result() {
+                res if res < 0 => Err(Error::Io(std::io::Error::from_raw_os_error(-res))),
+                res => Ok(res as u32),
+            };
+            completed += 1;
+        }
+        completed
+    }
+
+    fn retriable(e: &std::io::Error) -> bool {
+        matches!(
+            e.kind(),
+            std::io::ErrorKind::Interrupted
+                | std::io::ErrorKind::WouldBlock
+                | std::io::ErrorKind::ResourceBusy
+        )
+    }
+}
+
+fn zeroed_statx() -> libc::statx {
+    // SAFETY: plain C struct, all zeroes is a valid value
+    unsafe { std::mem::zeroed() }
+}
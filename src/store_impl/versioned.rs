@@ -0,0 +1,238 @@
+use anyhow::anyhow;
+use parking_lot::Mutex;
+
+use crate::{
+    error::{BlobError, Error, Result},
+    BlobMeta, BlobStore, ConditionalBlobStore, DeleteOpt, GetOpt, Key, Precondition, PutOpt,
+};
+
+/// Header of a versioned blob: `magic | version`.
+const MAGIC: [u8; 4] = *b"BSV1";
+const HEADER_LEN: usize = 12;
+
+/// Maintain a version for every blob of the inner store, and support
+/// conditional writes on it.
+///
+/// Versions only increase, also across the deletion and recreation of a blob:
+/// a write sets the version to the current time in nanoseconds, or past both
+/// the previous version of the blob and the last version issued by this store
+/// if the clock is behind them. The last version issued is kept in memory, so
+/// a blob deleted and recreated after the store is reopened relies on the
+/// clock alone, and can get an older version if the clock went back in
+/// between. Writes through this store are serialized, so a precondition holds
+/// until the write it guards is done. Other wrappers don't report the version
+/// of their inner store, so put them under this one.
+pub struct VersionedStore<S>
+where
+    S: BlobStore,
+{
+    store: S,
+    /// the last version issued, its lock serializes the check and the write
+    /// of conditional operations
+    lock: Mutex<u64>,
+}
+
+impl<S> VersionedStore<S>
+where
+    S: BlobStore,
+{
+    pub fn new(store: S) -> Self {
+        Self {
+            store,
+            lock: Mutex::new(0),
+        }
+    }
+
+    pub fn inner(&self) -> &S {
+        &self.store
+    }
+
+    pub fn into_inner(self) -> S {
+        self.store
+    }
+
+    /// size of the content of a blob, after its header
+    fn content_size(&self, key: Key) -> Result<usize> {
+        self.store
+            .meta(key)?
+            .size
+            .checked_sub(HEADER_LEN)
+            .ok_or_else(|| Error::other(anyhow!("not a versioned blob")))
+    }
+
+    fn read_version(&self, key: Key) -> Result<u64> {
+        let mut header = [0_u8; HEADER_LEN];
+        self.store
+            .get(key, &mut header, GetOpt::Range(0..HEADER_LEN))?;
+        if header[0..4] != MAGIC {
+            return Err(Error::other(anyhow!("not a versioned blob")));
+        }
+        Ok(u64::from_le_bytes(header[4..12].try_into().unwrap()))
+    }
+
+    /// version of the blob, `None` if it doesn't exist
+    fn current_version(&self, key: Key) -> Result<Option<u64>> {
+        match self.read_version(key) {
+            Ok(version) => Ok(Some(version)),
+            Err(Error::Blob(BlobError::NotFound)) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    fn next_version(current: Option<u64>, last: u64) -> u64 {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        now.max(current.map_or(1, |version| version + 1))
+            .max(last + 1)
+    }
+
+    fn check(current: Option<u64>, precondition: Precondition) -> Result<()> {
+        let holds = match precondition {
+            Precondition::IfMatch(version) => current == Some(version),
+            Precondition::IfNoneMatch => current.is_none(),
+        };
+        if !holds {
+            return Err(BlobError::PreconditionFailed.into());
+        }
+        Ok(())
+    }
+
+    /// put with the lock held, return the new version
+    fn put_locked(
+        &self,
+        key: Key,
+        value: &[u8],
+        opt: PutOpt,
+        current: Option<u64>,
+        last: &mut u64,
+    ) -> Result<u64> {
+        let version = Self::next_version(current, *last);
+        *last = version;
+        let mut header = [0_u8; HEADER_LEN];
+        header[0..4].copy_from_slice(&MAGIC);
+        header[4..12].copy_from_slice(&version.to_le_bytes());
+        match opt {
+            PutOpt::Create | PutOpt::ReplaceOrCreate => {
+                let mut blob = Vec::with_capacity(HEADER_LEN + value.len());
+                blob.extend_from_slice(&header);
+                blob.extend_from_slice(value);
+                self.store.put(key, &blob, opt)?;
+            }
+            PutOpt::Replace(range) => {
+                if current.is_none() {
+                    return Err(BlobError::NotFound.into());
+                }
+                let size = self.content_size(key)?;
+                if !crate::store_impl::helpers::range_contains(&(0..size), &range) {
+                    return Err(BlobError::RangeError.into());
+                }
+                if value.len() != range.len() {
+                    return Err(BlobError::RangeError.into());
+                }
+                // the version first: if the data isn't written then, the blob
+                // has a new version for its old data, never its new data under
+                // the old version
+                self.store
+                    .put(key, &header, PutOpt::Replace(0..HEADER_LEN))?;
+                self.store.put(
+                    key,
+                    value,
+                    PutOpt::Replace(HEADER_LEN + range.start..HEADER_LEN + range.end),
+                )?;
+            }
+        }
+        Ok(version)
+    }
+}
+
+impl<S> BlobStore for VersionedStore<S>
+where
+    S: BlobStore,
+{
+    fn contains(&self, key: Key) -> Result<bool> {
+        self.store.contains(key)
+    }
+
+    fn meta(&self, key: Key) -> Result<BlobMeta> {
+        let version = self.read_version(key)?;
+        let size = self.content_size(key)?;
+        Ok(BlobMeta {
+            size,
+            version: Some(version),
+            ..Default::default()
+        })
+    }
+
+    fn put(&self, key: Key, value: &[u8], opt: PutOpt) -> Result<()> {
+        let mut last = self.lock.lock();
+        let current = self.current_version(key)?;
+        self.put_locked(key, value, opt, current, &mut last)
+            .map(|_| ())
+    }
+
+    fn get(&self, key: Key, buf: &mut [u8], opt: GetOpt) -> Result<()> {
+        let size = self.content_size(key)?;
+        let range = match opt {
+            GetOpt::All => 0..size,
+            GetOpt::Range(range) => range,
+        };
+        if !crate::store_impl::helpers::range_contains(&(0..size), &range) {
+            return Err(BlobError::RangeError.into());
+        }
+        if buf.len() != range.len() {
+            return Err(BlobError::RangeError.into());
+        }
+        self.store.get(
+            key,
+            buf,
+            GetOpt::Range(HEADER_LEN + range.start..HEADER_LEN + range.end),
+        )
+    }
+
+    fn delete(&self, key: Key, opt: DeleteOpt) -> Result<Option<Vec<u8>>> {
+        let _guard = self.lock.lock();
+        let content = crate::store_impl::helpers::interest(self, key, &opt)?;
+        self.store.delete(key, DeleteOpt::Discard)?;
+        Ok(content)
+    }
+
+    fn keys(&self) -> Result<Vec<Key>> {
+        self.store.keys()
+    }
+
+    fn is_shared(&self) -> bool {
+        self.store.is_shared()
+    }
+}
+
+impl<S> ConditionalBlobStore for VersionedStore<S>
+where
+    S: BlobStore,
+{
+    fn put_if(
+        &self,
+        key: Key,
+        value: &[u8],
+        opt: PutOpt,
+        precondition: Precondition,
+    ) -> Result<u64> {
+        let mut last = self.lock.lock();
+        let current = self.current_version(key)?;
+        Self::check(current, precondition)?;
+        self.put_locked(key, value, opt, current, &mut last)
+    }
+
+    fn delete_if(
+        &self,
+        key: Key,
+        opt: DeleteOpt,
+        precondition: Precondition,
+    ) -> Result<Option<Vec<u8>>> {
+        let _guard = self.lock.lock();
+        Self::check(self.current_version(key)?, precondition)?;
+        let content = crate::store_impl::helpers::interest(self, key, &opt)?;
+        self.store.delete(key, DeleteOpt::Discard)?;
+        Ok(content)
+    }
+}
@@ -0,0 +1,238 @@
+use anyhow::anyhow;
+use serde::de::{DeserializeOwned, IntoDeserializer};
+
+use crate::{
+    error::{Error, Result},
+    store_impl::prelude::*,
+    BlobStore,
+};
+
+/// Open the store described by `uri`, selecting the backend at run time:
+///
+/// - `file:///data/blobs?create=true&fsync=always`, a `LocalFileSystemBlobStore`
+/// - `mmap:///data/blobs?cache=128`, a `MemMapStore`
+/// - `sqlite:///data/db?synchronous=normal&mmap_size=268435456`, a `SqliteBlobStore`
+/// - `memory://`, a `MemoryBlobStore`
+///
+/// The path is absolute, with an empty or `localhost` host, and `%XX` escapes
+/// are decoded. The query sets the fields of the configuration of the store,
+/// by their names in `LocalFileSystemConfig`, `MemMapConfig` and
+/// `SqliteConfig`, with these short names:
+///
+/// - `create`: `create_if_missing`
+/// - `fsync=never|data|always`: the `durability` `none`, `data` or `full`
+/// - `cache`: the `fd_cache` of `file`, the `cache_size` of `mmap`
+/// - `direct`: the `direct_io` of `file`
+///
+/// # Error
+/// - Other: the URI is malformed, its scheme is unknown or needs a disabled
+///   cargo feature, or an option is unknown or invalid.
+pub fn open(uri: &str) -> Result<Box<dyn BlobStore>> {
+    let (scheme, rest) = uri
+        .split_once("://")
+        .ok_or_else(|| invalid(uri, "no scheme"))?;
+    let (location, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let path = match location.find('/') {
+        Some(0) => location,
+        Some(i) if &location[..i] == "localhost" => &location[i..],
+        None if location.is_empty() => "",
+        _ => return Err(invalid(uri, "only local paths are supported")),
+    };
+    let path = decode(path).ok_or_else(|| invalid(uri, "invalid escape in the path"))?;
+    let options = Options::parse(uri, query)?;
+    if path.is_empty() && scheme != "memory" {
+        return Err(invalid(uri, "no path"));
+    }
+    let store: Box<dyn BlobStore> = match scheme {
+        "file" => Box::new(options.file()?.open(path)?),
+        #[cfg(feature = "memmap")]
+        "mmap" => Box::new(options.mmap()?.open(path)?),
+        #[cfg(feature = "sqlite")]
+        "sqlite" => Box::new(options.sqlite()?.open(path)?),
+        #[cfg(not(feature = "memmap"))]
+        "mmap" => return Err(disabled(scheme, "memmap")),
+        #[cfg(not(feature = "sqlite"))]
+        "sqlite" => return Err(disabled(scheme, "sqlite")),
+        "memory" if path.is_empty() => {
+            options.finish()?;
+            Box::new(MemoryBlobStore::new())
+        }
+        "memory" => return Err(invalid(uri, "a memory store has no path")),
+        _ => return Err(invalid(uri, "unknown scheme")),
+    };
+    Ok(store)
+}
+
+fn invalid(uri: &str, reason: &str) -> Error {
+    Error::Other(anyhow!("invalid store URI `{uri}`: {reason}"))
+}
+
+#[cfg(not(all(feature = "memmap", feature = "sqlite")))]
+fn disabled(scheme: &str, feature: &str) -> Error {
+    Error::Other(anyhow!(
+        "`{scheme}://` stores need the `{feature}` feature of blob-store"
+    ))
+}
+
+/// decode the `%XX` escapes of `s`
+fn decode(s: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(s.len());
+    let mut rest = s.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+/// The options of the query of a URI, each taken once by the configuration.
+struct Options<'u> {
+    uri: &'u str,
+    options: Vec<(String, String)>,
+}
+
+impl<'u> Options<'u> {
+    fn parse(uri: &'u str, query: &str) -> Result<Self> {
+        let mut options = Vec::new();
+        for option in query.split('&').filter(|option| !option.is_empty()) {
+            // a bare name is a flag
+            let (name, value) = option.split_once('=').unwrap_or((option, "true"));
+            let value = decode(value).ok_or_else(|| invalid(uri, "invalid escape in the query"))?;
+            options.push((name.to_string(), value));
+        }
+        Ok(Self { uri, options })
+    }
+
+    fn take(&mut self, name: &str) -> Option<String> {
+        let i = self.options.iter().position(|(n, _)| n == name)?;
+        Some(self.options.remove(i).1)
+    }
+
+    /// take an option parsed with `FromStr`
+    fn parse_value<T: std::str::FromStr>(&mut self, name: &str) -> Result<Option<T>> {
+        self.take(name)
+            .map(|value| {
+                value
+                    .parse()
+                    .map_err(|_| invalid(self.uri, &format!("invalid value `{value}` of `{name}`")))
+            })
+            .transpose()
+    }
+
+    /// take an option naming a variant of a configuration enum
+    fn parse_variant<T: DeserializeOwned>(&mut self, name: &str) -> Result<Option<T>> {
+        self.take(name)
+            .map(|value| {
+                T::deserialize(value.as_str().into_deserializer()).map_err(
+                    |_: serde::de::value::Error| {
+                        invalid(self.uri, &format!("invalid value `{value}` of `{name}`"))
+                    },
+                )
+            })
+            .transpose()
+    }
+
+    fn durability(&mut self) -> Result<Option<Durability>> {
+        self.take("fsync")
+            .map(|value| match value.as_str() {
+                "never" => Ok(Durability::None),
+                "data" => Ok(Durability::Data),
+                "always" => Ok(Durability::Full),
+                _ => Err(invalid(
+                    self.uri,
+                    &format!("invalid value `{value}` of `fsync`"),
+                )),
+            })
+            .transpose()
+    }
+
+    /// fail on the options left
+    fn finish(self) -> Result<()> {
+        match self.options.first() {
+            Some((name, _)) => Err(invalid(self.uri, &format!("unknown option `{name}`"))),
+            None => Ok(()),
+        }
+    }
+
+    fn file(mut self) -> Result<LocalFileSystemConfig> {
+        let mut config = LocalFileSystemConfig::default();
+        if let Some(create) = self.parse_value("create")? {
+            config.create_if_missing = create;
+        }
+        if let Some(mode) = self.parse_variant("mode")? {
+            config.mode = mode;
+        }
+        if let Some(layout) = self.parse_variant("layout")? {
+            config.layout = layout;
+        }
+        if let Some(durability) = self.durability()? {
+            config.durability = durability;
+        }
+        if let Some(capacity) = self.parse_value("cache")? {
+            config.fd_cache = capacity;
+        }
+        if let Some(direct) = self.parse_value("direct")? {
+            config.direct_io = direct;
+        }
+        if let Some(max_key_len) = self.parse_value("max_key_len")? {
+            config.max_key_len = max_key_len;
+        }
+        self.finish()?;
+        Ok(config)
+    }
+
+    #[cfg(feature = "memmap")]
+    fn mmap(mut self) -> Result<MemMapConfig> {
+        let mut config = MemMapConfig::default();
+        if let Some(create) = self.parse_value("create")? {
+            config.create_if_missing = create;
+        }
+        if let Some(mode) = self.parse_variant("mode")? {
+            config.mode = mode;
+        }
+        if let Some(layout) = self.parse_variant("layout")? {
+            config.layout = layout;
+        }
+        if let Some(durability) = self.durability()? {
+            config.durability = durability;
+        }
+        if let Some(cache_size) = self.parse_value("cache")? {
+            config.cache_size = cache_size;
+        }
+        if let Some(max_key_len) = self.parse_value("max_key_len")? {
+            config.max_key_len = max_key_len;
+        }
+        self.finish()?;
+        Ok(config)
+    }
+
+    #[cfg(feature = "sqlite")]
+    fn sqlite(mut self) -> Result<SqliteConfig> {
+        let mut config = SqliteConfig::default();
+        if let Some(create) = self.parse_value("create")? {
+            config.create_if_missing = create;
+        }
+        if let Some(mode) = self.parse_variant("mode")? {
+            config.mode = mode;
+        }
+        if let Some(journal_mode) = self.parse_variant("journal_mode")? {
+            config.journal_mode = journal_mode;
+        }
+        config.synchronous = self.parse_variant("synchronous")?;
+        config.page_size = self.parse_value("page_size")?;
+        config.cache_size = self.parse_value("cache_size")?;
+        config.mmap_size = self.parse_value("mmap_size")?;
+        config.checkpoint_interval = self.parse_value("checkpoint_interval")?;
+        if let Some(max_key_len) = self.parse_value("max_key_len")? {
+            config.max_key_len = max_key_len;
+        }
+        self.finish()?;
+        Ok(config)
+    }
+}
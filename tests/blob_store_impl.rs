@@ -13,12 +13,378 @@ fn test_local_fs() {
     common::dump(|| {
         LocalFileSystemBlobStore::connect(tmp_dir.path())
             .map(|obj| -> Box<dyn BlobStore> { Box::new(obj) })
-            .map_err(Into::into)
     });
     // concurrecy
     let tmp_dir = tempfile::tempdir().unwrap();
     let store = std::sync::Arc::new(LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap());
     common::concurrent(store);
+    // buckets
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    common::buckets(&store);
+    // variable-length keys
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    common::var_keys(&store);
+    common::delete_interest(&store);
+    // transactions
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    common::transactions(&store);
+    let key = 1_u64.as_key();
+    let mut txn = store.begin().unwrap();
+    txn.put(key, b"staged", PutOpt::Create).unwrap();
+    assert!(!store.contains(key).unwrap());
+    // a transaction interrupted before its commit is discarded
+    std::mem::forget(txn);
+    drop(store);
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    assert!(!store.contains(key).unwrap());
+    assert_eq!(
+        std::fs::read_dir(tmp_dir.path().join("txn"))
+            .unwrap()
+            .count(),
+        0
+    );
+    // a committed transaction interrupted before it is applied is replayed
+    let txn_dir = tmp_dir.path().join("txn").join("interrupted");
+    std::fs::create_dir_all(&txn_dir).unwrap();
+    std::fs::write(txn_dir.join(hex::encode(key)), b"replayed").unwrap();
+    std::fs::write(txn_dir.join("intent"), [&[1_u8][..], &key].concat()).unwrap();
+    drop(store);
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), b"replayed");
+    assert!(!txn_dir.exists());
+    // a write committed meanwhile makes the transaction fail and stay unapplied
+    let other = 2_u64.as_key();
+    let mut txn = store.begin().unwrap();
+    txn.put(other, b"staged", PutOpt::Create).unwrap();
+    store.put(other, b"direct", PutOpt::Create).unwrap();
+    assert!(matches!(
+        txn.commit(),
+        Err(BlobStoreError::Blob(error::BlobError::PreconditionFailed))
+    ));
+    assert_eq!(store.get_owned(other, GetOpt::All).unwrap(), b"direct");
+    let mut txn = store.begin().unwrap();
+    txn.put(key, b"staged", PutOpt::Replace(0..6)).unwrap();
+    txn.put(other, b"staged", PutOpt::Replace(0..6)).unwrap();
+    store.put(other, b"rewrit", PutOpt::Replace(0..6)).unwrap();
+    assert!(matches!(
+        txn.commit(),
+        Err(BlobStoreError::Blob(error::BlobError::PreconditionFailed))
+    ));
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), b"replayed");
+    assert_eq!(store.get_owned(other, GetOpt::All).unwrap(), b"rewrit");
+    assert_eq!(
+        std::fs::read_dir(tmp_dir.path().join("txn"))
+            .unwrap()
+            .count(),
+        0
+    );
+    // opening a bucket leaves the transactions in flight on it alone
+    let bucket = store.create_bucket("txn").unwrap();
+    let mut txn = bucket.begin().unwrap();
+    txn.put(key, b"staged", PutOpt::Create).unwrap();
+    let again = store.bucket("txn").unwrap();
+    txn.commit().unwrap();
+    assert_eq!(again.get_owned(key, GetOpt::All).unwrap(), b"staged");
+    // the store replays the committed transactions of its buckets
+    let txn_dir = tmp_dir.path().join("buckets/txn/txn/interrupted");
+    std::fs::create_dir_all(&txn_dir).unwrap();
+    std::fs::write(txn_dir.join(hex::encode(other)), b"replayed").unwrap();
+    std::fs::write(txn_dir.join("intent"), [&[1_u8][..], &other].concat()).unwrap();
+    drop((bucket, again, store));
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    let bucket = store.bucket("txn").unwrap();
+    assert_eq!(bucket.get_owned(other, GetOpt::All).unwrap(), b"replayed");
+    assert!(!txn_dir.exists());
+    // snapshots
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    common::snapshots(&store);
+    assert_eq!(
+        std::fs::read_dir(tmp_dir.path().join("snapshots"))
+            .unwrap()
+            .count(),
+        0
+    );
+    // the snapshots left by a crash are removed when the store is opened
+    std::mem::forget(store.snapshot().unwrap());
+    let bucket = store.create_bucket("crashed").unwrap();
+    std::mem::forget(bucket.snapshot().unwrap());
+    drop(bucket);
+    drop(store);
+    let store = LocalFileSystemBlobStore::connect_shared(tmp_dir.path()).unwrap();
+    assert_eq!(
+        std::fs::read_dir(tmp_dir.path().join("snapshots"))
+            .unwrap()
+            .count(),
+        1
+    );
+    drop(store);
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    assert!(!tmp_dir.path().join("snapshots").exists());
+    assert!(!tmp_dir.path().join("buckets/crashed/snapshots").exists());
+    drop(store);
+    // copy and rename
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    common::copy_rename(&store);
+    // batches
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    common::batches(&store);
+    #[cfg(feature = "io_uring")]
+    {
+        let tmp_dir = tempfile::tempdir().unwrap();
+        let store = LocalFileSystemBlobStore::connect(tmp_dir.path())
+            .unwrap()
+            .with_io_uring(64)
+            .unwrap();
+        common::batches(&store);
+        let tmp_dir = tempfile::tempdir().unwrap();
+        // batches larger than the ring, flushed
+        let store = LocalFileSystemConfig::new()
+            .with_durability(Durability::Full)
+            .with_fd_cache(16)
+            .open(tmp_dir.path())
+            .unwrap()
+            .with_io_uring(8)
+            .unwrap();
+        common::batches(&store);
+    }
+    // borrowed reads fall back to a copy
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    common::borrowed_reads(&store);
+    // vectored reads and writes
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    common::vectored(&store);
+    // file descriptor cache
+    let cached = |path: &std::path::Path| {
+        LocalFileSystemBlobStore::connect(path)
+            .unwrap()
+            .with_fd_cache(16)
+    };
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::write_read(&cached(tmp_dir.path()));
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::concurrent(std::sync::Arc::new(cached(tmp_dir.path())));
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::copy_rename(&cached(tmp_dir.path()));
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::vectored(&cached(tmp_dir.path()));
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::transactions(&cached(tmp_dir.path()));
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::snapshots(&cached(tmp_dir.path()));
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = cached(tmp_dir.path());
+    let key = 1_u64.as_key();
+    store.put(key, b"cached", PutOpt::Create).unwrap();
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), b"cached");
+    store
+        .put(key, b"resized blob", PutOpt::ReplaceOrCreate)
+        .unwrap();
+    assert_eq!(store.meta(key).unwrap().size, 12);
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), b"resized blob");
+    // a cached blob linked by a snapshot keeps the content of the snapshot
+    let snapshot = store.snapshot().unwrap();
+    store.put(key, b"R", PutOpt::Replace(0..1)).unwrap();
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), b"Resized blob");
+    assert_eq!(
+        snapshot.get_owned(key, GetOpt::All).unwrap(),
+        b"resized blob"
+    );
+    drop(snapshot);
+    store.delete(key, DeleteOpt::Discard).unwrap();
+    assert!(!store.contains(key).unwrap());
+    assert!(matches!(
+        store.get_owned(key, GetOpt::All),
+        Err(BlobStoreError::Blob(error::BlobError::NotFound))
+    ));
+    // direct I/O
+    let direct = |path: &std::path::Path| {
+        LocalFileSystemBlobStore::connect(path)
+            .unwrap()
+            .with_direct_io(true)
+    };
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::write_read(&direct(tmp_dir.path()));
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::vectored(&direct(tmp_dir.path()));
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::var_keys(&direct(tmp_dir.path()));
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::copy_rename(&direct(tmp_dir.path()).with_fd_cache(16));
+    // ranges across block boundaries, blob tails
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = direct(tmp_dir.path());
+    let random = |len: usize| (0..len).map(|_| rand::random::<u8>()).collect::<Vec<_>>();
+    let key = 1_u64.as_key();
+    let mut expect = random(10000);
+    store.put(key, &expect, PutOpt::Create).unwrap();
+    for range in [4090..4110, 0..1, 9999..10000, 4096..8192, 100..9000] {
+        let patch = random(range.len());
+        store
+            .put(key, &patch, PutOpt::Replace(range.clone()))
+            .unwrap();
+        expect[range.clone()].copy_from_slice(&patch);
+        assert_eq!(store.meta(key).unwrap().size, expect.len());
+        assert_eq!(
+            store.get_owned(key, GetOpt::Range(range.clone())).unwrap(),
+            expect[range]
+        );
+    }
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), expect);
+    store.put(key, b"short", PutOpt::ReplaceOrCreate).unwrap();
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), b"short");
+    // preallocation and holes
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    common::sized(&store);
+    let blocks = || {
+        use std::os::unix::fs::MetadataExt;
+        let path = tmp_dir.path().join("03000000").join("00000000");
+        std::fs::metadata(path).unwrap().blocks()
+    };
+    let key = 3_u64.as_key();
+    store.create_sized(key, 1 << 20).unwrap();
+    let allocated = blocks();
+    assert!(allocated >= (1 << 20) / 512);
+    store.punch_hole(key, 0..1 << 19).unwrap();
+    assert!(blocks() < allocated);
+    // read-only
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    let expect = common::put_blobs(&store);
+    let bucket_expect = vec![(1_u64.as_key(), b"bucket".to_vec())];
+    let bucket = store.create_bucket("bucket").unwrap();
+    bucket
+        .put(1_u64.as_key(), b"bucket", PutOpt::Create)
+        .unwrap();
+    drop((bucket, store));
+    let store = LocalFileSystemBlobStore::connect_read_only(tmp_dir.path())
+        .unwrap()
+        .with_fd_cache(16);
+    common::read_only(&store, &expect);
+    common::read_only(&store.bucket("bucket").unwrap(), &bucket_expect);
+    assert!(matches!(
+        store.create_bucket("new"),
+        Err(BlobStoreError::ReadOnly)
+    ));
+    assert!(matches!(store.begin(), Err(BlobStoreError::ReadOnly)));
+    drop(store);
+    // locking
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::locking(
+        || LocalFileSystemBlobStore::connect(tmp_dir.path()),
+        || LocalFileSystemBlobStore::connect_read_only(tmp_dir.path()),
+    );
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    assert!(matches!(
+        LocalFileSystemBlobStore::connect_shared(tmp_dir.path()),
+        Err(BlobStoreError::Locked)
+    ));
+    drop(store);
+    // shared access
+    let store = LocalFileSystemBlobStore::connect_shared(tmp_dir.path()).unwrap();
+    let other = LocalFileSystemBlobStore::connect_shared(tmp_dir.path()).unwrap();
+    let reader = LocalFileSystemBlobStore::connect_read_only(tmp_dir.path()).unwrap();
+    assert!(matches!(
+        LocalFileSystemBlobStore::connect(tmp_dir.path()),
+        Err(BlobStoreError::Locked)
+    ));
+    let key = 1_u64.as_key();
+    store.put(key, b"shared", PutOpt::Create).unwrap();
+    other.put(key, b"S", PutOpt::Replace(0..1)).unwrap();
+    assert_eq!(reader.get_owned(key, GetOpt::All).unwrap(), b"Shared");
+    other.delete(key, DeleteOpt::Discard).unwrap();
+    assert!(!store.contains(key).unwrap());
+    assert!(matches!(store.begin(), Err(BlobStoreError::Other(_))));
+    assert!(matches!(store.snapshot(), Err(BlobStoreError::Other(_))));
+    drop((store, other, reader));
+    // configuration
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let root = tmp_dir.path().join("store");
+    let config: LocalFileSystemConfig = toml::from_str(
+        r#"
+        create_if_missing = true
+        layout = "flat"
+        durability = "full"
+        fd_cache = 16
+        "#,
+    )
+    .unwrap();
+    assert_eq!(
+        config,
+        LocalFileSystemConfig::new()
+            .with_create_if_missing(true)
+            .with_layout(Layout::Flat)
+            .with_durability(Durability::Full)
+            .with_fd_cache(16)
+    );
+    let store = config.open(&root).unwrap();
+    common::copy_rename(&store);
+    common::copy_rename(&store.create_bucket("flat").unwrap());
+    store
+        .put(3_u64.as_key(), b"flat", PutOpt::ReplaceOrCreate)
+        .unwrap();
+    assert!(root.join("0300000000000000").is_file());
+    let keys = store.keys().unwrap();
+    drop(store);
+    // the blobs are only found with the layout of the store
+    let store = LocalFileSystemBlobStore::connect(&root).unwrap();
+    assert!(store.keys().unwrap().is_empty());
+    drop(store);
+    let store = config.open(&root).unwrap();
+    assert_eq!(store.keys().unwrap().len(), keys.len());
+    drop(store);
+    // the descriptor cache would miss the writes of the other handles
+    assert!(matches!(
+        config.clone().with_mode(OpenMode::Shared).open(&root),
+        Err(BlobStoreError::Other(_))
+    ));
+    let store = config
+        .clone()
+        .with_mode(OpenMode::Shared)
+        .with_fd_cache(0)
+        .open(&root)
+        .unwrap();
+    assert_eq!(store.keys().unwrap().len(), keys.len());
+    assert!(
+        serde_json::from_str::<LocalFileSystemConfig>(r#"{"fd_cache": 16, "cache": 1}"#).is_err()
+    );
+    assert!(matches!(
+        LocalFileSystemBlobStore::connect(tmp_dir.path().join("missing")),
+        Err(BlobStoreError::Io(_))
+    ));
+    // usage
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    common::stats(&store);
+    common::var_key_bucket_stats(&store);
+    store
+        .create_bucket("kept")
+        .unwrap()
+        .put(1_u64.as_key(), b"kept", PutOpt::Create)
+        .unwrap();
+    let stats = store.stats().unwrap();
+    drop(store);
+    // saved on drop
+    assert!(tmp_dir.path().join("usage.stats").is_file());
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    assert!(!tmp_dir.path().join("usage.stats").exists());
+    assert_eq!(store.stats().unwrap(), stats);
+    drop(store);
+    // counted again after a crash
+    std::fs::remove_file(tmp_dir.path().join("usage.stats")).unwrap();
+    let store = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    assert_eq!(store.stats().unwrap(), stats);
+    drop(store);
+    let store = LocalFileSystemBlobStore::connect_shared(tmp_dir.path()).unwrap();
+    assert!(matches!(store.stats(), Err(BlobStoreError::Other(_))));
 }
 
 #[test]
@@ -31,10 +397,217 @@ fn test_sqlite() {
     // dump
     let tmp_dir = tempfile::tempdir().unwrap();
     common::dump(|| {
-        SqliteBlobStore::connect(tmp_dir.path())
-            .map(|obj| -> Box<dyn BlobStore> { Box::new(obj) })
-            .map_err(Into::into)
+        SqliteBlobStore::connect(tmp_dir.path()).map(|obj| -> Box<dyn BlobStore> { Box::new(obj) })
     });
+    // buckets
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    common::buckets(&store);
+    // buckets survive reopening
+    let expect = common::put_blobs(&store.create_bucket("reopen").unwrap());
+    drop(store);
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    common::check_match(&store.bucket("reopen").unwrap(), &expect);
+    // a failed drop keeps the bucket and all its blobs
+    drop(store);
+    rusqlite::Connection::open(tmp_dir.path().join("blobs.db"))
+        .unwrap()
+        .execute_batch(
+            "CREATE TRIGGER keep_buckets BEFORE DELETE ON buckets \
+             BEGIN SELECT RAISE(ABORT, 'kept'); END",
+        )
+        .unwrap();
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    assert!(store.drop_bucket("reopen").is_err());
+    common::check_match(&store.bucket("reopen").unwrap(), &expect);
+    drop(store);
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    common::check_match(&store.bucket("reopen").unwrap(), &expect);
+    // variable-length keys
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    common::var_keys(&store);
+    common::var_keys(&store.create_bucket("var").unwrap());
+    common::delete_interest(&store);
+    // transactions
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    common::transactions(&store);
+    let keys = store.keys().unwrap();
+    drop(store);
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    assert_eq!(store.keys().unwrap().len(), keys.len());
+    // the writes made through the store are rolled back with the transaction
+    let (a, b) = ((u64::MAX - 1).as_key(), u64::MAX.as_key());
+    let txn = store.begin().unwrap();
+    store.put(a, b"a", PutOpt::Create).unwrap();
+    let bucket = store.create_bucket("rolled-back").unwrap();
+    bucket.put(b, b"b", PutOpt::Create).unwrap();
+    txn.rollback().unwrap();
+    assert!(!store.contains(a).unwrap());
+    assert!(store.bucket("rolled-back").is_err());
+    // and their rows, reused by the next writes, are not aliased
+    store.put(b, b"new b", PutOpt::Create).unwrap();
+    store.put(a, b"new a", PutOpt::Create).unwrap();
+    assert_eq!(store.get_owned(a, GetOpt::All).unwrap(), b"new a");
+    assert_eq!(store.get_owned(b, GetOpt::All).unwrap(), b"new b");
+    // snapshots
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    common::snapshots(&store);
+    // copy and rename
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    common::copy_rename(&store);
+    common::copy_rename(&store.create_bucket("copy").unwrap());
+    store.put_var(b"object/a", b"var", PutOpt::Create).unwrap();
+    store
+        .rename_var(b"object/a", b"object/b", CopyOpt::Create)
+        .unwrap();
+    assert!(!store.contains_var(b"object/a").unwrap());
+    assert_eq!(
+        store.get_owned_var(b"object/b", GetOpt::All).unwrap(),
+        b"var"
+    );
+    store
+        .copy_var(b"object/b", &7_u64.as_key(), CopyOpt::Create)
+        .unwrap();
+    assert_eq!(
+        store.get_owned(7_u64.as_key(), GetOpt::All).unwrap(),
+        b"var"
+    );
+    // vectored reads and writes
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    common::vectored(&store);
+    common::vectored(&store.create_bucket("vectored").unwrap());
+    // preallocation and holes
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    common::sized(&store);
+    common::sized(&store.create_bucket("sized").unwrap());
+    // read-only
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    let expect = common::put_blobs(&store);
+    let bucket_expect = common::put_blobs(&store.create_bucket("bucket").unwrap());
+    drop(store);
+    let map_path = tmp_dir.path().join("blobs.map.dump");
+    let modified = || std::fs::metadata(&map_path).unwrap().modified().unwrap();
+    let before = modified();
+    let store = SqliteBlobStore::connect_read_only(tmp_dir.path()).unwrap();
+    common::read_only(&store, &expect);
+    common::read_only(&store.bucket("bucket").unwrap(), &bucket_expect);
+    assert!(matches!(
+        store.put_var(b"object/a", b"var", PutOpt::Create),
+        Err(BlobStoreError::ReadOnly)
+    ));
+    assert!(matches!(store.begin(), Err(BlobStoreError::ReadOnly)));
+    drop(store);
+    // the key maps are not rewritten
+    assert_eq!(modified(), before);
+    // locking
+    let tmp_dir = tempfile::tempdir().unwrap();
+    common::locking(
+        || SqliteBlobStore::connect(tmp_dir.path()),
+        || SqliteBlobStore::connect_read_only(tmp_dir.path()),
+    );
+    // configuration
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let root = tmp_dir.path().join("store");
+    let config: SqliteConfig = serde_json::from_str(
+        r#"{
+            "create_if_missing": true,
+            "journal_mode": "delete",
+            "synchronous": "full",
+            "page_size": 8192,
+            "cache_size": -4096
+        }"#,
+    )
+    .unwrap();
+    assert_eq!(
+        config,
+        SqliteConfig::new()
+            .with_create_if_missing(true)
+            .with_journal_mode(JournalMode::Delete)
+            .with_synchronous(Synchronous::Full)
+            .with_page_size(8192)
+            .with_cache_size(-4096)
+    );
+    let store = config.open(&root).unwrap();
+    common::copy_rename(&store);
+    assert!(!root.join("blobs.db-wal").exists());
+    drop(store);
+    // the page size is in the header of the database
+    let header = std::fs::read(root.join("blobs.db")).unwrap();
+    assert_eq!(u16::from_be_bytes([header[16], header[17]]), 8192);
+    assert!(matches!(
+        config.clone().with_mode(OpenMode::Shared).open(&root),
+        Err(BlobStoreError::Other(_))
+    ));
+    // checkpoints
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let config: SqliteConfig =
+        toml::from_str("mmap_size = 16777216\ncheckpoint_interval = 4").unwrap();
+    assert_eq!(
+        config,
+        SqliteConfig::new()
+            .with_mmap_size(16 * 1024 * 1024)
+            .with_checkpoint_interval(4)
+    );
+    let store = config.open(tmp_dir.path()).unwrap();
+    let db_len = || {
+        std::fs::metadata(tmp_dir.path().join("blobs.db"))
+            .unwrap()
+            .len()
+    };
+    // the writes stay in the WAL until the fourth
+    let before = db_len();
+    for key in 1..4u64 {
+        store
+            .put(key.to_be_bytes(), &[1; 4096], PutOpt::Create)
+            .unwrap();
+    }
+    assert_eq!(db_len(), before);
+    store
+        .put(4u64.to_be_bytes(), &[1; 4096], PutOpt::Create)
+        .unwrap();
+    let after = db_len();
+    assert!(after > before);
+    store
+        .put(5u64.to_be_bytes(), &[1; 4096], PutOpt::Create)
+        .unwrap();
+    assert_eq!(db_len(), after);
+    store.checkpoint().unwrap();
+    assert!(db_len() > after);
+    assert_eq!(
+        store.get_owned(5u64.to_be_bytes(), GetOpt::All).unwrap(),
+        [1; 4096]
+    );
+    drop(store);
+    let store = SqliteBlobStore::connect_read_only(tmp_dir.path()).unwrap();
+    assert!(matches!(store.checkpoint(), Err(BlobStoreError::ReadOnly)));
+    assert!(matches!(
+        SqliteConfig::new()
+            .with_checkpoint_interval(0)
+            .open(tmp_dir.path()),
+        Err(BlobStoreError::Other(_))
+    ));
+    // usage
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    common::stats(&store);
+    common::var_key_bucket_stats(&store);
+    let stats = store.stats().unwrap();
+    // undone with the transaction
+    let mut txn = store.begin().unwrap();
+    txn.put(u64::MAX.as_key(), &[1; 100], PutOpt::Create)
+        .unwrap();
+    txn.rollback().unwrap();
+    assert_eq!(store.stats().unwrap(), stats);
+    drop(store);
+    let store = SqliteBlobStore::connect(tmp_dir.path()).unwrap();
+    assert_eq!(store.stats().unwrap(), stats);
 }
 
 #[test]
@@ -43,4 +616,744 @@ fn test_mapped_file() {
     let tmp_dir = tempfile::tempdir().unwrap();
     let store = MemMapStore::connect(tmp_dir.path()).unwrap();
     common::write_read(&store);
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = MemMapStore::connect(tmp_dir.path()).unwrap();
+    common::buckets(&store);
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = MemMapStore::connect(tmp_dir.path())
+        .unwrap()
+        .with_max_key_len(256);
+    common::var_keys(&store);
+    common::delete_interest(&store);
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = MemMapStore::connect(tmp_dir.path()).unwrap();
+    common::copy_rename(&store);
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = MemMapStore::connect(tmp_dir.path()).unwrap();
+    common::borrowed_reads(&store);
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = MemMapStore::connect(tmp_dir.path()).unwrap();
+    common::vectored(&store);
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = MemMapStore::connect(tmp_dir.path()).unwrap();
+    common::sized(&store);
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = MemMapStore::connect(tmp_dir.path()).unwrap();
+    let expect = common::put_blobs(&store);
+    drop(store);
+    let store = MemMapStore::connect_read_only(tmp_dir.path()).unwrap();
+    common::read_only(&store, &expect);
+    drop(store);
+    common::locking(
+        || MemMapStore::connect(tmp_dir.path()),
+        || MemMapStore::connect_read_only(tmp_dir.path()),
+    );
+    // configuration
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let root = tmp_dir.path().join("store");
+    let config: MemMapConfig = toml::from_str(
+        r#"
+        create_if_missing = true
+        layout = "flat"
+        durability = "data"
+        cache_size = 4
+        "#,
+    )
+    .unwrap();
+    let store = config.open(&root).unwrap();
+    common::copy_rename(&store);
+    common::sized(&store);
+    assert!(root.join("0100000000000000").is_file());
+    drop(store);
+    assert!(matches!(
+        config.clone().with_cache_size(0).open(&root),
+        Err(BlobStoreError::Other(_))
+    ));
+    assert!(matches!(
+        config.with_mode(OpenMode::Shared).open(&root),
+        Err(BlobStoreError::Other(_))
+    ));
+    // usage
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = MemMapStore::connect(tmp_dir.path()).unwrap();
+    common::stats(&store);
+    common::var_key_bucket_stats(&store);
+    let stats = store.stats().unwrap();
+    drop(store);
+    let store = MemMapStore::connect(tmp_dir.path()).unwrap();
+    assert_eq!(store.stats().unwrap(), stats);
+    drop(store);
+    std::fs::remove_file(tmp_dir.path().join("usage.stats")).unwrap();
+    let store = MemMapStore::connect(tmp_dir.path()).unwrap();
+    assert_eq!(store.stats().unwrap(), stats);
+}
+
+#[test]
+fn test_memory() {
+    common::write_read(&MemoryBlobStore::new());
+    common::delete_interest(&MemoryBlobStore::new());
+    common::concurrent(std::sync::Arc::new(MemoryBlobStore::new()));
+    common::copy_rename(&MemoryBlobStore::new());
+    common::borrowed_reads(&MemoryBlobStore::new());
+    common::vectored(&MemoryBlobStore::new());
+    common::sized(&MemoryBlobStore::new());
+    common::stats(&MemoryBlobStore::new());
+}
+
+#[test]
+fn test_sharded() {
+    let tmp_dirs = (0..5)
+        .map(|_| tempfile::tempdir().unwrap())
+        .collect::<Vec<_>>();
+    let shard = |i: usize| LocalFileSystemBlobStore::connect(tmp_dirs[i].path()).unwrap();
+    let mut store = ShardedStore::new(std::num::NonZeroUsize::new(32).unwrap());
+    (0..3).for_each(|i| {
+        let stats = store.add_shard(format!("shard-{i}"), shard(i)).unwrap();
+        assert_eq!(stats, RebalanceStats::default());
+    });
+    common::write_read(&store);
+    // add shard
+    let expect = common::put_blobs(&store);
+    let stats = store.add_shard("shard-3", shard(3)).unwrap();
+    assert!(stats.moved_blobs > 0 && stats.moved_blobs < expect.len());
+    common::check_match(&store, &expect);
+    expect.iter().for_each(|(key, _)| {
+        let owner = store.shard(store.shard_for(*key).unwrap()).unwrap();
+        assert!(owner.contains(*key).unwrap());
+    });
+    // remove shard
+    let (removed, stats) = store.remove_shard("shard-0").unwrap();
+    assert!(stats.moved_blobs > 0);
+    assert!(removed.keys().unwrap().is_empty());
+    common::check_match(&store, &expect);
+    assert_eq!(store.rebalance().unwrap(), RebalanceStats::default());
+    // a misplaced blob is left to rebalance by add_shard
+    let key = (0_u64..)
+        .map(|i| i.as_key())
+        .find(|key| store.shard_for(*key) != Some("shard-1"))
+        .unwrap();
+    let misplaced = store.shard("shard-1").unwrap();
+    misplaced.put(key, b"misplaced", PutOpt::Create).unwrap();
+    store.add_shard("shard-4", shard(4)).unwrap();
+    let stats = store.rebalance().unwrap();
+    assert!(stats.moved_blobs >= 1);
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), b"misplaced");
+}
+
+#[test]
+#[cfg(feature = "compress")]
+fn test_compressed() {
+    const FRAME_SIZE: usize = 512;
+    [Codec::Zstd { level: 3 }, Codec::Lz4]
+        .into_iter()
+        .for_each(|codec| {
+            let tmp_dir = tempfile::tempdir().unwrap();
+            let inner = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+            let store = CompressedStore::with_frame_size(inner, codec, FRAME_SIZE);
+            common::write_read(&store);
+            common::delete_interest(&store);
+            // compressible blob
+            let key = u64::MAX.as_key();
+            let mut expect = b"{\"level\":\"info\",\"msg\":\"blob stored\"}\n".repeat(256);
+            store.put(key, &expect, PutOpt::Create).unwrap();
+            assert_eq!(store.meta(key).unwrap().size, expect.len());
+            assert!(store.inner().meta(key).unwrap().size < expect.len() / 4);
+            let range = FRAME_SIZE - 7..3 * FRAME_SIZE + 11;
+            assert_eq!(
+                store.get_owned(key, GetOpt::Range(range.clone())).unwrap(),
+                &expect[range.clone()]
+            );
+            // replace across frames with incompressible data, the frames it
+            // covers are stored raw
+            use rand::{Rng, SeedableRng};
+            let mut rng = rand::rngs::StdRng::seed_from_u64(7919);
+            let patch = (0..range.len()).map(|_| rng.gen()).collect::<Vec<u8>>();
+            store
+                .put(key, &patch, PutOpt::Replace(range.clone()))
+                .unwrap();
+            expect[range].copy_from_slice(&patch);
+            assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), expect);
+            assert!(store.inner().meta(key).unwrap().size > 2 * FRAME_SIZE);
+        });
+}
+
+#[test]
+#[cfg(feature = "encrypt")]
+fn test_encrypted() {
+    const CHUNK_SIZE: usize = 512;
+    let secret: EncryptionKey = rand::random();
+    [Cipher::Aes256Gcm, Cipher::ChaCha20Poly1305]
+        .into_iter()
+        .for_each(|cipher| {
+            let tmp_dir = tempfile::tempdir().unwrap();
+            // a second handle reads with another key
+            let connect = || LocalFileSystemBlobStore::connect_shared(tmp_dir.path()).unwrap();
+            let store = EncryptedStore::with_chunk_size(connect(), cipher, &secret, CHUNK_SIZE);
+            common::write_read(&store);
+            common::delete_interest(&store);
+            let key = u64::MAX.as_key();
+            let mut expect = vec![0x5a_u8; 4 * CHUNK_SIZE + 3];
+            store.put(key, &expect, PutOpt::Create).unwrap();
+            assert_eq!(store.meta(key).unwrap().size, expect.len());
+            // the content is not stored in clear
+            let stored = store.inner().get_owned(key, GetOpt::All).unwrap();
+            assert!(!stored.windows(32).any(|w| w == &expect[..32]));
+            // replace across chunks
+            let range = CHUNK_SIZE - 5..2 * CHUNK_SIZE + 5;
+            let patch = vec![0xa5_u8; range.len()];
+            store
+                .put(key, &patch, PutOpt::Replace(range.clone()))
+                .unwrap();
+            expect[range.clone()].copy_from_slice(&patch);
+            assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), expect);
+            assert_eq!(
+                store.get_owned(key, GetOpt::Range(range.clone())).unwrap(),
+                &expect[range]
+            );
+            // a wrong key fails authentication
+            let other = EncryptedStore::new(connect(), cipher, &rand::random());
+            assert!(matches!(
+                other.get_owned(key, GetOpt::All),
+                Err(BlobStoreError::Blob(error::BlobError::AuthenticationFailed))
+            ));
+            // so does a flipped bit, only in the chunks it belongs to
+            let flipped = stored.len() - 1;
+            store
+                .inner()
+                .put(
+                    key,
+                    &[!stored[flipped]],
+                    PutOpt::Replace(flipped..flipped + 1),
+                )
+                .unwrap();
+            assert!(matches!(
+                store.get_owned(key, GetOpt::All),
+                Err(BlobStoreError::Blob(error::BlobError::AuthenticationFailed))
+            ));
+            assert!(store.get_owned(key, GetOpt::Range(0..CHUNK_SIZE)).is_ok());
+            // an empty blob is authenticated too, truncating a blob to a
+            // header claiming no content fails
+            let empty = (u64::MAX - 1).as_key();
+            store.put(empty, &[], PutOpt::Create).unwrap();
+            assert_eq!(store.meta(empty).unwrap().size, 0);
+            assert!(store.get_owned(empty, GetOpt::All).unwrap().is_empty());
+            let mut header = stored[..20].to_vec();
+            header[12..20].fill(0);
+            store
+                .inner()
+                .put(key, &header, PutOpt::ReplaceOrCreate)
+                .unwrap();
+            assert!(matches!(
+                store.meta(key),
+                Err(BlobStoreError::Blob(error::BlobError::AuthenticationFailed))
+            ));
+            assert!(matches!(
+                store.get_owned(key, GetOpt::All),
+                Err(BlobStoreError::Blob(error::BlobError::AuthenticationFailed))
+            ));
+        });
+}
+
+#[test]
+fn test_checksum() {
+    const CHUNK_SIZE: usize = 512;
+    [ChecksumAlgorithm::Crc32c, ChecksumAlgorithm::XxHash64]
+        .into_iter()
+        .for_each(|algorithm| {
+            let tmp_dir = tempfile::tempdir().unwrap();
+            let inner = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+            let store = ChecksumStore::with_chunk_size(inner, algorithm, CHUNK_SIZE);
+            common::write_read(&store);
+            common::delete_interest(&store);
+            let key = u64::MAX.as_key();
+            let expect = (0..4 * CHUNK_SIZE).map(|i| i as u8).collect::<Vec<_>>();
+            store.put(key, &expect, PutOpt::Create).unwrap();
+            let checksum = store.meta(key).unwrap().checksum.unwrap();
+            match algorithm {
+                ChecksumAlgorithm::Crc32c => {
+                    assert_eq!(checksum, Checksum::Crc32c(crc32c::crc32c(&expect)))
+                }
+                ChecksumAlgorithm::XxHash64 => {
+                    assert!(matches!(checksum, Checksum::ChunkTable(_)))
+                }
+            }
+            // the checksum follows the content
+            store
+                .put(
+                    key,
+                    &[0xff, 0xfe],
+                    PutOpt::Replace(CHUNK_SIZE..CHUNK_SIZE + 2),
+                )
+                .unwrap();
+            assert_ne!(store.meta(key).unwrap().checksum.unwrap(), checksum);
+            store
+                .put(
+                    key,
+                    &expect[CHUNK_SIZE..CHUNK_SIZE + 2],
+                    PutOpt::Replace(CHUNK_SIZE..CHUNK_SIZE + 2),
+                )
+                .unwrap();
+            assert_eq!(store.meta(key).unwrap().checksum.unwrap(), checksum);
+            assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), expect);
+            // flip a bit of the last chunk behind the back of the store
+            let stored = store.inner().get_owned(key, GetOpt::All).unwrap();
+            let flipped = stored.len() - 1;
+            store
+                .inner()
+                .put(
+                    key,
+                    &[stored[flipped] ^ 1],
+                    PutOpt::Replace(flipped..flipped + 1),
+                )
+                .unwrap();
+            let last_chunk = 3 * CHUNK_SIZE..4 * CHUNK_SIZE;
+            [
+                GetOpt::All,
+                GetOpt::Range(last_chunk.start + 1..last_chunk.start + 2),
+            ]
+            .into_iter()
+            .for_each(|opt| {
+                assert!(matches!(
+                    store.get_owned(key, opt),
+                    Err(BlobStoreError::Blob(error::BlobError::Corrupted))
+                ));
+            });
+            assert_eq!(
+                store
+                    .get_owned(key, GetOpt::Range(0..last_chunk.start))
+                    .unwrap(),
+                &expect[0..last_chunk.start]
+            );
+        });
+}
+
+#[test]
+#[cfg(feature = "dedup")]
+fn test_dedup() {
+    let connect = |dir: &tempfile::TempDir| LocalFileSystemBlobStore::connect(dir.path()).unwrap();
+    [Chunking::Whole, Chunking::DEFAULT_FASTCDC]
+        .into_iter()
+        .for_each(|chunking| {
+            let (index_dir, chunk_dir) =
+                (tempfile::tempdir().unwrap(), tempfile::tempdir().unwrap());
+            let store = DedupStore::new(connect(&index_dir), connect(&chunk_dir), chunking);
+            common::write_read(&store);
+            common::delete_interest(&store);
+            store
+                .keys()
+                .unwrap()
+                .into_iter()
+                .for_each(|key| assert!(store.delete(key, DeleteOpt::Discard).is_ok()));
+            assert!(store.chunks().keys().unwrap().is_empty());
+            // identical blobs share their content
+            let content = (0..256 * 1024)
+                .map(|_| rand::random::<u8>())
+                .collect::<Vec<_>>();
+            (0..16_u64).for_each(|key| {
+                store.put(key.as_key(), &content, PutOpt::Create).unwrap();
+            });
+            let chunk_count = store.chunks().keys().unwrap().len();
+            assert!(chunk_count >= 1);
+            // so do blobs sharing a region when chunking by content
+            let mut shifted = vec![0xaa_u8; 1000];
+            shifted.extend_from_slice(&content);
+            store
+                .put(16_u64.as_key(), &shifted, PutOpt::Create)
+                .unwrap();
+            assert_eq!(
+                store.get_owned(16_u64.as_key(), GetOpt::All).unwrap(),
+                shifted
+            );
+            let added = store.chunks().keys().unwrap().len() - chunk_count;
+            match chunking {
+                Chunking::Whole => assert_eq!(added, 1),
+                _ => assert!(added < chunk_count),
+            }
+            // a replace keeps the other references intact
+            store
+                .put(0_u64.as_key(), &[0, 0, 0], PutOpt::Replace(1000..1003))
+                .unwrap();
+            assert_eq!(
+                store.get_owned(1_u64.as_key(), GetOpt::All).unwrap(),
+                content
+            );
+            (0..=16_u64).for_each(|key| {
+                store.delete(key.as_key(), DeleteOpt::Discard).unwrap();
+            });
+            assert!(store.chunks().keys().unwrap().is_empty());
+        });
+}
+
+#[test]
+fn test_named() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = NamedStore::new(LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap());
+    common::write_read(&store);
+    common::delete_interest(&store);
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = NamedStore::new(LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap());
+    common::var_keys(&store);
+    // names are stored under their hash
+    store.put_var(b"alice", b"data", PutOpt::Create).unwrap();
+    assert!(store.inner().contains("alice".as_key()).unwrap());
+    assert_eq!(store.names().unwrap(), vec![b"alice".to_vec()]);
+    // the key of "bob" is taken by a fixed key
+    store.put("bob".as_key(), b"fixed", PutOpt::Create).unwrap();
+    assert!(!store.contains_var(b"bob").unwrap());
+    assert!(matches!(
+        store.get_owned_var(b"bob", GetOpt::All),
+        Err(BlobStoreError::Blob(error::BlobError::KeyCollision))
+    ));
+    for opt in [
+        PutOpt::Create,
+        PutOpt::ReplaceOrCreate,
+        PutOpt::Replace(0..4),
+    ] {
+        assert!(matches!(
+            store.put_var(b"bob", b"data", opt),
+            Err(BlobStoreError::Blob(error::BlobError::KeyCollision))
+        ));
+    }
+    assert!(matches!(
+        store.delete_var(b"bob", DeleteOpt::Discard),
+        Err(BlobStoreError::Blob(error::BlobError::KeyCollision))
+    ));
+    assert_eq!(
+        store.get_owned("bob".as_key(), GetOpt::All).unwrap(),
+        b"fixed"
+    );
+}
+
+#[test]
+fn test_key_like() {
+    assert_eq!(7_u32.as_key(), 7_u64.as_key());
+    assert_eq!((7_u128 | 1 << 64).as_key(), 7_u64.as_key());
+    assert_eq!([1_u8; 8].as_key(), [1_u8; 8]);
+    assert_ne!([1_u8; 16].as_key(), [2_u8; 16].as_key());
+    assert_eq!("name".as_key(), String::from("name").as_key());
+    assert_eq!((&"name").as_key(), "name".as_key());
+    assert_ne!("name".as_key(), "other".as_key());
+}
+
+#[test]
+fn test_versioned() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = VersionedStore::new(LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap());
+    common::write_read(&store);
+    common::delete_interest(&store);
+    // conditional writes
+    let key = 1_u64.as_key();
+    let v1 = store
+        .put_if(key, b"first", PutOpt::Create, Precondition::IfNoneMatch)
+        .unwrap();
+    assert_eq!(store.meta(key).unwrap().version, Some(v1));
+    assert!(matches!(
+        store.put_if(
+            key,
+            b"again",
+            PutOpt::ReplaceOrCreate,
+            Precondition::IfNoneMatch
+        ),
+        Err(BlobStoreError::Blob(error::BlobError::PreconditionFailed))
+    ));
+    let v2 = store
+        .put_if(key, b"F", PutOpt::Replace(0..1), Precondition::IfMatch(v1))
+        .unwrap();
+    assert!(v2 > v1);
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), b"First");
+    // a stale version fails
+    assert!(matches!(
+        store.put_if(
+            key,
+            b"stale",
+            PutOpt::ReplaceOrCreate,
+            Precondition::IfMatch(v1)
+        ),
+        Err(BlobStoreError::Blob(error::BlobError::PreconditionFailed))
+    ));
+    assert!(matches!(
+        store.delete_if(key, DeleteOpt::Discard, Precondition::IfMatch(v1)),
+        Err(BlobStoreError::Blob(error::BlobError::PreconditionFailed))
+    ));
+    // unconditional writes bump the version too
+    store.put(key, b"second", PutOpt::ReplaceOrCreate).unwrap();
+    let v3 = store.meta(key).unwrap().version.unwrap();
+    assert!(v3 > v2);
+    store
+        .delete_if(key, DeleteOpt::Discard, Precondition::IfMatch(v3))
+        .unwrap();
+    // versions keep increasing across recreation
+    let v4 = store
+        .put_if(key, b"third", PutOpt::Create, Precondition::IfNoneMatch)
+        .unwrap();
+    assert!(v4 > v3);
+    // a blob shorter than a header is not a versioned blob
+    let short = 3_u64.as_key();
+    store.inner().put(short, b"abc", PutOpt::Create).unwrap();
+    assert!(matches!(
+        store.get(short, &mut [0], GetOpt::Range(0..1)),
+        Err(BlobStoreError::Other(_))
+    ));
+    assert!(store.meta(short).is_err());
+    // concurrent compare-and-swap increments
+    let store = std::sync::Arc::new(store);
+    let counter = 2_u64.as_key();
+    store
+        .put(counter, &0_u64.to_le_bytes(), PutOpt::Create)
+        .unwrap();
+    let handles = (0..4)
+        .map(|_| {
+            let store = std::sync::Arc::clone(&store);
+            std::thread::spawn(move || {
+                for _ in 0..25 {
+                    loop {
+                        let version = store.meta(counter).unwrap().version.unwrap();
+                        let value = store.get_owned(counter, GetOpt::All).unwrap();
+                        let value = u64::from_le_bytes(value.try_into().unwrap()) + 1;
+                        match store.put_if(
+                            counter,
+                            &value.to_le_bytes(),
+                            PutOpt::Replace(0..8),
+                            Precondition::IfMatch(version),
+                        ) {
+                            Ok(_) => break,
+                            Err(BlobStoreError::Blob(error::BlobError::PreconditionFailed)) => {}
+                            Err(e) => panic!("{e}"),
+                        }
+                    }
+                }
+            })
+        })
+        .collect::<Vec<_>>();
+    handles.into_iter().for_each(|h| h.join().unwrap());
+    let value = store.get_owned(counter, GetOpt::All).unwrap();
+    assert_eq!(u64::from_le_bytes(value.try_into().unwrap()), 100);
+}
+
+#[test]
+fn test_expiring() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = ExpiringStore::new(LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap());
+    common::write_read(&store);
+    common::delete_interest(&store);
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let store = ExpiringStore::new(LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap());
+    let ttl = std::time::Duration::from_millis(200);
+    let (short, long) = (1_u64.as_key(), 2_u64.as_key());
+    store
+        .put_with_ttl(short, b"short", PutOpt::Create, ttl)
+        .unwrap();
+    store
+        .put_with_ttl(long, b"long", PutOpt::Create, ttl * 100)
+        .unwrap();
+    assert!(store.meta(short).unwrap().expires_at.is_some());
+    assert_eq!(store.get_owned(short, GetOpt::All).unwrap(), b"short");
+    std::thread::sleep(ttl);
+    // expired blobs are gone for reads
+    assert!(!store.contains(short).unwrap());
+    assert!(matches!(
+        store.get_owned(short, GetOpt::All),
+        Err(BlobStoreError::Blob(error::BlobError::NotFound))
+    ));
+    assert!(matches!(
+        store.put(short, b"S", PutOpt::Replace(0..1)),
+        Err(BlobStoreError::Blob(error::BlobError::NotFound))
+    ));
+    assert_eq!(store.get_owned(long, GetOpt::All).unwrap(), b"long");
+    // but still stored until swept
+    assert!(store.inner().contains(short).unwrap());
+    assert_eq!(store.expire_now().unwrap(), 1);
+    assert!(!store.inner().contains(short).unwrap());
+    // an expired blob can be created again
+    store
+        .put_with_ttl(short, b"short", PutOpt::Create, ttl)
+        .unwrap();
+    std::thread::sleep(ttl);
+    store.put(short, b"forever", PutOpt::Create).unwrap();
+    assert_eq!(store.meta(short).unwrap().expires_at, None);
+    // background sweeper
+    let store = std::sync::Arc::new(store);
+    store
+        .put_with_ttl(3_u64.as_key(), b"swept", PutOpt::Create, ttl)
+        .unwrap();
+    let sweeper = store.spawn_sweeper(ttl / 4);
+    std::thread::sleep(ttl * 3);
+    assert!(!store.inner().contains(3_u64.as_key()).unwrap());
+    drop(sweeper);
+    let mut keys = store.keys().unwrap();
+    keys.sort();
+    assert_eq!(keys, vec![short, long]);
+    // a blob rewritten while it is swept is kept
+    let key = 4_u64.as_key();
+    let sweeper = store.spawn_sweeper(std::time::Duration::from_micros(100));
+    for _ in 0..200 {
+        let ttl = std::time::Duration::from_micros(500);
+        store
+            .put_with_ttl(key, b"swept", PutOpt::ReplaceOrCreate, ttl)
+            .unwrap();
+        std::thread::sleep(ttl);
+        store.put(key, b"kept", PutOpt::ReplaceOrCreate).unwrap();
+        std::thread::sleep(ttl * 2);
+        assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), b"kept");
+    }
+    drop(sweeper);
+    // blobs not written by the store are reported, not read past their start
+    let key = 5_u64.as_key();
+    store.inner().put(key, b"raw", PutOpt::Create).unwrap();
+    assert!(store.meta(key).is_err());
+    assert!(store.get_owned(key, GetOpt::All).is_err());
+}
+
+#[test]
+fn test_quota() {
+    // the usage is kept in step with the inner store
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let inner = LocalFileSystemBlobStore::connect(tmp_dir.path()).unwrap();
+    let expect = common::put_blobs(&inner);
+    inner.put_var(&[7; 20], &[1; 10], PutOpt::Create).unwrap();
+    let store = QuotaStore::new(inner, Quota::default()).unwrap();
+    // the writes through the other handles of a shared store would be missed
+    let shared_dir = tempfile::tempdir().unwrap();
+    let shared = LocalFileSystemBlobStore::connect_shared(shared_dir.path()).unwrap();
+    let shared = ExpiringStore::new(shared);
+    assert!(matches!(
+        QuotaStore::new(shared, Quota::default()),
+        Err(BlobStoreError::Other(_))
+    ));
+    // the usage is the one the inner store keeps
+    let read_only_dir = tempfile::tempdir().unwrap();
+    let read_only = LocalFileSystemBlobStore::connect_read_only(read_only_dir.path()).unwrap();
+    assert!(matches!(
+        QuotaStore::new(read_only, Quota::default()),
+        Err(BlobStoreError::Other(_))
+    ));
+    // with the blob of the variable-length key
+    let stats = store.stats().unwrap();
+    assert_eq!(stats.blobs, expect.len() as u64 + 1);
+    assert_eq!(
+        stats.logical_bytes,
+        expect
+            .iter()
+            .map(|(_, data)| data.len() as u64)
+            .sum::<u64>()
+            + 10
+    );
+    common::copy_rename(&store);
+    common::vectored(&store);
+    common::sized(&store);
+    let stats = store.stats().unwrap();
+    let store = QuotaStore::new(store.into_inner(), Quota::default()).unwrap();
+    assert_eq!(store.stats().unwrap(), stats);
+    // holes are not allocated
+    let key = u64::MAX.as_key();
+    store.create_sized(key, 1 << 20).unwrap();
+    let physical = store.stats().unwrap().physical_bytes;
+    store.punch_hole(key, 0..1 << 20).unwrap();
+    assert!(store.stats().unwrap().physical_bytes < physical);
+    assert_eq!(
+        store.stats().unwrap().logical_bytes,
+        stats.logical_bytes + (1 << 20)
+    );
+    // limits
+    let store = QuotaStore::new(
+        MemoryBlobStore::new(),
+        Quota {
+            max_bytes: Some(100),
+            max_blobs: Some(2),
+        },
+    )
+    .unwrap();
+    let (a, b, c) = (1_u64.as_key(), 2_u64.as_key(), 3_u64.as_key());
+    store.put(a, &[1; 40], PutOpt::Create).unwrap();
+    store.put(b, &[2; 40], PutOpt::Create).unwrap();
+    assert!(matches!(
+        store.put(c, &[3; 1], PutOpt::Create),
+        Err(BlobStoreError::Blob(error::BlobError::QuotaExceeded))
+    ));
+    assert!(matches!(
+        store.put(a, &[1; 61], PutOpt::ReplaceOrCreate),
+        Err(BlobStoreError::Blob(error::BlobError::QuotaExceeded))
+    ));
+    assert!(matches!(
+        store.copy(a, c, CopyOpt::Create),
+        Err(BlobStoreError::Blob(error::BlobError::QuotaExceeded))
+    ));
+    assert!(!store.contains(c).unwrap());
+    store.put(a, &[1; 60], PutOpt::ReplaceOrCreate).unwrap();
+    store.copy(a, b, CopyOpt::ReplaceOrCreate).unwrap_err();
+    store.put(a, &[1; 10], PutOpt::ReplaceOrCreate).unwrap();
+    store.delete(b, DeleteOpt::Discard).unwrap();
+    store.create_sized(c, 90).unwrap();
+    assert_eq!(
+        store.stats().unwrap(),
+        StoreStats {
+            blobs: 2,
+            logical_bytes: 100,
+            physical_bytes: 100,
+        }
+    );
+    store.rename(c, b, CopyOpt::Create).unwrap();
+    assert_eq!(store.stats().unwrap().blobs, 2);
+}
+
+#[test]
+fn test_open_uri() {
+    let tmp_dir = tempfile::tempdir().unwrap();
+    let root = tmp_dir.path().to_str().unwrap();
+    let is_invalid = |uri: &str| matches!(blob_store::open(uri), Err(BlobStoreError::Other(_)));
+    let store = blob_store::open(&format!(
+        "file://{root}/file%20store?create&fsync=always&layout=flat&cache=16"
+    ))
+    .unwrap();
+    common::copy_rename(&*store);
+    drop(store);
+    assert!(tmp_dir.path().join("file store").is_dir());
+    let store = blob_store::open(&format!(
+        "file://localhost{root}/file%20store?mode=read_only"
+    ))
+    .unwrap();
+    assert!(matches!(
+        store.put(1_u64.as_key(), b"", PutOpt::Create),
+        Err(BlobStoreError::ReadOnly)
+    ));
+    drop(store);
+    assert!(matches!(
+        blob_store::open(&format!("file://{root}/missing")),
+        Err(BlobStoreError::Io(_))
+    ));
+    assert!(is_invalid(&format!(
+        "file://{root}/file%20store?mode=shared&cache=16"
+    )));
+    common::copy_rename(&*blob_store::open("memory://").unwrap());
+    #[cfg(feature = "memmap")]
+    common::copy_rename(
+        &*blob_store::open(&format!("mmap://{root}/mmap?create=true&cache=128")).unwrap(),
+    );
+    #[cfg(not(feature = "memmap"))]
+    assert!(is_invalid(&format!("mmap://{root}/mmap?create=true")));
+    #[cfg(feature = "sqlite")]
+    common::copy_rename(
+        &*blob_store::open(&format!(
+            "sqlite://{root}/sqlite?create=true&journal_mode=wal&synchronous=normal&page_size=8192\
+             &mmap_size=1048576&checkpoint_interval=16"
+        ))
+        .unwrap(),
+    );
+    #[cfg(not(feature = "sqlite"))]
+    assert!(is_invalid(&format!("sqlite://{root}/sqlite?create=true")));
+    for uri in [
+        "/data/blobs",
+        "s3://bucket/blobs",
+        "file://host/data",
+        "file://",
+        "memory:///data",
+        "memory://?create",
+        "file:///data?fsync=sometimes",
+        "file:///data?layout=deep",
+        "file:///data?cache=many",
+        "file:///data?journal_mode=wal",
+        "file:///data/%zz",
+    ] {
+        assert!(is_invalid(uri), "{uri}");
+    }
 }
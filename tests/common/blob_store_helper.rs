@@ -10,15 +10,15 @@ use crate::common::gen_random;
 const LOAD: usize = 4096;
 const BLOB_SIZE_RANGE: std::ops::Range<usize> = 1..4096;
 
-fn put_blobs(blob_store: &dyn BlobStore) -> Vec<(Key, Vec<u8>)> {
+pub fn put_blobs(blob_store: &dyn BlobStore) -> Vec<(Key, Vec<u8>)> {
     let mut rng = rand::thread_rng();
     (0..LOAD)
         .map(|_| gen_random(rng.gen_range(BLOB_SIZE_RANGE.clone())))
-        .inspect(|(key, data)| blob_store.put(*key, &data, PutOpt::Create).unwrap())
+        .inspect(|(key, data)| blob_store.put(*key, data, PutOpt::Create).unwrap())
         .collect::<Vec<_>>()
 }
 
-fn check_match(blob_store: &dyn BlobStore, expect: &[(Key, Vec<u8>)]) {
+pub fn check_match(blob_store: &dyn BlobStore, expect: &[(Key, Vec<u8>)]) {
     expect.iter().for_each(|(key, expect)| {
         assert!(blob_store.contains(*key).unwrap());
         assert_eq!(blob_store.meta(*key).unwrap().size, expect.len());
@@ -27,6 +27,14 @@ fn check_match(blob_store: &dyn BlobStore, expect: &[(Key, Vec<u8>)]) {
     });
 }
 
+fn check_keys(blob_store: &dyn BlobStore, expect: &[(Key, Vec<u8>)]) {
+    let mut keys = blob_store.keys().unwrap();
+    keys.sort();
+    let mut expect = expect.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+    expect.sort();
+    assert_eq!(keys, expect);
+}
+
 fn check_get(blob_store: &dyn BlobStore, expect: &[(Key, Vec<u8>)]) {
     let mut rng = rand::thread_rng();
     expect.iter().for_each(|(key, expect)| {
@@ -67,7 +75,7 @@ fn get_not_exist(blob_store: &dyn BlobStore) {
     let mut buf = vec![0; range.len()];
     (0..LOAD).for_each(|_| {
         let key: Key = rng.gen();
-        assert!(blob_store.contains(key).unwrap() == false);
+        assert!(!blob_store.contains(key).unwrap());
         assert!(matches!(
             blob_store.meta(key),
             Err(BlobStoreError::Blob(BlobError::NotFound))
@@ -146,7 +154,7 @@ fn check_range(blob_store: &dyn BlobStore, expect: &[(Key, Vec<u8>)]) {
             .into_iter()
             .for_each(|buf| {
                 assert!(matches!(
-                    blob_store.put(*key, &buf, PutOpt::Replace(valide_range.clone())),
+                    blob_store.put(*key, buf, PutOpt::Replace(valide_range.clone())),
                     Err(BlobStoreError::Blob(BlobError::RangeError))
                 ));
                 assert!(matches!(
@@ -216,6 +224,7 @@ pub fn write_read(blob_store: &dyn BlobStore) {
     delete_not_exist(blob_store);
     let expect = put_blobs(blob_store);
     check_match(blob_store, &expect);
+    check_keys(blob_store, &expect);
     check_get(blob_store, &expect);
     check_range(blob_store, &expect);
     put_exists(blob_store, &expect);
@@ -291,3 +300,594 @@ pub fn concurrent(blob: Arc<dyn BlobStore + Send + Sync>) {
         .chain(delete_handle)
         .for_each(|h| h.join().unwrap());
 }
+
+/// expected to receive a clean store
+#[allow(dead_code)]
+pub fn buckets<S: BucketStore + BlobStore>(store: &S) {
+    use error::BucketError;
+    assert!(store.list_buckets().unwrap().is_empty());
+    assert!(matches!(
+        store.create_bucket(".hidden"),
+        Err(BlobStoreError::Bucket(BucketError::InvalidName))
+    ));
+    assert!(matches!(
+        store.create_bucket("Upper"),
+        Err(BlobStoreError::Bucket(BucketError::InvalidName))
+    ));
+    let (key, data) = gen_random(128);
+    {
+        let a = store.create_bucket("tenant-a").unwrap();
+        let b = store.create_bucket("tenant-b").unwrap();
+        write_read(&b);
+        // same key in distinct buckets
+        a.put(key, &data, PutOpt::Create).unwrap();
+        b.put(key, &data[..64], PutOpt::Create).unwrap();
+        assert!(!store.contains(key).unwrap());
+        assert_eq!(a.get_owned(key, GetOpt::All).unwrap(), data);
+        assert_eq!(b.get_owned(key, GetOpt::All).unwrap(), &data[..64]);
+        assert_eq!(a.keys().unwrap(), vec![key]);
+    }
+    assert!(matches!(
+        store.create_bucket("tenant-a"),
+        Err(BlobStoreError::Bucket(BucketError::AlreadyExists))
+    ));
+    let mut names = store.list_buckets().unwrap();
+    names.sort();
+    assert_eq!(names, ["tenant-a", "tenant-b"]);
+    assert!(store.keys().unwrap().is_empty());
+    // drop
+    store.drop_bucket("tenant-b").unwrap();
+    assert!(matches!(
+        store.bucket("tenant-b"),
+        Err(BlobStoreError::Bucket(BucketError::NotFound))
+    ));
+    assert!(matches!(
+        store.drop_bucket("tenant-b"),
+        Err(BlobStoreError::Bucket(BucketError::NotFound))
+    ));
+    assert_eq!(store.list_buckets().unwrap(), ["tenant-a"]);
+    let a = store.bucket("tenant-a").unwrap();
+    assert_eq!(a.get_owned(key, GetOpt::All).unwrap(), data);
+    // a recreated bucket is empty
+    drop(a);
+    store.drop_bucket("tenant-a").unwrap();
+    let a = store.create_bucket("tenant-a").unwrap();
+    assert!(!a.contains(key).unwrap());
+}
+
+/// expected to receive a clean store
+#[allow(dead_code)]
+pub fn var_keys(store: &dyn VarKeyBlobStore) {
+    let max = store.max_key_len();
+    let keys: Vec<Vec<u8>> = vec![
+        b"a".to_vec(),
+        b"a/b".to_vec(),
+        b"..".to_vec(),
+        b"%2E%2E".to_vec(),
+        "object/\u{e9}t\u{e9}.bin".as_bytes().to_vec(),
+        vec![0, 255, 1],
+        // segment boundary of the file system backends
+        vec![b'a'; 192],
+        vec![b'a'; 193],
+        vec![b'/'; max],
+    ];
+    let expect = keys
+        .iter()
+        .map(|key| {
+            let (_, data) = gen_random(key.len() % 512 + 1);
+            store.put_var(key, &data, PutOpt::Create).unwrap();
+            (key.clone(), data)
+        })
+        .collect::<Vec<_>>();
+    expect.iter().for_each(|(key, data)| {
+        assert!(store.contains_var(key).unwrap());
+        assert_eq!(store.meta_var(key).unwrap().size, data.len());
+        assert_eq!(&store.get_owned_var(key, GetOpt::All).unwrap(), data);
+        assert!(matches!(
+            store.put_var(key, data, PutOpt::Create),
+            Err(BlobStoreError::Blob(BlobError::AlreadyExists))
+        ));
+    });
+    // variable-length keys are not listed with the fixed ones
+    assert!(store.keys().unwrap().is_empty());
+    // replace
+    let (key, data) = &expect[1];
+    let mut patched = data.clone();
+    patched[0] = !patched[0];
+    store
+        .put_var(key, &patched[..1], PutOpt::Replace(0..1))
+        .unwrap();
+    assert_eq!(store.get_owned_var(key, GetOpt::All).unwrap(), patched);
+    store
+        .put_var(key, b"replaced", PutOpt::ReplaceOrCreate)
+        .unwrap();
+    assert_eq!(
+        store.get_owned_var(key, GetOpt::Range(2..6)).unwrap(),
+        b"plac"
+    );
+    // keys of the size of a fixed key take the fixed key path
+    let (fixed, data) = gen_random(64);
+    store.put_var(&fixed, &data, PutOpt::Create).unwrap();
+    assert_eq!(store.get_owned(fixed, GetOpt::All).unwrap(), data);
+    store.delete(fixed, DeleteOpt::Discard).unwrap();
+    assert!(!store.contains_var(&fixed).unwrap());
+    // invalid keys
+    let too_long = vec![0; max + 1];
+    for key in [&b""[..], &too_long] {
+        assert!(matches!(
+            store.put_var(key, b"data", PutOpt::Create),
+            Err(BlobStoreError::Blob(BlobError::InvalidKey))
+        ));
+        assert!(matches!(
+            store.contains_var(key),
+            Err(BlobStoreError::Blob(BlobError::InvalidKey))
+        ));
+    }
+    // delete with interest, a bad range keeps the blob
+    let (key, data) = &expect[4];
+    assert!(matches!(
+        store.delete_var(key, DeleteOpt::Interest(0..data.len() + 1)),
+        Err(BlobStoreError::Blob(BlobError::RangeError))
+    ));
+    assert_eq!(
+        store
+            .delete_var(key, DeleteOpt::Interest(1..data.len()))
+            .unwrap()
+            .as_deref(),
+        Some(&data[1..])
+    );
+    assert!(!store.contains_var(key).unwrap());
+    store.put_var(key, data, PutOpt::Create).unwrap();
+    // delete
+    expect.iter().for_each(|(key, _)| {
+        assert_eq!(store.delete_var(key, DeleteOpt::Discard).unwrap(), None);
+        assert!(!store.contains_var(key).unwrap());
+        assert!(matches!(
+            store.delete_var(key, DeleteOpt::Discard),
+            Err(BlobStoreError::Blob(BlobError::NotFound))
+        ));
+    });
+}
+
+/// expected to receive a clean store
+#[allow(dead_code)]
+pub fn transactions<S: TransactionalBlobStore>(store: &S) {
+    let (a, data_a) = gen_random(256);
+    let (b, data_b) = gen_random(256);
+    let (c, data_c) = gen_random(256);
+    store.put(a, &data_a, PutOpt::Create).unwrap();
+    store.put(b, &data_b, PutOpt::Create).unwrap();
+    // commit
+    let mut txn = store.begin().unwrap();
+    txn.put(c, &data_c, PutOpt::Create).unwrap();
+    txn.put(c, &[0, 0], PutOpt::Replace(0..2)).unwrap();
+    txn.put(a, &[1, 1], PutOpt::Replace(4..6)).unwrap();
+    txn.delete(b).unwrap();
+    assert!(matches!(
+        txn.put(c, &data_c, PutOpt::Create),
+        Err(BlobStoreError::Blob(BlobError::AlreadyExists))
+    ));
+    assert!(matches!(
+        txn.delete(b),
+        Err(BlobStoreError::Blob(BlobError::NotFound))
+    ));
+    assert!(matches!(
+        txn.put(b, &[0], PutOpt::Replace(0..1)),
+        Err(BlobStoreError::Blob(BlobError::NotFound))
+    ));
+    txn.commit().unwrap();
+    let mut expect_a = data_a.clone();
+    expect_a[4..6].copy_from_slice(&[1, 1]);
+    let mut expect_c = data_c.clone();
+    expect_c[0..2].copy_from_slice(&[0, 0]);
+    assert_eq!(store.get_owned(a, GetOpt::All).unwrap(), expect_a);
+    assert_eq!(store.get_owned(c, GetOpt::All).unwrap(), expect_c);
+    assert!(!store.contains(b).unwrap());
+    // rollback
+    let mut txn = store.begin().unwrap();
+    txn.put(b, &data_b, PutOpt::Create).unwrap();
+    txn.put(a, &data_a, PutOpt::ReplaceOrCreate).unwrap();
+    txn.delete(c).unwrap();
+    txn.rollback().unwrap();
+    assert!(!store.contains(b).unwrap());
+    assert_eq!(store.get_owned(a, GetOpt::All).unwrap(), expect_a);
+    assert_eq!(store.get_owned(c, GetOpt::All).unwrap(), expect_c);
+    // drop
+    let mut txn = store.begin().unwrap();
+    txn.put(b, &data_b, PutOpt::Create).unwrap();
+    txn.delete(a).unwrap();
+    drop(txn);
+    assert!(!store.contains(b).unwrap());
+    assert_eq!(store.get_owned(a, GetOpt::All).unwrap(), expect_a);
+    let mut keys = store.keys().unwrap();
+    keys.sort();
+    let mut expect = vec![a, c];
+    expect.sort();
+    assert_eq!(keys, expect);
+}
+
+/// expected to receive a clean store
+#[allow(dead_code)]
+pub fn snapshots<S: SnapshotBlobStore>(store: &S) {
+    let expect = (0..64)
+        .map(|_| gen_random(1024))
+        .inspect(|(key, data)| store.put(*key, data, PutOpt::Create).unwrap())
+        .collect::<Vec<_>>();
+    let snapshot = store.snapshot().unwrap();
+    // writes continue on the live store
+    let (a, b, c) = (expect[0].0, expect[1].0, expect[2].0);
+    store.put(a, &[0; 16], PutOpt::Replace(0..16)).unwrap();
+    store.put(b, b"replaced", PutOpt::ReplaceOrCreate).unwrap();
+    store.delete(c, DeleteOpt::Discard).unwrap();
+    store.put(c, b"recreated", PutOpt::Create).unwrap();
+    let (d, data_d) = gen_random(64);
+    store.put(d, &data_d, PutOpt::Create).unwrap();
+    assert_eq!(store.get_owned(a, GetOpt::Range(0..16)).unwrap(), [0; 16]);
+    assert_eq!(store.get_owned(b, GetOpt::All).unwrap(), b"replaced");
+    assert_eq!(store.get_owned(c, GetOpt::All).unwrap(), b"recreated");
+    // the snapshot doesn't see them
+    check_match(&snapshot, &expect);
+    check_keys(&snapshot, &expect);
+    assert!(!snapshot.contains(d).unwrap());
+    assert!(matches!(
+        snapshot.put(d, &data_d, PutOpt::Create),
+        Err(BlobStoreError::ReadOnly)
+    ));
+    assert!(matches!(
+        snapshot.delete(a, DeleteOpt::Discard),
+        Err(BlobStoreError::ReadOnly)
+    ));
+    drop(snapshot);
+    assert_eq!(store.get_owned(d, GetOpt::All).unwrap(), data_d);
+}
+
+#[allow(dead_code)]
+pub fn copy_rename(store: &dyn BlobStore) {
+    let expect = put_blobs(store);
+    let (src, data) = &expect[0];
+    let (other, other_data) = &expect[1];
+    let missing = u64::MAX.as_key();
+    let dst = (u64::MAX - 1).as_key();
+    // source not exist
+    assert!(matches!(
+        store.copy(missing, dst, CopyOpt::ReplaceOrCreate),
+        Err(BlobStoreError::Blob(BlobError::NotFound))
+    ));
+    assert!(matches!(
+        store.rename(missing, dst, CopyOpt::ReplaceOrCreate),
+        Err(BlobStoreError::Blob(BlobError::NotFound))
+    ));
+    // copy
+    store.copy(*src, dst, CopyOpt::Create).unwrap();
+    assert_eq!(&store.get_owned(dst, GetOpt::All).unwrap(), data);
+    assert!(matches!(
+        store.copy(*src, dst, CopyOpt::Create),
+        Err(BlobStoreError::Blob(BlobError::AlreadyExists))
+    ));
+    assert!(matches!(
+        store.copy(*src, *src, CopyOpt::Create),
+        Err(BlobStoreError::Blob(BlobError::AlreadyExists))
+    ));
+    store.copy(*src, *src, CopyOpt::ReplaceOrCreate).unwrap();
+    assert_eq!(&store.get_owned(*src, GetOpt::All).unwrap(), data);
+    // the copy is independent of the source
+    store.put(dst, &[!data[0]], PutOpt::Replace(0..1)).unwrap();
+    assert_eq!(&store.get_owned(*src, GetOpt::All).unwrap(), data);
+    store.copy(*other, dst, CopyOpt::ReplaceOrCreate).unwrap();
+    assert_eq!(&store.get_owned(dst, GetOpt::All).unwrap(), other_data);
+    // rename
+    assert!(matches!(
+        store.rename(*src, dst, CopyOpt::Create),
+        Err(BlobStoreError::Blob(BlobError::AlreadyExists))
+    ));
+    store.rename(*src, dst, CopyOpt::ReplaceOrCreate).unwrap();
+    assert!(!store.contains(*src).unwrap());
+    assert_eq!(&store.get_owned(dst, GetOpt::All).unwrap(), data);
+    store.rename(dst, *src, CopyOpt::Create).unwrap();
+    assert!(!store.contains(dst).unwrap());
+    store.rename(*src, *src, CopyOpt::ReplaceOrCreate).unwrap();
+    check_match(store, &expect);
+}
+
+#[allow(dead_code)]
+pub fn borrowed_reads(store: &dyn BlobStore) {
+    let expect = put_blobs(store);
+    expect.iter().for_each(|(key, data)| {
+        let mut read = Vec::new();
+        store
+            .with_blob(*key, GetOpt::All, &mut |blob| read = blob.to_vec())
+            .unwrap();
+        assert_eq!(&read, data);
+        let range = 0..data.len() / 2;
+        store
+            .with_blob(*key, GetOpt::Range(range.clone()), &mut |blob| {
+                read = blob.to_vec()
+            })
+            .unwrap();
+        assert_eq!(read, data[range]);
+        assert!(matches!(
+            store.with_blob(*key, GetOpt::Range(0..data.len() + 1), &mut |_| {
+                unreachable!()
+            }),
+            Err(BlobStoreError::Blob(BlobError::RangeError))
+        ));
+    });
+    assert!(matches!(
+        store.with_blob(u64::MAX.as_key(), GetOpt::All, &mut |_| unreachable!()),
+        Err(BlobStoreError::Blob(BlobError::NotFound))
+    ));
+}
+
+#[allow(dead_code)]
+pub fn vectored(store: &dyn BlobStore) {
+    let (key, data) = gen_random(4096);
+    store.put(key, &data, PutOpt::Create).unwrap();
+    // disjoint ranges, out of order
+    let (mut head, mut tail, mut mid) = (vec![0_u8; 16], vec![0_u8; 96], vec![0_u8; 1000]);
+    store
+        .get_vectored(
+            key,
+            &mut [
+                (0..16, &mut head),
+                (4000..4096, &mut tail),
+                (100..1100, &mut mid),
+            ],
+        )
+        .unwrap();
+    assert_eq!(head, data[0..16]);
+    assert_eq!(tail, data[4000..4096]);
+    assert_eq!(mid, data[100..1100]);
+    // write and read back
+    let mut expect = data.clone();
+    expect[8..12].copy_from_slice(b"head");
+    expect[2048..2052].copy_from_slice(b"body");
+    store
+        .put_vectored(key, &[(8..12, b"head"), (2048..2052, b"body")])
+        .unwrap();
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), expect);
+    // all the ranges are checked before any data is touched
+    assert!(matches!(
+        store.put_vectored(key, &[(0..4, b"lost"), (4094..4098, b"tail")]),
+        Err(BlobStoreError::Blob(BlobError::RangeError))
+    ));
+    assert!(matches!(
+        store.put_vectored(key, &[(0..4, b"lost"), (8..16, b"short")]),
+        Err(BlobStoreError::Blob(BlobError::RangeError))
+    ));
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), expect);
+    assert!(matches!(
+        store.get_vectored(key, &mut [(0..16, &mut head), (4090..4106, &mut mid[..16])]),
+        Err(BlobStoreError::Blob(BlobError::RangeError))
+    ));
+    assert!(matches!(
+        store.get_vectored(u64::MAX.as_key(), &mut [(0..16, &mut head)]),
+        Err(BlobStoreError::Blob(BlobError::NotFound))
+    ));
+}
+
+#[allow(dead_code)]
+pub fn sized(store: &dyn BlobStore) {
+    const LEN: usize = 64 * 1024;
+    let key = 1_u64.as_key();
+    store.create_sized(key, LEN).unwrap();
+    assert_eq!(store.meta(key).unwrap().size, LEN);
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), vec![0; LEN]);
+    assert!(matches!(
+        store.create_sized(key, LEN),
+        Err(BlobStoreError::Blob(BlobError::AlreadyExists))
+    ));
+    let (_, data) = gen_random(LEN);
+    store.put(key, &data, PutOpt::Replace(0..LEN)).unwrap();
+    // holes read as zeros, the size doesn't change
+    let mut expect = data.clone();
+    for range in [4096..8192, 100..200, LEN - 10..LEN] {
+        store.punch_hole(key, range.clone()).unwrap();
+        expect[range].fill(0);
+    }
+    assert_eq!(store.meta(key).unwrap().size, LEN);
+    assert_eq!(store.get_owned(key, GetOpt::All).unwrap(), expect);
+    assert!(matches!(
+        store.punch_hole(key, LEN - 1..LEN + 1),
+        Err(BlobStoreError::Blob(BlobError::RangeError))
+    ));
+    assert!(matches!(
+        store.punch_hole(u64::MAX.as_key(), 0..1),
+        Err(BlobStoreError::Blob(BlobError::NotFound))
+    ));
+    // empty blob
+    store.create_sized(2_u64.as_key(), 0).unwrap();
+    assert_eq!(store.meta(2_u64.as_key()).unwrap().size, 0);
+}
+
+/// `store` was opened read-only on a store holding `expect`
+#[allow(dead_code)]
+pub fn read_only(store: &dyn BlobStore, expect: &[(Key, Vec<u8>)]) {
+    check_match(store, expect);
+    let (key, data) = &expect[0];
+    let is_read_only = |result: BlobResult<()>| matches!(result, Err(BlobStoreError::ReadOnly));
+    assert!(is_read_only(store.put(
+        u64::MAX.as_key(),
+        b"new",
+        PutOpt::Create
+    )));
+    assert!(is_read_only(store.put(
+        *key,
+        &data[..1],
+        PutOpt::Replace(0..1)
+    )));
+    assert!(is_read_only(store.put(
+        *key,
+        b"new",
+        PutOpt::ReplaceOrCreate
+    )));
+    assert!(is_read_only(
+        store.delete(*key, DeleteOpt::Discard).map(|_| ())
+    ));
+    assert!(is_read_only(store.create_sized(u64::MAX.as_key(), 16)));
+    assert!(is_read_only(store.punch_hole(*key, 0..1)));
+    assert!(is_read_only(store.copy(
+        *key,
+        u64::MAX.as_key(),
+        CopyOpt::Create
+    )));
+    // nothing changed
+    assert!(!store.contains(u64::MAX.as_key()).unwrap());
+    check_match(store, expect);
+}
+
+/// a writer has the store to itself, readers share it
+#[allow(dead_code)]
+pub fn locking<S, W, R>(connect: W, connect_read_only: R)
+where
+    W: Fn() -> BlobResult<S>,
+    R: Fn() -> BlobResult<S>,
+{
+    let is_locked = |result: BlobResult<S>| matches!(result, Err(BlobStoreError::Locked));
+    let writer = connect().unwrap();
+    assert!(is_locked(connect()));
+    assert!(is_locked(connect_read_only()));
+    drop(writer);
+    let reader = connect_read_only().unwrap();
+    let other_reader = connect_read_only().unwrap();
+    assert!(is_locked(connect()));
+    drop((reader, other_reader));
+    drop(connect().unwrap());
+}
+
+#[allow(dead_code)]
+pub fn delete_interest(store: &dyn BlobStore) {
+    let (key, data) = gen_random(4096);
+    store.put(key, &data, PutOpt::Create).unwrap();
+    // a bad range keeps the blob
+    assert!(matches!(
+        store.delete(key, DeleteOpt::Interest(0..data.len() + 1)),
+        Err(BlobStoreError::Blob(BlobError::RangeError))
+    ));
+    assert!(store.contains(key).unwrap());
+    let range = 100..1100;
+    assert_eq!(
+        store
+            .delete(key, DeleteOpt::Interest(range.clone()))
+            .unwrap()
+            .as_deref(),
+        Some(&data[range])
+    );
+    assert!(!store.contains(key).unwrap());
+    assert!(matches!(
+        store.delete(key, DeleteOpt::Interest(0..1)),
+        Err(BlobStoreError::Blob(BlobError::NotFound))
+    ));
+}
+
+/// expected to receive a clean store
+#[allow(dead_code)]
+pub fn batches<S: BatchBlobStore>(store: &S) {
+    let mut rng = rand::thread_rng();
+    let mut expect = (0..256)
+        .map(|_| gen_random(rng.gen_range(BLOB_SIZE_RANGE.clone())))
+        .collect::<Vec<_>>();
+    expect.push((gen_random(0).0, vec![]));
+    let blobs = expect
+        .iter()
+        .map(|(key, data)| (*key, data.as_slice()))
+        .collect::<Vec<_>>();
+    assert!(store
+        .put_many(&blobs, PutOpt::Create)
+        .into_iter()
+        .all(|result| result.is_ok()));
+    check_match(store, &expect);
+    let keys = expect.iter().map(|(key, _)| *key).collect::<Vec<_>>();
+    let received = store.get_many(&keys);
+    assert_eq!(received.len(), expect.len());
+    for (received, (_, data)) in received.into_iter().zip(&expect) {
+        assert_eq!(&received.unwrap(), data);
+    }
+    // every blob gets its own result
+    let (missing, fresh) = (gen_random(1).0, gen_random(16));
+    let results = store.put_many(&[blobs[0], (fresh.0, &fresh.1)], PutOpt::Create);
+    assert!(matches!(
+        results[0],
+        Err(BlobStoreError::Blob(BlobError::AlreadyExists))
+    ));
+    assert!(results[1].is_ok());
+    let results = store.get_many(&[missing, fresh.0]);
+    assert!(matches!(
+        results[0],
+        Err(BlobStoreError::Blob(BlobError::NotFound))
+    ));
+    assert_eq!(results[1].as_ref().unwrap(), &fresh.1);
+    // replaced blobs take the size of their new content
+    for (_, data) in expect.iter_mut() {
+        *data = gen_random(rng.gen_range(BLOB_SIZE_RANGE.clone())).1;
+    }
+    let blobs = expect
+        .iter()
+        .map(|(key, data)| (*key, data.as_slice()))
+        .collect::<Vec<_>>();
+    assert!(store
+        .put_many(&blobs, PutOpt::ReplaceOrCreate)
+        .into_iter()
+        .all(|result| result.is_ok()));
+    check_match(store, &expect);
+    let results = store.put_many(&[(missing, &[0])], PutOpt::Replace(0..1));
+    assert!(matches!(
+        results[0],
+        Err(BlobStoreError::Blob(BlobError::NotFound))
+    ));
+}
+
+/// usage of the blobs with fixed keys, counted from their metadata
+fn counted_stats(store: &dyn BlobStore) -> StoreStats {
+    let mut stats = StoreStats::default();
+    for key in store.keys().unwrap() {
+        let meta = store.meta(key).unwrap();
+        stats.blobs += 1;
+        stats.logical_bytes += meta.size as u64;
+        stats.physical_bytes += meta.allocated.unwrap_or(meta.size) as u64;
+    }
+    stats
+}
+
+/// expected to receive a clean store
+#[allow(dead_code)]
+pub fn stats(store: &dyn BlobStore) {
+    assert_eq!(store.stats().unwrap(), StoreStats::default());
+    put_blobs(store);
+    assert_eq!(store.stats().unwrap(), counted_stats(store));
+    copy_rename(store);
+    vectored(store);
+    sized(store);
+    delete_interest(store);
+    assert_eq!(store.stats().unwrap(), counted_stats(store));
+    for key in store.keys().unwrap() {
+        store.delete(key, DeleteOpt::Discard).unwrap();
+    }
+    assert_eq!(store.stats().unwrap(), StoreStats::default());
+    put_blobs(store);
+}
+
+/// the usage covers the blobs with variable-length keys and the buckets,
+/// expected to receive a store without them
+#[allow(dead_code)]
+pub fn var_key_bucket_stats<S: BucketStore + VarKeyBlobStore>(store: &S) {
+    let before = store.stats().unwrap();
+    let var_key = [7_u8; 20];
+    store.put_var(&var_key, &[1; 10], PutOpt::Create).unwrap();
+    let bucket = store.create_bucket("stats").unwrap();
+    bucket
+        .put(1_u64.as_key(), &[2; 100], PutOpt::Create)
+        .unwrap();
+    bucket
+        .put(2_u64.as_key(), &[3; 1000], PutOpt::Create)
+        .unwrap();
+    let in_bucket = bucket.stats().unwrap();
+    assert_eq!(in_bucket, counted_stats(&bucket));
+    assert_eq!((in_bucket.blobs, in_bucket.logical_bytes), (2, 1100));
+    let stats = store.stats().unwrap();
+    assert_eq!(stats.blobs, before.blobs + 3);
+    assert_eq!(stats.logical_bytes, before.logical_bytes + 1110);
+    drop(bucket);
+    store.drop_bucket("stats").unwrap();
+    store.delete_var(&var_key, DeleteOpt::Discard).unwrap();
+    assert_eq!(store.stats().unwrap(), before);
+}